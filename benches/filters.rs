@@ -0,0 +1,49 @@
+//! Times the heaviest per-pixel operations on a 4000x3000 image. Compare
+//! `cargo bench --bench filters` with `cargo bench --bench filters --features rayon` to see
+//! the speedup from spreading rows over threads.
+
+use image_manip::{BmpFile, BorderMode, Color, Image};
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 4000;
+const HEIGHT: usize = 3000;
+const RUNS: u32 = 3;
+
+/// The fastest of [`RUNS`] runs of `operation` on a fresh copy of `image`.
+fn time(image: &BmpFile, operation: impl Fn(&mut BmpFile)) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let mut copy = image.clone();
+            let start = Instant::now();
+            operation(&mut copy);
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    let mut image = BmpFile::new(WIDTH, HEIGHT, Color::BLACK);
+    image.update(|x, y, _| Color::rgb(x as u8, y as u8, (x ^ y) as u8));
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!(
+        "{}x{}, rayon {}, {} hardware threads",
+        WIDTH,
+        HEIGHT,
+        if cfg!(feature = "rayon") { "on" } else { "off" },
+        threads
+    );
+    let kernel = [1.0 / 9.0; 9];
+    let report = |name: &str, duration: Duration| println!("{:<20}{:>10.1?}", name, duration);
+    report(
+        "convolve 3x3",
+        time(&image, |image| {
+            image.convolve(&kernel, 3, BorderMode::Replicate).unwrap()
+        }),
+    );
+    report("grayscale", time(&image, |image| image.grayscale()));
+    report(
+        "adjust_brightness",
+        time(&image, |image| image.adjust_brightness(20)),
+    );
+}
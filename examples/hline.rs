@@ -0,0 +1,15 @@
+use image_manip::{BmpFile, Color, Image};
+use std::env;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <input.bmp> <output.bmp>", args[0]);
+        process::exit(1);
+    }
+
+    let mut bmp = BmpFile::open(&args[1]).unwrap();
+    bmp.draw_hline(10, 10, Color::WHITE).unwrap();
+    bmp.save(&args[2]).unwrap();
+}
@@ -0,0 +1,1462 @@
+//! The [`ReadImage`] and [`Image`] traits, which let the analysis, drawing and filter
+//! operations run on any pixel canvas rather than only on [`BmpFile`].
+//!
+//! Implementors supply the dimensions and access to single pixels, and every operation is
+//! provided on top of those. Read-only canvases such as [`View`] implement just
+//! [`ReadImage`]. [`Image::update`] can be overridden by canvases that rewrite all of their
+//! pixels faster than one [`ReadImage::get`] and [`Image::set`] at a time.
+
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::shape::{arc, rounded_rect};
+use crate::stroke::stroke_polygons;
+use crate::text::{glyph, GLYPH_SIZE};
+use crate::tone::curve_lut;
+#[cfg(feature = "truetype")]
+use crate::Font;
+use crate::{
+    convolve_plane, median_cut, quantize_channel, BayerMatrix, Blending, BmpFile, BorderMode,
+    Color, CornerRadii, FadeDirection, FillStyle, Gradient, ImageError, Levels, Luma, Path,
+    StrokeStyle, View, ViewMut,
+};
+use num::clamp;
+use std::collections::HashMap;
+
+/// Read access to a rectangular grid of colors addressed by (x, y), with (0, 0) at the top
+/// left. Everything that only inspects pixels lives here, so [`View`] gets it too.
+pub trait ReadImage {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// The color at (x, y), or `None` outside the image.
+    fn get(&self, x: usize, y: usize) -> Option<Color>;
+    /// The colors of row `y` from left to right, empty past the bottom edge.
+    fn row(&self, y: usize) -> Vec<Color> {
+        (0..self.width()).map_while(|x| self.get(x, y)).collect()
+    }
+    /// Every row from top to bottom, see [`ReadImage::row`].
+    fn rows(&self) -> impl Iterator<Item = Vec<Color>> + '_ {
+        (0..self.height()).map(|y| self.row(y))
+    }
+    /// A read-only window onto the `width`x`height` rectangle whose top-left corner is
+    /// (x, y). Fails with [`ImageError::OutOfBounds`] if the rectangle does not fit.
+    fn view(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<View<'_, Self>, ImageError> {
+        check_rect(self, x, y, width, height)?;
+        Ok(View::new(self, x, y, width, height))
+    }
+    /// The region [`Image::flood_fill`] would paint, as a row-major mask with one entry per
+    /// pixel, for building selections without touching the image. All `false` when (x, y) is
+    /// outside the image. Uses an explicit scanline stack rather than recursion.
+    fn flood_select(&self, x: usize, y: usize, tolerance: u8) -> Vec<bool> {
+        let (width, height) = (self.width(), self.height());
+        let mut region = vec![false; width * height];
+        let Some(seed) = self.get(x, y) else {
+            return region;
+        };
+        let fillable = |region: &[bool], x: usize, y: usize| {
+            !region[y * width + x]
+                && self.get(x, y).is_some_and(|color| {
+                    color.within_tolerance(&seed, tolerance)
+                        && color.a().abs_diff(seed.a()) <= tolerance
+                })
+        };
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if !fillable(&region, x, y) {
+                continue;
+            }
+            let mut left = x;
+            while left > 0 && fillable(&region, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && fillable(&region, right + 1, y) {
+                right += 1;
+            }
+            region[y * width + left..=y * width + right].fill(true);
+            for neighbour_y in [y.checked_sub(1), Some(y + 1).filter(|&y| y < height)]
+                .into_iter()
+                .flatten()
+            {
+                let mut in_span = false;
+                for x in left..=right {
+                    let candidate = fillable(&region, x, neighbour_y);
+                    if candidate && !in_span {
+                        stack.push((x, neighbour_y));
+                    }
+                    in_span = candidate;
+                }
+            }
+        }
+        region
+    }
+    /// Up to `count` colors that best represent the image, chosen by median cut over its
+    /// colors with alpha ignored. Images with at most `count` colors get them back exactly.
+    fn extract_palette(&self, count: usize) -> Vec<Color> {
+        let mut histogram: HashMap<Color, u32> = HashMap::new();
+        for color in colors(self) {
+            *histogram.entry(color.with_alpha(255)).or_default() += 1;
+        }
+        median_cut(&histogram, count.max(1))
+    }
+    /// The threshold level that best separates the luminance histogram into dark and light
+    /// classes by Otsu's method, maximizing the variance between them.
+    fn otsu_level(&self) -> u8 {
+        let mut histogram = [0u32; 256];
+        for color in colors(self) {
+            histogram[color.luminance() as usize] += 1;
+        }
+        let total: f64 = histogram.iter().map(|&count| count as f64).sum();
+        let weighted_total: f64 = histogram
+            .iter()
+            .enumerate()
+            .map(|(level, &count)| level as f64 * count as f64)
+            .sum();
+
+        let mut best_level = 0;
+        let mut best_variance = 0.0;
+        let mut background_weight = 0.0;
+        let mut background_sum = 0.0;
+        for (level, &count) in histogram.iter().enumerate() {
+            background_weight += count as f64;
+            if background_weight == 0.0 {
+                continue;
+            }
+            let foreground_weight = total - background_weight;
+            if foreground_weight == 0.0 {
+                break;
+            }
+            background_sum += level as f64 * count as f64;
+            let background_mean = background_sum / background_weight;
+            let foreground_mean = (weighted_total - background_sum) / foreground_weight;
+            let variance =
+                background_weight * foreground_weight * (background_mean - foreground_mean).powi(2);
+            if variance > best_variance {
+                best_variance = variance;
+                best_level = level;
+            }
+        }
+        // Pixels at or below the chosen level belong to the background class.
+        (best_level + 1).min(255) as u8
+    }
+}
+
+/// A [`ReadImage`] whose pixels can be changed, with every drawing and filter operation.
+pub trait Image: ReadImage {
+    /// Sets the pixel at (x, y) to `color`, alpha included. Positions outside the image are
+    /// ignored.
+    fn set(&mut self, x: usize, y: usize, color: Color);
+    /// Replaces every color with `f(x, y, color)`.
+    fn update<F>(&mut self, f: F)
+    where
+        F: Fn(usize, usize, Color) -> Color + Sync,
+    {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if let Some(color) = self.get(x, y) {
+                    self.set(x, y, f(x, y, color));
+                }
+            }
+        }
+    }
+    /// A window like [`ReadImage::view`] that every drawing and filter method works on in local
+    /// coordinates, leaving the rest of the image untouched.
+    fn view_mut(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<ViewMut<'_, Self>, ImageError> {
+        check_rect(self, x, y, width, height)?;
+        Ok(ViewMut::new(self, x, y, width, height))
+    }
+
+    /// Bresenham line between two points, clipped to the image.
+    fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            if x >= 0 && y >= 0 {
+                self.set(x as usize, y as usize, color);
+            }
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+    /// Anti-aliased line between two points using Xiaolin Wu's algorithm, clipped to the
+    /// image. Whole-number coordinates land on pixel centers as in [`Image::draw_line`], and
+    /// each pixel gets `color` blended over it in proportion to how much the line covers it.
+    fn draw_line_aa(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, color: Color) {
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        // Walk along the major axis from left to right, mapping back when plotting.
+        let (mut x0, mut y0, mut x1, mut y1) = if steep {
+            (y0, x0, y1, x1)
+        } else {
+            (x0, y0, x1, y1)
+        };
+        if x0 > x1 {
+            (x0, y0, x1, y1) = (x1, y1, x0, y0);
+        }
+        let major_len = if steep { self.height() } else { self.width() } as f64;
+        let gradient = if x1 == x0 { 1.0 } else { (y1 - y0) / (x1 - x0) };
+        let fract = |value: f64| value - value.floor();
+        let mut plot = |x: f64, y: f64, coverage: f64| {
+            let (x, y) = if steep { (y, x) } else { (x, y) };
+            blend(self, x as isize, y as isize, color, coverage);
+        };
+        let mut endpoint = |x: f64, y: f64, gap: f64| {
+            let end_x = x.round();
+            let end_y = y + gradient * (end_x - x);
+            plot(end_x, end_y.floor(), (1.0 - fract(end_y)) * gap);
+            plot(end_x, end_y.floor() + 1.0, fract(end_y) * gap);
+            (end_x, end_y)
+        };
+        let (start_x, start_y) = endpoint(x0, y0, 1.0 - fract(x0 + 0.5));
+        let (end_x, _) = endpoint(x1, y1, fract(x1 + 0.5));
+        // Columns off the image draw nothing, so skip straight to the visible ones.
+        let first = (start_x + 1.0).max(0.0);
+        let last = (end_x - 1.0).min(major_len - 1.0);
+        let mut x = first;
+        while x <= last {
+            let y = start_y + gradient * (x - start_x);
+            plot(x, y.floor(), 1.0 - fract(y));
+            plot(x, y.floor() + 1.0, fract(y));
+            x += 1.0;
+        }
+    }
+    /// Connects each point to the next with [`Image::draw_line`], leaving the path open.
+    fn draw_polyline(&mut self, points: &[(isize, isize)], color: Color) {
+        for pair in points.windows(2) {
+            let [(x0, y0), (x1, y1)] = [pair[0], pair[1]];
+            self.draw_line(x0, y0, x1, y1, color);
+        }
+    }
+    /// Outlines the polygon through `points`, closing it back to the first point.
+    fn draw_polygon(&mut self, points: &[(usize, usize)], color: Color) {
+        for (index, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(index + 1) % points.len()];
+            self.draw_line(x0 as isize, y0 as isize, x1 as isize, y1 as isize, color);
+        }
+    }
+    /// Fills the polygon through `points` using the even-odd rule, sampling at pixel centers.
+    /// Concave and self-intersecting outlines are fine; where loops overlap an even number
+    /// of times the area is left unfilled. Fewer than three points fill nothing.
+    fn fill_polygon<'a>(&mut self, points: &[(usize, usize)], style: impl Into<FillStyle<'a>>) {
+        let points: Vec<(f64, f64)> = points.iter().map(|&(x, y)| (x as f64, y as f64)).collect();
+        self.fill_polygon_f64(&points, style);
+    }
+    /// [`Image::fill_polygon`] with fractional points that may lie outside the image, which
+    /// is clipped. Pixel (x, y) is filled when its center (x + 0.5, y + 0.5) is inside.
+    fn fill_polygon_f64<'a>(&mut self, points: &[(f64, f64)], style: impl Into<FillStyle<'a>>) {
+        fill_even_odd(self, &[points], style.into());
+    }
+    /// Fills every subpath of `path` together with the even-odd rule, so a subpath inside
+    /// another cuts a hole in it. Open subpaths are treated as closed.
+    fn fill_path<'a>(&mut self, path: &Path, style: impl Into<FillStyle<'a>>) {
+        let rings: Vec<Vec<(f64, f64)>> = path
+            .subpaths
+            .iter()
+            .map(|subpath| to_pixel_centers(&subpath.points))
+            .collect();
+        fill_even_odd(self, &rings, style.into());
+    }
+    /// Strokes every subpath of `path`, with caps on open subpaths and joins at every corner.
+    fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, color: Color) {
+        for subpath in &path.subpaths {
+            for polygon in stroke_polygons(&subpath.points, subpath.closed, style) {
+                fill_even_odd(self, &[to_pixel_centers(&polygon)], FillStyle::Solid(color));
+            }
+        }
+    }
+    /// Strokes the line between two points with the given width, caps and joins.
+    /// Whole-number coordinates land on pixel centers as in [`Image::draw_line`].
+    fn stroke_line(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        style: &StrokeStyle,
+        color: Color,
+    ) {
+        self.stroke_polyline(&[(x0, y0), (x1, y1)], style, color);
+    }
+    /// Strokes the open path through `points`, with caps at both ends and joins at every
+    /// corner. Whole-number coordinates land on pixel centers.
+    fn stroke_polyline(&mut self, points: &[(f64, f64)], style: &StrokeStyle, color: Color) {
+        for polygon in stroke_polygons(points, false, style) {
+            fill_even_odd(self, &[to_pixel_centers(&polygon)], FillStyle::Solid(color));
+        }
+    }
+    /// Strokes the outline of the polygon through `points`, joining the last point back to
+    /// the first. Whole-number coordinates land on pixel centers.
+    fn stroke_polygon(&mut self, points: &[(f64, f64)], style: &StrokeStyle, color: Color) {
+        for polygon in stroke_polygons(points, true, style) {
+            fill_even_odd(self, &[to_pixel_centers(&polygon)], FillStyle::Solid(color));
+        }
+    }
+    /// Paints the whole image with `gradient`.
+    fn fill_gradient(&mut self, gradient: &Gradient) {
+        self.fill_rect(0, 0, self.width(), self.height(), gradient);
+    }
+    /// Outlines the `width`x`height` rectangle whose top-left pixel is (x, y) with a one
+    /// pixel border inside it, clipped to the image.
+    fn draw_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let right = x.saturating_add_unsigned(width - 1);
+        let bottom = y.saturating_add_unsigned(height - 1);
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, bottom, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(right, y, 1, height, color);
+    }
+    /// Fills the `width`x`height` rectangle whose top-left pixel is (x, y), clipped to the
+    /// image.
+    fn fill_rect<'a>(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+        style: impl Into<FillStyle<'a>>,
+    ) {
+        let style = style.into();
+        let clip = |start: isize, len: usize, limit: usize| {
+            let end = start.saturating_add_unsigned(len).clamp(0, limit as isize) as usize;
+            start.clamp(0, limit as isize) as usize..end
+        };
+        let columns = clip(x, width, self.width());
+        for y in clip(y, height, self.height()) {
+            for x in columns.clone() {
+                self.set(x, y, style.color_at(x, y));
+            }
+        }
+    }
+    /// Outlines the `width`x`height` rectangle whose top-left pixel is (x, y), rounding its
+    /// corners by `radii`, with a one pixel border inside it.
+    fn draw_rounded_rect(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+        radii: CornerRadii,
+        color: Color,
+    ) {
+        if width <= 2 || height <= 2 {
+            // Too small to have an inside, so the border covers it all.
+            self.fill_rect(x, y, width, height, color);
+            return;
+        }
+        // The border's center line runs half a pixel inside the filled shape's edge.
+        let inset = |radius: f64| (radius - 0.5).max(0.0);
+        let center_radii = CornerRadii {
+            top_left: inset(radii.top_left),
+            top_right: inset(radii.top_right),
+            bottom_right: inset(radii.bottom_right),
+            bottom_left: inset(radii.bottom_left),
+        };
+        let (left, top) = (x as f64, y as f64);
+        let (right, bottom) = (left + width as f64 - 1.0, top + height as f64 - 1.0);
+        let outline = rounded_rect(left, top, right, bottom, center_radii);
+        self.stroke_polygon(&outline, &StrokeStyle::default(), color);
+    }
+    /// Fills the `width`x`height` rectangle whose top-left pixel is (x, y), rounding its
+    /// corners by `radii`. Radii too large for the rectangle are scaled down to fit.
+    fn fill_rounded_rect<'a>(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+        radii: CornerRadii,
+        style: impl Into<FillStyle<'a>>,
+    ) {
+        let (left, top) = (x as f64, y as f64);
+        let (right, bottom) = (left + width as f64, top + height as f64);
+        fill_even_odd(
+            self,
+            &[rounded_rect(left, top, right, bottom, radii)],
+            style.into(),
+        );
+    }
+    /// Outlines the circle of `radius` around (cx, cy) with the midpoint algorithm, clipped
+    /// to the image.
+    fn draw_circle(&mut self, cx: isize, cy: isize, radius: usize, color: Color) {
+        self.draw_ellipse(cx, cy, radius, radius, color);
+    }
+    /// Fills the circle of `radius` around (cx, cy), covering the same pixels as
+    /// [`Image::draw_circle`] and everything inside them.
+    fn fill_circle<'a>(
+        &mut self,
+        cx: isize,
+        cy: isize,
+        radius: usize,
+        style: impl Into<FillStyle<'a>>,
+    ) {
+        self.fill_ellipse(cx, cy, radius, radius, style);
+    }
+    /// Outlines the axis-aligned ellipse around (cx, cy) with horizontal radius `rx` and
+    /// vertical radius `ry` using the midpoint algorithm, clipped to the image.
+    fn draw_ellipse(&mut self, cx: isize, cy: isize, rx: usize, ry: usize, color: Color) {
+        midpoint_ellipse(rx, ry, |x, y| {
+            for (x, y) in [
+                (cx + x, cy + y),
+                (cx - x, cy + y),
+                (cx + x, cy - y),
+                (cx - x, cy - y),
+            ] {
+                if x >= 0 && y >= 0 {
+                    self.set(x as usize, y as usize, color);
+                }
+            }
+        });
+    }
+    /// Fills the ellipse [`Image::draw_ellipse`] outlines, outline included.
+    fn fill_ellipse<'a>(
+        &mut self,
+        cx: isize,
+        cy: isize,
+        rx: usize,
+        ry: usize,
+        style: impl Into<FillStyle<'a>>,
+    ) {
+        let style = style.into();
+        midpoint_ellipse(rx, ry, |x, y| {
+            let span = 2 * x as usize + 1;
+            self.fill_rect(cx - x, cy + y, span, 1, style);
+            self.fill_rect(cx - x, cy - y, span, 1, style);
+        });
+    }
+    /// Outlines the part of the ellipse [`Image::draw_ellipse`] draws that runs clockwise from
+    /// `start_deg` to `end_deg`. Angles are in degrees as seen from the center, with 0 at the
+    /// right and 90 straight down; a sweep of 360 or more draws the whole ellipse.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_arc(
+        &mut self,
+        cx: isize,
+        cy: isize,
+        rx: usize,
+        ry: usize,
+        start_deg: f64,
+        end_deg: f64,
+        color: Color,
+    ) {
+        let within = angle_range(start_deg, end_deg);
+        midpoint_ellipse(rx, ry, |x, y| {
+            for (x, y) in [(x, y), (-x, y), (x, -y), (-x, -y)] {
+                let (px, py) = (cx + x, cy + y);
+                if px >= 0 && py >= 0 && within(x, y) {
+                    self.set(px as usize, py as usize, color);
+                }
+            }
+        });
+    }
+    /// Fills the pie slice of the ellipse [`Image::fill_ellipse`] covers between `start_deg`
+    /// and `end_deg`, measured as in [`Image::draw_arc`].
+    #[allow(clippy::too_many_arguments)]
+    fn fill_pie<'a>(
+        &mut self,
+        cx: isize,
+        cy: isize,
+        rx: usize,
+        ry: usize,
+        start_deg: f64,
+        end_deg: f64,
+        style: impl Into<FillStyle<'a>>,
+    ) {
+        let style = style.into();
+        let within = angle_range(start_deg, end_deg);
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        midpoint_ellipse(rx, ry, |half_width, y| {
+            for y in [y, -y] {
+                if !(0..height).contains(&(cy + y)) {
+                    continue;
+                }
+                let first = (cx - half_width).max(0);
+                let last = (cx + half_width).min(width - 1);
+                for px in first..=last {
+                    if within(px - cx, y) {
+                        let (px, py) = (px as usize, (cy + y) as usize);
+                        self.set(px, py, style.color_at(px, py));
+                    }
+                }
+            }
+        });
+    }
+    /// Strokes the arc of the circle of `radius` around (cx, cy) from `start_deg` clockwise
+    /// to `end_deg`, measured as in [`Image::draw_arc`]. With a wide style this draws
+    /// progress rings and donut chart segments.
+    #[allow(clippy::too_many_arguments)]
+    fn stroke_arc(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        start_deg: f64,
+        end_deg: f64,
+        style: &StrokeStyle,
+        color: Color,
+    ) {
+        let sweep = clockwise_sweep(start_deg, end_deg);
+        let start = start_deg.to_radians();
+        let points: Vec<(f64, f64)> =
+            arc((cx, cy), radius, start, start + sweep.to_radians()).collect();
+        self.stroke_polyline(&points, style, color);
+    }
+    /// Fills a column `thickness` pixels wide centered on `pos`, clipped to the image.
+    /// Fails if `pos` itself is outside the image.
+    fn draw_vline(&mut self, pos: usize, thickness: usize, color: Color) -> Result<(), ImageError> {
+        let columns =
+            line_span(pos, thickness, self.width()).ok_or_else(|| out_of_bounds(self, pos, 0))?;
+        for x in columns {
+            for y in 0..self.height() {
+                self.set(x, y, color);
+            }
+        }
+        Ok(())
+    }
+    /// Fills a row `thickness` pixels tall centered on `pos`, clipped to the image.
+    /// Fails if `pos` itself is outside the image.
+    fn draw_hline(&mut self, pos: usize, thickness: usize, color: Color) -> Result<(), ImageError> {
+        let rows =
+            line_span(pos, thickness, self.height()).ok_or_else(|| out_of_bounds(self, 0, pos))?;
+        for y in rows {
+            for x in 0..self.width() {
+                self.set(x, y, color);
+            }
+        }
+        Ok(())
+    }
+    /// Writes `text` in the built-in 8x8 font with its top-left corner at (x, y), drawing each
+    /// font pixel as a `scale`x`scale` square, clipped to the image. Every line break starts a
+    /// new line below the first. [`text_size`](crate::text_size) measures the text beforehand.
+    fn draw_text(&mut self, x: isize, y: isize, text: &str, color: Color, scale: usize) {
+        let step = (GLYPH_SIZE * scale) as isize;
+        for (line_index, line) in text.lines().enumerate() {
+            let top = y + line_index as isize * step;
+            for (column, character) in line.chars().enumerate() {
+                let left = x + column as isize * step;
+                for (row, bits) in glyph(character).iter().enumerate() {
+                    for bit in (0..GLYPH_SIZE).filter(|bit| bits >> bit & 1 == 1) {
+                        let (px, py) = ((bit * scale) as isize, (row * scale) as isize);
+                        self.fill_rect(left + px, top + py, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+    /// Writes `text` in `font` at `size` pixels per em with its top-left corner at (x, y),
+    /// anti-aliased, kerned and blended over the image. Every line break starts a new line
+    /// spaced by the font's line height. [`Font::text_size`] measures the text beforehand.
+    #[cfg(feature = "truetype")]
+    fn draw_text_ttf(
+        &mut self,
+        x: isize,
+        y: isize,
+        text: &str,
+        font: &Font,
+        size: f64,
+        color: Color,
+    ) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let clip = [
+            x.saturating_neg(),
+            y.saturating_neg(),
+            width.saturating_sub(x),
+            height.saturating_sub(y),
+        ];
+        font.rasterize(text, size, clip, |px, py, coverage| {
+            blend(self, x + px, y + py, color, coverage);
+        });
+    }
+    /// Paint-bucket fill of the region connected to (x, y) whose channels, alpha included,
+    /// are each within `tolerance` of the seed pixel. See [`ReadImage::flood_select`].
+    fn flood_fill(&mut self, x: usize, y: usize, fill: Color, tolerance: u8) {
+        let width = self.width();
+        let region = self.flood_select(x, y, tolerance);
+        for (index, _) in region.iter().enumerate().filter(|&(_, &inside)| inside) {
+            self.set(index % width, index / width, fill);
+        }
+    }
+    fn mirror_horizontal_left(&mut self) {
+        let width = self.width();
+        let source = colors(self);
+        self.update(|x, y, color| {
+            if x >= width - width / 2 {
+                source[y * width + width - 1 - x]
+            } else {
+                color
+            }
+        });
+    }
+    fn vertical_fade_left(&mut self) {
+        self.fade(FadeDirection::Left, 0.0, 1.0);
+    }
+    fn fade(&mut self, direction: FadeDirection, from: f64, to: f64) {
+        self.fade_with(direction, from, to, Blending::Srgb);
+    }
+    /// Scales every color by a multiplier running from `from` at the `direction` edge to
+    /// `to` at the opposite one, applied in the given `blending` space.
+    fn fade_with(&mut self, direction: FadeDirection, from: f64, to: f64, blending: Blending) {
+        let last_x = self.width().saturating_sub(1).max(1) as f64;
+        let last_y = self.height().saturating_sub(1).max(1) as f64;
+        self.update(|x, y, color| {
+            let t = match direction {
+                FadeDirection::Left => x as f64 / last_x,
+                FadeDirection::Right => 1.0 - x as f64 / last_x,
+                FadeDirection::Up => y as f64 / last_y,
+                FadeDirection::Down => 1.0 - y as f64 / last_y,
+            };
+            let factor = from + (to - from) * t;
+            match blending {
+                Blending::Srgb => color * factor,
+                Blending::Linear => {
+                    let (r, g, b) = color.to_linear();
+                    Color::from_linear(r * factor, g * factor, b * factor).with_alpha(color.a())
+                }
+            }
+        });
+    }
+    fn make_red(&mut self) {
+        fill(self, Color::RED);
+    }
+    fn make_blue(&mut self) {
+        fill(self, Color::BLUE);
+    }
+    /// Swaps every pixel whose red, green and blue channels each differ from `target` by at
+    /// most `tolerance` (a per-channel maximum, not a Euclidean distance) for `replacement`.
+    fn replace_color(&mut self, target: Color, replacement: Color, tolerance: u8) {
+        self.update(|_, _, color| {
+            if color.within_tolerance(&target, tolerance) {
+                replacement
+            } else {
+                color
+            }
+        });
+    }
+    /// Rotates hue by `hue_deg` (wrapping at 360°) and scales saturation and value,
+    /// clamping both to the 0..=1 range.
+    fn adjust_hsv(&mut self, hue_deg: f64, sat_mul: f64, val_mul: f64) {
+        self.update(|_, _, color| {
+            let (hue, saturation, value) = color.to_hsv();
+            Color::from_hsv(
+                (hue + hue_deg).rem_euclid(360.0),
+                clamp(saturation * sat_mul, 0.0, 1.0),
+                clamp(value * val_mul, 0.0, 1.0),
+            )
+            .with_alpha(color.a())
+        });
+    }
+    /// Replaces every color with its negative, keeping alpha. Like every per-pixel filter
+    /// this is a single [`Image::update`], so it runs in parallel on a [`BmpFile`] with the
+    /// `rayon` feature and respects views and clip rectangles.
+    fn invert(&mut self) {
+        self.update(|_, _, color| !color);
+    }
+    /// Replaces every color with the gray of its [`Luma::Rec601`] luma, keeping alpha.
+    fn grayscale(&mut self) {
+        self.grayscale_with(Luma::Rec601);
+    }
+    /// Replaces every color with the gray of its luma under the given weights, keeping alpha.
+    fn grayscale_with(&mut self, luma: Luma) {
+        self.update(|_, _, color| Color::gray(color.luma(luma)).with_alpha(color.a()));
+    }
+    /// Tones the image a warm brown with the classic sepia matrix, which also brightens it
+    /// slightly. For other colors use [`Image::duotone`].
+    fn sepia(&mut self) {
+        self.update(|_, _, color| {
+            let (r, g, b) = (color.r() as f64, color.g() as f64, color.b() as f64);
+            Color::rgba(
+                clamp(0.393 * r + 0.769 * g + 0.189 * b, 0.0, 255.0) as u8,
+                clamp(0.349 * r + 0.686 * g + 0.168 * b, 0.0, 255.0) as u8,
+                clamp(0.272 * r + 0.534 * g + 0.131 * b, 0.0, 255.0) as u8,
+                color.a(),
+            )
+        });
+    }
+    /// Maps each pixel's luminance onto the ramp from `dark` (black) to `light` (white),
+    /// keeping the pixel's alpha.
+    fn duotone(&mut self, dark: Color, light: Color) {
+        let mut ramp = [Color::TRANSPARENT; 256];
+        for (luminance, color) in ramp.iter_mut().enumerate() {
+            *color = Color::lerp(dark, light, luminance as f64 / 255.0);
+        }
+        self.update(|_, _, color| ramp[color.luminance() as usize].with_alpha(color.a()));
+    }
+    /// Adds `delta` to red, green and blue, clamping to 0..=255.
+    fn adjust_brightness(&mut self, delta: i16) {
+        self.brightness_contrast(delta, 1.0);
+    }
+    /// Scales the distance of red, green and blue from mid-gray (128) by `factor`, clamping
+    /// to 0..=255: above 1 increases contrast, between 0 and 1 flattens it.
+    fn adjust_contrast(&mut self, factor: f64) {
+        self.brightness_contrast(0, factor);
+    }
+    /// [`Image::adjust_contrast`] by `factor` followed by [`Image::adjust_brightness`] by
+    /// `delta`, through a single lookup table.
+    fn brightness_contrast(&mut self, delta: i16, factor: f64) {
+        let mut lut = [0; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            let adjusted = (value as f64 - 128.0) * factor + 128.0 + delta as f64;
+            *entry = clamp(adjusted.round(), 0.0, 255.0) as u8;
+        }
+        apply_lut(self, &lut);
+    }
+    /// Raises red, green and blue, scaled to 0..=1, to the power `1 / gamma` through a lookup
+    /// table, so a `gamma` above 1 brightens the midtones and below 1 darkens them.
+    fn gamma(&mut self, gamma: f64) {
+        let mut lut = [0; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            let corrected = 255.0 * (value as f64 / 255.0).powf(1.0 / gamma);
+            *entry = clamp(corrected.round(), 0.0, 255.0) as u8;
+        }
+        apply_lut(self, &lut);
+    }
+    /// Decodes the stored sRGB values to linear light, still 8 bits per channel, for
+    /// processing that should add up light, such as resampling. Dark shades lose precision.
+    fn srgb_to_linear(&mut self) {
+        let mut lut = [0; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            *entry = (srgb_to_linear(value as u8) * 255.0).round() as u8;
+        }
+        apply_lut(self, &lut);
+    }
+    /// Encodes linear-light values back to sRGB, undoing [`Image::srgb_to_linear`].
+    fn linear_to_srgb(&mut self) {
+        let mut lut = [0; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            *entry = linear_to_srgb(value as f64 / 255.0);
+        }
+        apply_lut(self, &lut);
+    }
+    /// Applies a [`Levels`] adjustment to red, green and blue alike.
+    fn levels(&mut self, levels: &Levels) {
+        apply_lut(self, &levels.lut());
+    }
+    /// Maps red, green and blue alike through the tone curve through `points`, each an
+    /// `(input, output)` pair, smoothly interpolated without overshooting.
+    fn curves(&mut self, points: &[(u8, u8)]) {
+        apply_lut(self, &curve_lut(points));
+    }
+    /// [`Image::curves`] with a separate curve for each channel.
+    fn curves_rgb(&mut self, red: &[(u8, u8)], green: &[(u8, u8)], blue: &[(u8, u8)]) {
+        apply_channel_luts(self, [red, green, blue].map(curve_lut));
+    }
+    /// Rounds red, green and blue each to the nearest of `levels` evenly spaced values from
+    /// 0 to 255 inclusive, so 2 leaves only 0 and 255. Fewer than 2 levels count as 2.
+    fn posterize(&mut self, levels: u8) {
+        self.posterize_rgb(levels, levels, levels);
+    }
+    /// [`Image::posterize`] with a separate number of levels per channel, such as 8, 8 and 4
+    /// to fit the 256 colors of a 3-3-2 palette.
+    fn posterize_rgb(&mut self, red: u8, green: u8, blue: u8) {
+        apply_channel_luts(
+            self,
+            [red, green, blue].map(|levels| {
+                let mut lut = [0; 256];
+                for (value, entry) in lut.iter_mut().enumerate() {
+                    *entry = quantize_channel(value as f64, levels);
+                }
+                lut
+            }),
+        );
+    }
+    /// Posterizes with Floyd-Steinberg error diffusion, spreading each pixel's
+    /// quantization error onto its unvisited neighbours.
+    fn dither(&mut self, levels: u8) {
+        diffuse_error(self, |value| {
+            let [r, g, b] = value.map(|channel| quantize_channel(channel, levels));
+            Color::rgb(r, g, b)
+        });
+    }
+    /// Floyd-Steinberg dithering to the nearest colors of `palette` by RGB distance, such as
+    /// black and white for a 1-bit image or the table of a paletted file from
+    /// [`Header::palette`]. Alpha is kept, and an empty palette leaves the image unchanged.
+    ///
+    /// [`Header::palette`]: crate::Header::palette
+    ///
+    /// ```no_run
+    /// use image_manip::{BmpFile, Image};
+    ///
+    /// let mut image = BmpFile::open("paletted.bmp")?;
+    /// image.dither_to_palette(&image.header().palette());
+    /// image.save("paletted.bmp")?;
+    /// # Ok::<(), image_manip::ImageError>(())
+    /// ```
+    fn dither_to_palette(&mut self, palette: &[Color]) {
+        if palette.is_empty() {
+            return;
+        }
+        diffuse_error(self, |[r, g, b]| {
+            let color = Color::rgb(
+                clamp(r.round(), 0.0, 255.0) as u8,
+                clamp(g.round(), 0.0, 255.0) as u8,
+                clamp(b.round(), 0.0, 255.0) as u8,
+            );
+            palette[color.nearest_index(palette)]
+        });
+    }
+    /// Reduces the image to at most `count` colors: builds a palette with
+    /// [`ReadImage::extract_palette`], replaces every pixel with its nearest entry, keeping
+    /// alpha, and returns the palette. To dither instead, pass the palette from
+    /// [`ReadImage::extract_palette`] to [`Image::dither_to_palette`].
+    fn quantize(&mut self, count: usize) -> Vec<Color> {
+        let palette = self.extract_palette(count);
+        let mut nearest: HashMap<Color, Color> = HashMap::new();
+        for color in colors(self) {
+            let opaque = color.with_alpha(255);
+            nearest
+                .entry(opaque)
+                .or_insert_with(|| palette[opaque.nearest_index(&palette)]);
+        }
+        self.update(|_, _, color| nearest[&color.with_alpha(255)].with_alpha(color.a()));
+        palette
+    }
+    /// Posterizes with ordered dithering, nudging each pixel up or down by its entry in the
+    /// Bayer `matrix` before rounding to one of `levels` per channel. Unlike
+    /// [`Image::dither`], every pixel's result depends only on its own color and position,
+    /// so flat areas dither identically wherever they are and across animation frames.
+    fn ordered_dither(&mut self, levels: u8, matrix: BayerMatrix) {
+        let step = 255.0 / (levels.max(2) as f64 - 1.0);
+        let cells = (matrix.size() * matrix.size()) as f64;
+        self.update(|x, y, color| {
+            let offset = ((matrix.threshold(x, y) as f64 + 0.5) / cells - 0.5) * step;
+            let [r, g, b] = [color.r(), color.g(), color.b()]
+                .map(|channel| quantize_channel(channel as f64 + offset, levels));
+            Color::rgba(r, g, b, color.a())
+        });
+    }
+    /// Fills each `block`x`block` tile with its average color. Tiles along the right and
+    /// bottom edges may be smaller and are averaged over just the pixels they cover.
+    fn pixelate(&mut self, block: usize) {
+        let (width, height) = (self.width(), self.height());
+        let block = block.max(1);
+        let tiles_across = width.div_ceil(block);
+        let mut sums = vec![[0usize; 5]; tiles_across * height.div_ceil(block)];
+        for (index, color) in colors(self).into_iter().enumerate() {
+            let (x, y) = (index % width, index / width);
+            let sum = &mut sums[y / block * tiles_across + x / block];
+            sum[0] += color.r() as usize;
+            sum[1] += color.g() as usize;
+            sum[2] += color.b() as usize;
+            sum[3] += color.a() as usize;
+            sum[4] += 1;
+        }
+        let averages: Vec<Color> = sums
+            .iter()
+            .map(|&[r, g, b, a, count]| {
+                let [r, g, b, a] = [r, g, b, a].map(|sum| ((sum + count / 2) / count.max(1)) as u8);
+                Color::rgba(r, g, b, a)
+            })
+            .collect();
+        self.update(|x, y, _| averages[y / block * tiles_across + x / block]);
+    }
+    /// Replaces each channel with its median over the surrounding square of the given radius.
+    /// Neighbours past the edges use [`BorderMode::Replicate`].
+    fn median_filter(&mut self, radius: usize) {
+        let (width, height) = (self.width(), self.height());
+        let source = colors(self);
+        let resolve =
+            |position: isize, len: usize| BorderMode::Replicate.resolve(position, len).unwrap_or(0);
+        let radius = radius as isize;
+        // Reused for every pixel, so only the first one allocates.
+        let mut window: [Vec<u8>; 3] = Default::default();
+        let mut filtered = Vec::with_capacity(source.len());
+        for y in 0..height {
+            for x in 0..width {
+                window.iter_mut().for_each(Vec::clear);
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let neighbour_x = resolve(x as isize + dx, width);
+                        let neighbour_y = resolve(y as isize + dy, height);
+                        let neighbour = source[neighbour_y * width + neighbour_x];
+                        window[0].push(neighbour.r());
+                        window[1].push(neighbour.g());
+                        window[2].push(neighbour.b());
+                    }
+                }
+                let [r, g, b] = window.each_mut().map(|channel| {
+                    let middle = channel.len() / 2;
+                    *channel.select_nth_unstable(middle).1
+                });
+                filtered.push(Color::rgb(r, g, b));
+            }
+        }
+        self.update(|x, y, color| filtered[y * width + x].with_alpha(color.a()));
+    }
+    /// Convolves every channel but alpha with a square `size`x`size` kernel given in
+    /// row-major order, sampling past the edges through `border`. Fails without touching
+    /// the image unless `size` is at least 1 and `kernel` holds `size * size` weights.
+    fn convolve(
+        &mut self,
+        kernel: &[f64],
+        size: usize,
+        border: BorderMode,
+    ) -> Result<(), ImageError> {
+        if size == 0 || Some(kernel.len()) != size.checked_mul(size) {
+            return Err(ImageError::KernelSize {
+                size,
+                len: kernel.len(),
+            });
+        }
+        convolve_rgb(self, kernel, size, border);
+        Ok(())
+    }
+    /// Box blur averaging the square of the given radius around each pixel.
+    /// Uses [`BorderMode::Replicate`] at the edges.
+    fn blur(&mut self, radius: usize) {
+        self.box_blur(radius);
+    }
+    /// Averages every channel but alpha over the `radius * 2 + 1` square around each pixel,
+    /// replicating edge pixels past the borders. Running sums along rows and then columns
+    /// make the cost independent of the radius. Radii past the larger image dimension count
+    /// as that dimension.
+    fn box_blur(&mut self, radius: usize) {
+        let (width, height) = (self.width(), self.height());
+        let radius = radius.min(width.max(height));
+        let source = colors(self);
+        let size = radius as u64 * 2 + 1;
+        let area = size * size;
+        let [r, g, b] = [0, 1, 2].map(|channel| {
+            let mut plane: Vec<u64> = source
+                .iter()
+                .map(|color| [color.r(), color.g(), color.b()][channel] as u64)
+                .collect();
+            for row in plane.chunks_mut(width.max(1)) {
+                let sums = window_sums(row, radius);
+                row.copy_from_slice(&sums);
+            }
+            for x in 0..width {
+                let column: Vec<u64> = (0..height).map(|y| plane[y * width + x]).collect();
+                for (y, sum) in window_sums(&column, radius).into_iter().enumerate() {
+                    plane[y * width + x] = sum;
+                }
+            }
+            plane
+        });
+        self.update(|x, y, color| {
+            let index = y * width + x;
+            let channel = |plane: &[u64]| ((plane[index] + area / 2) / area) as u8;
+            Color::rgba(channel(&r), channel(&g), channel(&b), color.a())
+        });
+    }
+    /// Blurs every channel but alpha with a Gaussian of standard deviation `sigma` pixels,
+    /// as a horizontal and then a vertical pass, replicating edge pixels past the borders.
+    /// The kernel reaches three sigmas either side, but no further than the larger image
+    /// dimension; a `sigma` of zero or less does nothing.
+    fn gaussian_blur(&mut self, sigma: f64) {
+        if sigma.is_nan() || sigma <= 0.0 {
+            return;
+        }
+        let limit = self.width().max(self.height()) as f64;
+        let radius = (sigma * 3.0).ceil().min(limit) as isize;
+        let weights: Vec<f64> = (-radius..=radius)
+            .map(|offset| (-(offset * offset) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let kernel: Vec<f64> = weights.iter().map(|weight| weight / total).collect();
+        convolve_separable(self, &kernel, BorderMode::Replicate);
+    }
+    /// Crisps edges with the 3x3 kernel that adds each pixel's difference from its four
+    /// direct neighbours. Uses [`BorderMode::Replicate`] at the edges.
+    fn sharpen(&mut self) {
+        const KERNEL: [f64; 9] = [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0];
+        convolve_rgb(self, &KERNEL, 3, BorderMode::Replicate);
+    }
+    /// Sharpens by adding back `amount` times the detail a [`Image::gaussian_blur`] with
+    /// standard deviation `sigma` removes, so 1 doubles the contrast of fine detail. Like the
+    /// blur, the effect reaches about three sigmas from each edge. Channels that differ from
+    /// their blurred value by less than `threshold` are left alone, which keeps noise and
+    /// smooth gradients from being sharpened. Alpha is kept.
+    fn unsharp_mask(&mut self, sigma: f64, amount: f64, threshold: u8) {
+        let width = self.width();
+        let original = colors(self);
+        self.gaussian_blur(sigma);
+        self.update(|x, y, blurred| {
+            let color = original[y * width + x];
+            let channel = |value: u8, blurred: u8| {
+                let detail = value as f64 - blurred as f64;
+                if detail.abs() < threshold as f64 {
+                    value
+                } else {
+                    clamp((value as f64 + amount * detail).round(), 0.0, 255.0) as u8
+                }
+            };
+            Color::rgba(
+                channel(color.r(), blurred.r()),
+                channel(color.g(), blurred.g()),
+                channel(color.b(), blurred.b()),
+                color.a(),
+            )
+        });
+    }
+    /// Smears the image along a line `length` pixels long at `angle_deg`, in degrees with 0
+    /// pointing right and 90 straight down, as if it moved during the exposure. Each pixel
+    /// averages samples along the line centered on it, replicating edge pixels past the
+    /// borders. Alpha is kept.
+    fn motion_blur(&mut self, angle_deg: f64, length: f64) {
+        let (dx, dy) = angle_deg.to_radians().sin_cos();
+        let (dx, dy) = (dy * length / 2.0, dx * length / 2.0);
+        blur_along(self, |x, y| ((x - dx, y - dy), (x + dx, y + dy)));
+    }
+    /// Zoom blur: smears the image along lines through (cx, cy), as if zooming in during the
+    /// exposure. Each pixel averages samples along the line towards the center covering
+    /// `amount` of its distance from it, centered on the pixel, so 0 leaves the image sharp
+    /// and the blur grows towards the edges. Alpha is kept.
+    fn radial_blur(&mut self, cx: f64, cy: f64, amount: f64) {
+        blur_along(self, |x, y| {
+            let (dx, dy) = ((cx - x) * amount / 2.0, (cy - y) * amount / 2.0);
+            ((x - dx, y - dy), (x + dx, y + dy))
+        });
+    }
+    /// Turns pixels with luminance of at least `level` white and the rest black, keeping
+    /// alpha.
+    fn threshold(&mut self, level: u8) {
+        self.update(|_, _, color| {
+            let shade = if color.luminance() >= level {
+                Color::WHITE
+            } else {
+                Color::BLACK
+            };
+            shade.with_alpha(color.a())
+        });
+    }
+    /// [`Image::threshold`] at the level [`ReadImage::otsu_level`] picks.
+    fn otsu_threshold(&mut self) {
+        self.threshold(self.otsu_level());
+    }
+}
+
+/// Floyd-Steinberg error diffusion: replaces every pixel, in row-major order, with
+/// `quantize` of its color plus the error spread onto it so far, then spreads the
+/// difference onto its unvisited neighbours. Alpha is kept.
+fn diffuse_error<I: Image + ?Sized>(image: &mut I, quantize: impl Fn([f64; 3]) -> Color) {
+    let (width, height) = (image.width(), image.height());
+    let mut values: Vec<[f64; 3]> = colors(image)
+        .iter()
+        .map(|color| [color.r() as f64, color.g() as f64, color.b() as f64])
+        .collect();
+    let mut dithered = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let old = values[y * width + x];
+            let color = quantize(old);
+            let new = [color.r(), color.g(), color.b()];
+            dithered.push(color);
+            let mut spread = |x: usize, y: usize, weight: f64| {
+                if x < width && y < height {
+                    let target = &mut values[y * width + x];
+                    for channel in 0..3 {
+                        target[channel] += (old[channel] - new[channel] as f64) * weight;
+                    }
+                }
+            };
+            spread(x + 1, y, 7.0 / 16.0);
+            if x > 0 {
+                spread(x - 1, y + 1, 3.0 / 16.0);
+            }
+            spread(x, y + 1, 5.0 / 16.0);
+            spread(x + 1, y + 1, 1.0 / 16.0);
+        }
+    }
+    image.update(|x, y, color| dithered[y * width + x].with_alpha(color.a()));
+}
+
+/// [`Image::convolve`] with a kernel already known to be `size`x`size`.
+fn convolve_rgb<I: Image + ?Sized>(image: &mut I, kernel: &[f64], size: usize, border: BorderMode) {
+    let (width, height) = (image.width(), image.height());
+    let source = colors(image);
+    let channels: [Vec<f64>; 3] = [
+        source.iter().map(|color| color.r() as f64).collect(),
+        source.iter().map(|color| color.g() as f64).collect(),
+        source.iter().map(|color| color.b() as f64).collect(),
+    ];
+    let outside = match border {
+        BorderMode::Constant(color) => [color.r(), color.g(), color.b()].map(f64::from),
+        _ => [0.0; 3],
+    };
+    let [r, g, b] = [0, 1, 2].map(|channel| {
+        convolve_plane(
+            &channels[channel],
+            width,
+            height,
+            kernel,
+            size,
+            border,
+            outside[channel],
+        )
+    });
+    image.update(|x, y, color| {
+        let index = y * width + x;
+        let channel = |plane: &[f64]| clamp(plane[index].round(), 0.0, 255.0) as u8;
+        Color::rgba(channel(&r), channel(&g), channel(&b), color.a())
+    });
+}
+
+/// Convolves every channel but alpha of `image` with the odd-length `kernel` along rows, then
+/// with the same kernel along columns, sampling past the edges through `border`.
+fn convolve_separable<I: Image + ?Sized>(image: &mut I, kernel: &[f64], border: BorderMode) {
+    let (width, height) = (image.width(), image.height());
+    let radius = (kernel.len() / 2) as isize;
+    let source = colors(image);
+    let outside = match border {
+        BorderMode::Constant(color) => [color.r(), color.g(), color.b()].map(f64::from),
+        _ => [0.0; 3],
+    };
+    let pass = |values: &[f64], outside: f64| -> Vec<f64> {
+        (0..values.len() as isize)
+            .map(|index| {
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, weight)| {
+                        let sample = border
+                            .resolve(index + offset as isize - radius, values.len())
+                            .map_or(outside, |index| values[index]);
+                        sample * weight
+                    })
+                    .sum()
+            })
+            .collect()
+    };
+    let [r, g, b] = [0, 1, 2].map(|channel| {
+        let mut plane: Vec<f64> = source
+            .iter()
+            .map(|color| [color.r(), color.g(), color.b()][channel] as f64)
+            .collect();
+        for row in plane.chunks_mut(width.max(1)) {
+            let filtered = pass(row, outside[channel]);
+            row.copy_from_slice(&filtered);
+        }
+        for x in 0..width {
+            let column: Vec<f64> = (0..height).map(|y| plane[y * width + x]).collect();
+            for (y, value) in pass(&column, outside[channel]).into_iter().enumerate() {
+                plane[y * width + x] = value;
+            }
+        }
+        plane
+    });
+    image.update(|x, y, color| {
+        let index = y * width + x;
+        let channel = |plane: &[f64]| clamp(plane[index].round(), 0.0, 255.0) as u8;
+        Color::rgba(channel(&r), channel(&g), channel(&b), color.a())
+    });
+}
+
+/// Replaces every pixel's red, green and blue with their average along the segment
+/// `segment(x, y)` returns for it, sampled bilinearly about once per pixel of its length,
+/// with edge pixels replicated past the borders.
+fn blur_along<I: Image + ?Sized>(
+    image: &mut I,
+    segment: impl Fn(f64, f64) -> ((f64, f64), (f64, f64)) + Sync,
+) {
+    let (width, height) = (image.width(), image.height());
+    let source = colors(image);
+    let at = |x: isize, y: isize| {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        let color = source[y * width + x];
+        [color.r(), color.g(), color.b()].map(f64::from)
+    };
+    let sample = |x: f64, y: f64| {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let corners = [
+            (at(x0, y0), (1.0 - fx) * (1.0 - fy)),
+            (at(x0 + 1, y0), fx * (1.0 - fy)),
+            (at(x0, y0 + 1), (1.0 - fx) * fy),
+            (at(x0 + 1, y0 + 1), fx * fy),
+        ];
+        let mut value = [0.0; 3];
+        for (corner, weight) in corners {
+            for channel in 0..3 {
+                value[channel] += corner[channel] * weight;
+            }
+        }
+        value
+    };
+    image.update(|x, y, color| {
+        let ((x0, y0), (x1, y1)) = segment(x as f64, y as f64);
+        let samples = ((x1 - x0).hypot(y1 - y0).ceil() as usize).clamp(1, 10_000) + 1;
+        let mut sum = [0.0; 3];
+        for step in 0..samples {
+            let t = step as f64 / (samples - 1) as f64;
+            let value = sample(x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+            for channel in 0..3 {
+                sum[channel] += value[channel];
+            }
+        }
+        let [r, g, b] = sum.map(|total| clamp((total / samples as f64).round(), 0.0, 255.0) as u8);
+        Color::rgba(r, g, b, color.a())
+    });
+}
+
+/// The sum of the `radius * 2 + 1` values centered on each position of `values`, with the
+/// first and last values repeated past the ends, by a sliding window.
+fn window_sums(values: &[u64], radius: usize) -> Vec<u64> {
+    let Some(last) = values.len().checked_sub(1) else {
+        return Vec::new();
+    };
+    let at = |index: isize| values[index.clamp(0, last as isize) as usize];
+    let radius = radius as isize;
+    // The first window holds radius + 1 copies of the first value, then the values after it,
+    // padded out with copies of the last value if the window is wider than `values`.
+    let mut sum = (radius as u64 + 1) * values[0]
+        + values[1..=last.min(radius as usize)].iter().sum::<u64>()
+        + (radius - last as isize).max(0) as u64 * values[last];
+    let mut sums = Vec::with_capacity(values.len());
+    for index in 0..values.len() as isize {
+        sums.push(sum);
+        sum = sum + at(index + radius + 1) - at(index - radius);
+    }
+    sums
+}
+
+/// Every color of `image` in row-major order.
+fn colors<I: ReadImage + ?Sized>(image: &I) -> Vec<Color> {
+    (0..image.height()).flat_map(|y| image.row(y)).collect()
+}
+
+/// Sets every pixel of `image` to `color`.
+fn fill<I: Image + ?Sized>(image: &mut I, color: Color) {
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            image.set(x, y, color);
+        }
+    }
+}
+
+/// Maps every channel of every pixel through a 256-entry lookup table.
+fn apply_lut<I: Image + ?Sized>(image: &mut I, lut: &[u8; 256]) {
+    apply_channel_luts(image, [*lut; 3]);
+}
+
+/// Maps red, green and blue of every pixel through their own 256-entry lookup tables.
+fn apply_channel_luts<I: Image + ?Sized>(image: &mut I, [red, green, blue]: [[u8; 256]; 3]) {
+    image.update(|_, _, color| {
+        Color::rgba(
+            red[color.r() as usize],
+            green[color.g() as usize],
+            blue[color.b() as usize],
+            color.a(),
+        )
+    });
+}
+
+/// The rows or columns within `0..len` covered by a line of `thickness` centered on `pos`,
+/// or `None` if `pos` is not.
+fn line_span(pos: usize, thickness: usize, len: usize) -> Option<std::ops::Range<usize>> {
+    (pos < len)
+        .then(|| pos.saturating_sub(thickness / 2)..(pos + thickness - thickness / 2).min(len))
+}
+
+/// Fills the polygons `rings` together using the even-odd rule, sampling at pixel centers,
+/// so pixel (x, y) covers the square from (x, y) to (x + 1, y + 1). Rings with fewer than
+/// three points fill nothing.
+fn fill_even_odd<I, R>(image: &mut I, rings: &[R], style: FillStyle)
+where
+    I: Image + ?Sized,
+    R: AsRef<[(f64, f64)]>,
+{
+    let rings: Vec<&[(f64, f64)]> = rings
+        .iter()
+        .map(|ring| ring.as_ref())
+        .filter(|ring| ring.len() >= 3)
+        .collect();
+    let all_y = || rings.iter().flat_map(|ring| ring.iter().map(|&(_, y)| y));
+    let min_y = all_y().fold(f64::INFINITY, f64::min);
+    let max_y = all_y().fold(f64::NEG_INFINITY, f64::max);
+    let first_row = min_y.floor().max(0.0) as usize;
+    let end_row = max_y.ceil().clamp(0.0, image.height() as f64) as usize;
+    let mut crossings: Vec<f64> = Vec::new();
+    for y in first_row..end_row {
+        let center_y = y as f64 + 0.5;
+        crossings.clear();
+        for ring in &rings {
+            for (index, &(x0, y0)) in ring.iter().enumerate() {
+                let (x1, y1) = ring[(index + 1) % ring.len()];
+                if (y0 <= center_y) != (y1 <= center_y) {
+                    crossings.push(x0 + (center_y - y0) * (x1 - x0) / (y1 - y0));
+                }
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+        for span in crossings.chunks_exact(2) {
+            let start = (span[0] - 0.5).ceil().max(0.0) as usize;
+            let end = ((span[1] - 0.5).floor() + 1.0).clamp(0.0, image.width() as f64) as usize;
+            for x in start..end {
+                image.set(x, y, style.color_at(x, y));
+            }
+        }
+    }
+}
+
+/// Shifts points whose whole-number coordinates name pixel centers into the coordinates
+/// [`fill_even_odd`] uses.
+fn to_pixel_centers(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    points.iter().map(|&(x, y)| (x + 0.5, y + 0.5)).collect()
+}
+
+/// Calls `visit` with the offsets from the center of the pixels on one quarter of the
+/// outline of an ellipse with radii `rx` and `ry`, for x and y both at least zero.
+/// The decision variables are kept four times too large so they stay whole numbers.
+fn midpoint_ellipse<F: FnMut(isize, isize)>(rx: usize, ry: usize, mut visit: F) {
+    let (rx, ry) = (rx as i64, ry as i64);
+    if ry == 0 {
+        (0..=rx).for_each(|x| visit(x as isize, 0));
+        return;
+    }
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let (mut x, mut y) = (0, ry);
+    let (mut step_x, mut step_y) = (0, 2 * rx2 * y);
+    // Where the slope is shallower than -1, x advances every step.
+    let mut decision = 4 * ry2 - 4 * rx2 * ry + rx2;
+    while step_x < step_y {
+        visit(x as isize, y as isize);
+        x += 1;
+        step_x += 2 * ry2;
+        if decision < 0 {
+            decision += 4 * (ry2 + step_x);
+        } else {
+            y -= 1;
+            step_y -= 2 * rx2;
+            decision += 4 * (ry2 + step_x - step_y);
+        }
+    }
+    // Past it, y advances every step.
+    decision = ry2 * (2 * x + 1) * (2 * x + 1) + 4 * rx2 * (y - 1) * (y - 1) - 4 * rx2 * ry2;
+    while y >= 0 {
+        visit(x as isize, y as isize);
+        y -= 1;
+        step_y -= 2 * rx2;
+        if decision > 0 {
+            decision += 4 * (rx2 - step_y);
+        } else {
+            x += 1;
+            step_x += 2 * ry2;
+            decision += 4 * (rx2 - step_y + step_x);
+        }
+    }
+}
+
+/// How many degrees lie clockwise from `start_deg` to `end_deg`, wrapping past 360 and
+/// capped at a full turn.
+fn clockwise_sweep(start_deg: f64, end_deg: f64) -> f64 {
+    let sweep = end_deg - start_deg;
+    if sweep >= 360.0 {
+        360.0
+    } else {
+        sweep.rem_euclid(360.0)
+    }
+}
+
+/// Whether the offset (x, y) from a center lies in the clockwise sweep from `start_deg` to
+/// `end_deg`. The center itself always does.
+fn angle_range(start_deg: f64, end_deg: f64) -> impl Fn(isize, isize) -> bool {
+    let sweep = clockwise_sweep(start_deg, end_deg);
+    move |x, y| {
+        let angle = (y as f64).atan2(x as f64).to_degrees();
+        (x, y) == (0, 0) || sweep >= 360.0 || (angle - start_deg).rem_euclid(360.0) <= sweep
+    }
+}
+
+/// Composites `color` over the pixel at (x, y) with its alpha scaled by `coverage`, doing
+/// nothing outside the image.
+fn blend<I: Image + ?Sized>(image: &mut I, x: isize, y: isize, color: Color, coverage: f64) {
+    if x < 0 || y < 0 {
+        return;
+    }
+    let (x, y) = (x as usize, y as usize);
+    if let Some(background) = image.get(x, y) {
+        let alpha = (color.a() as f64 * clamp(coverage, 0.0, 1.0)).round() as u8;
+        image.set(x, y, color.with_alpha(alpha).over(background));
+    }
+}
+
+fn out_of_bounds<I: ReadImage + ?Sized>(image: &I, x: usize, y: usize) -> ImageError {
+    ImageError::OutOfBounds {
+        x,
+        y,
+        width: image.width(),
+        height: image.height(),
+    }
+}
+
+/// Checks that the `width`x`height` rectangle at (x, y) lies inside `image`, naming its
+/// bottom-right corner if not. Empty rectangles always fit.
+fn check_rect<I: ReadImage + ?Sized>(
+    image: &I,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+    // Saturating keeps corners past usize::MAX out of bounds instead of overflowing.
+    let (right, bottom) = (x.saturating_add(width - 1), y.saturating_add(height - 1));
+    if right >= image.width() || bottom >= image.height() {
+        return Err(out_of_bounds(image, right, bottom));
+    }
+    Ok(())
+}
+
+impl ReadImage for BmpFile {
+    fn width(&self) -> usize {
+        self.header.width
+    }
+    fn height(&self) -> usize {
+        self.header.height
+    }
+    fn get(&self, x: usize, y: usize) -> Option<Color> {
+        self.get_pixel(x, y)
+    }
+}
+
+impl Image for BmpFile {
+    /// Does nothing outside the clip rectangle, see [`BmpFile::set_clip_rect`].
+    fn set(&mut self, x: usize, y: usize, color: Color) {
+        let (columns, rows) = self.clip_ranges();
+        if columns.contains(&x) && rows.contains(&y) {
+            let _ = self.set_pixel(x, y, color);
+        }
+    }
+    /// Runs over the rows in parallel with the `rayon` feature.
+    fn update<F>(&mut self, f: F)
+    where
+        F: Fn(usize, usize, Color) -> Color + Sync,
+    {
+        let (columns, rows) = self.clip_ranges();
+        self.for_each_row_mut(|y, row| {
+            if !rows.contains(&y) {
+                return;
+            }
+            for (x, pixel) in row[columns.clone()].iter_mut().enumerate() {
+                let x = columns.start + x;
+                *pixel = f(x, y, *pixel);
+            }
+        });
+    }
+}
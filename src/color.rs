@@ -0,0 +1,352 @@
+//! The public [`Color`] type.
+
+use crate::{ImageError, Luma};
+use num::clamp;
+use std::ops::{Add, Mul, Not, Sub};
+use std::str::FromStr;
+
+/// An 8-bit-per-channel color with straight (not premultiplied) alpha, 255 being opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub(crate) r: u8,
+    pub(crate) g: u8,
+    pub(crate) b: u8,
+    /// Opacity; 255 for images without an alpha channel.
+    pub(crate) a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const RED: Color = Color::rgb(255, 0, 0);
+    pub const GREEN: Color = Color::rgb(0, 255, 0);
+    pub const BLUE: Color = Color::rgb(0, 0, 255);
+    pub const YELLOW: Color = Color::rgb(255, 255, 0);
+    pub const CYAN: Color = Color::rgb(0, 255, 255);
+    pub const MAGENTA: Color = Color::rgb(255, 0, 255);
+    pub const GRAY: Color = Color::rgb(128, 128, 128);
+    /// Fully transparent black.
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+
+    /// An opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+    /// A gray with red, green and blue all set to `value`.
+    pub const fn gray(value: u8) -> Color {
+        Color::rgb(value, value, value)
+    }
+    pub const fn r(&self) -> u8 {
+        self.r
+    }
+    pub const fn g(&self) -> u8 {
+        self.g
+    }
+    pub const fn b(&self) -> u8 {
+        self.b
+    }
+    pub const fn a(&self) -> u8 {
+        self.a
+    }
+    /// This color with its alpha replaced.
+    pub const fn with_alpha(&self, a: u8) -> Color {
+        Color { a, ..*self }
+    }
+    /// Parses a web-style `#rrggbb` or `#rrggbbaa` literal, in either case. The `#` is
+    /// optional and six digits give an opaque color.
+    pub fn from_hex(literal: &str) -> Result<Color, ImageError> {
+        let digits = literal.strip_prefix('#').unwrap_or(literal);
+        let bad_color = || ImageError::BadColor(literal.to_string());
+        if !matches!(digits.len(), 6 | 8) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(bad_color());
+        }
+        let channel = |index: usize| {
+            digits
+                .get(2 * index..2 * index + 2)
+                .map_or(Ok(255), |pair| u8::from_str_radix(pair, 16))
+                .map_err(|_| bad_color())
+        };
+        Ok(Color::rgba(
+            channel(0)?,
+            channel(1)?,
+            channel(2)?,
+            channel(3)?,
+        ))
+    }
+    /// The color as a lowercase `#rrggbb` literal, or `#rrggbbaa` when it is not opaque.
+    pub fn to_hex(&self) -> String {
+        if self.a == 255 {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    /// Hue in degrees (0 for grays), then saturation and value in 0..=1. Alpha is ignored.
+    pub fn to_hsv(&self) -> (f64, f64, f64) {
+        let [r, g, b] = [self.r, self.g, self.b].map(|channel| channel as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+        (hue, saturation, max)
+    }
+    /// The opaque color with hue `hue` in degrees, wrapping at 360, and `saturation` and
+    /// `value` in 0..=1, clamped to that range.
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Color {
+        let (saturation, value) = (clamp(saturation, 0.0, 1.0), clamp(value, 0.0, 1.0));
+        let chroma = value * saturation;
+        let sector = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as u8 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+        let channel = |c: f64| clamp(((c + m) * 255.0).round(), 0.0, 255.0) as u8;
+        Color::rgb(channel(r), channel(g), channel(b))
+    }
+    /// Hue in degrees (0 for grays), then saturation and lightness in 0..=1. Alpha is ignored.
+    pub fn to_hsl(&self) -> (f64, f64, f64) {
+        let (hue, saturation, value) = self.to_hsv();
+        let lightness = value * (1.0 - saturation / 2.0);
+        let shortest = lightness.min(1.0 - lightness);
+        let saturation = if shortest == 0.0 {
+            0.0
+        } else {
+            (value - lightness) / shortest
+        };
+        (hue, saturation, lightness)
+    }
+    /// The opaque color with hue `hue` in degrees, wrapping at 360, and `saturation` and
+    /// `lightness` in 0..=1, clamped to that range.
+    pub fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> Color {
+        let (saturation, lightness) = (clamp(saturation, 0.0, 1.0), clamp(lightness, 0.0, 1.0));
+        let value = lightness + saturation * lightness.min(1.0 - lightness);
+        let saturation = if value == 0.0 {
+            0.0
+        } else {
+            2.0 * (1.0 - lightness / value)
+        };
+        Color::from_hsv(hue, saturation, value)
+    }
+
+    /// Red, green and blue decoded from sRGB to linear light, each in 0..=1. Alpha is ignored.
+    pub fn to_linear(&self) -> (f64, f64, f64) {
+        (
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+        )
+    }
+    /// The opaque color encoding linear-light `r`, `g` and `b`, clamped to 0..=1, as sRGB.
+    pub fn from_linear(r: f64, g: f64, b: f64) -> Color {
+        Color::rgb(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+    /// CIELAB lightness (0..=100) and the a* and b* axes, relative to the D65 white point.
+    /// Alpha is ignored.
+    pub fn to_lab(&self) -> (f64, f64, f64) {
+        let (r, g, b) = self.to_linear();
+        let [x, y, z] = [
+            0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+            0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+            0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+        ];
+        let f = |t: f64| {
+            if t > LAB_EPSILON.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3.0 * LAB_EPSILON * LAB_EPSILON) + 4.0 / 29.0
+            }
+        };
+        let (fx, fy, fz) = (f(x / D65[0]), f(y / D65[1]), f(z / D65[2]));
+        (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+    /// The opaque color closest to CIELAB `l`, `a` and `b`, clipping each channel of colors
+    /// outside the sRGB gamut.
+    pub fn from_lab(l: f64, a: f64, b: f64) -> Color {
+        let fy = (l + 16.0) / 116.0;
+        let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+        let f_inverse = |t: f64| {
+            if t > LAB_EPSILON {
+                t.powi(3)
+            } else {
+                3.0 * LAB_EPSILON * LAB_EPSILON * (t - 4.0 / 29.0)
+            }
+        };
+        let (x, y, z) = (
+            D65[0] * f_inverse(fx),
+            D65[1] * f_inverse(fy),
+            D65[2] * f_inverse(fz),
+        );
+        Color::from_linear(
+            3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+            -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+            0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+        )
+    }
+
+    /// The color `t` of the way from `from` to `to`, with `t` clamped to 0..=1. All four
+    /// channels, alpha included, are interpolated and rounded to the nearest value.
+    pub fn lerp(from: Color, to: Color, t: f64) -> Color {
+        let t = clamp(t, 0.0, 1.0);
+        let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        Color::rgba(
+            mix(from.r, to.r),
+            mix(from.g, to.g),
+            mix(from.b, to.b),
+            mix(from.a, to.a),
+        )
+    }
+
+    /// This color composited over `background` with the "source over" rule, so an opaque
+    /// color replaces it and a transparent one leaves it unchanged.
+    pub fn over(&self, background: Color) -> Color {
+        let source_alpha = self.a as f64 / 255.0;
+        let background_alpha = background.a as f64 / 255.0 * (1.0 - source_alpha);
+        let alpha = source_alpha + background_alpha;
+        if alpha == 0.0 {
+            return Color::TRANSPARENT;
+        }
+        let mix = |source: u8, background: u8| {
+            ((source as f64 * source_alpha + background as f64 * background_alpha) / alpha).round()
+                as u8
+        };
+        Color::rgba(
+            mix(self.r, background.r),
+            mix(self.g, background.g),
+            mix(self.b, background.b),
+            (alpha * 255.0).round() as u8,
+        )
+    }
+
+    /// Whether red, green and blue each differ from `other` by at most `tolerance`.
+    pub(crate) fn within_tolerance(&self, other: &Color, tolerance: u8) -> bool {
+        self.r.abs_diff(other.r) <= tolerance
+            && self.g.abs_diff(other.g) <= tolerance
+            && self.b.abs_diff(other.b) <= tolerance
+    }
+    pub(crate) fn luminance(&self) -> u8 {
+        self.luma(Luma::Rec601)
+    }
+    pub(crate) fn luma(&self, luma: Luma) -> u8 {
+        let [red, green, blue] = match luma {
+            Luma::Rec601 => [0.299, 0.587, 0.114],
+            Luma::Rec709 => [0.2126, 0.7152, 0.0722],
+        };
+        (red * self.r as f64 + green * self.g as f64 + blue * self.b as f64).round() as u8
+    }
+    /// Index of the entry closest to this color by squared RGB distance, 0 for an empty palette.
+    pub(crate) fn nearest_index(&self, palette: &[Color]) -> usize {
+        let distance = |other: &Color| {
+            [
+                self.r.abs_diff(other.r),
+                self.g.abs_diff(other.g),
+                self.b.abs_diff(other.b),
+            ]
+            .map(|difference| difference as u32 * difference as u32)
+            .iter()
+            .sum::<u32>()
+        };
+        palette
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| distance(entry))
+            .map_or(0, |(index, _)| index)
+    }
+}
+
+/// The D65 reference white in CIE XYZ, with Y normalized to 1.
+const D65: [f64; 3] = [0.950_47, 1.0, 1.088_83];
+/// Where the CIELAB companding switches from a cube root to a straight line.
+const LAB_EPSILON: f64 = 6.0 / 29.0;
+
+/// Decodes one sRGB channel to linear light in 0..=1.
+pub(crate) fn srgb_to_linear(channel: u8) -> f64 {
+    let value = channel as f64 / 255.0;
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes linear light, clamped to 0..=1, as an sRGB channel.
+pub(crate) fn linear_to_srgb(value: f64) -> u8 {
+    let value = clamp(value, 0.0, 1.0);
+    let encoded = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Parses hex literals as [`Color::from_hex`] does.
+impl FromStr for Color {
+    type Err = ImageError;
+    fn from_str(literal: &str) -> Result<Color, ImageError> {
+        Color::from_hex(literal)
+    }
+}
+
+/// Adds red, green and blue channel by channel, saturating at 255 and keeping the left
+/// operand's alpha.
+impl Add for Color {
+    type Output = Color;
+    fn add(self, rhs: Color) -> Self {
+        Color::rgba(
+            self.r.saturating_add(rhs.r),
+            self.g.saturating_add(rhs.g),
+            self.b.saturating_add(rhs.b),
+            self.a,
+        )
+    }
+}
+
+/// Subtracts red, green and blue channel by channel, saturating at 0 and keeping the left
+/// operand's alpha.
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, rhs: Color) -> Self {
+        Color::rgba(
+            self.r.saturating_sub(rhs.r),
+            self.g.saturating_sub(rhs.g),
+            self.b.saturating_sub(rhs.b),
+            self.a,
+        )
+    }
+}
+
+/// Scales red, green and blue, leaving alpha alone.
+impl Mul<f64> for Color {
+    type Output = Color;
+    fn mul(self, rhs: f64) -> Self {
+        let scale = |channel: u8| clamp(channel as f64 * rhs, 0.0, 255.0) as u8;
+        Color::rgba(scale(self.r), scale(self.g), scale(self.b), self.a)
+    }
+}
+
+/// The negative: each of red, green and blue becomes 255 minus itself, and alpha is kept.
+impl Not for Color {
+    type Output = Color;
+    fn not(self) -> Self {
+        Color::rgba(!self.r, !self.g, !self.b, self.a)
+    }
+}
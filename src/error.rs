@@ -0,0 +1,160 @@
+use std::fmt::{Display, Formatter};
+
+/// Everything that can go wrong while decoding, editing or encoding an image.
+#[derive(Debug)]
+pub enum ImageError {
+    /// Reading or writing the underlying file or stream failed.
+    Io(std::io::Error),
+    /// The data ends before the headers do.
+    TruncatedHeader,
+    /// The file does not start with the `BM` signature.
+    BadMagic([u8; 2]),
+    /// The DIB header size matches none of the known header layouts.
+    UnsupportedHeader(u32),
+    /// The bit depth is not one the decoder understands.
+    UnsupportedBitDepth(u16),
+    /// The compression mode is not implemented for this bit depth.
+    UnsupportedCompression {
+        compression: u32,
+        bits_per_pixel: u16,
+    },
+    /// The data ends before the uncompressed pixel array does.
+    TruncatedPixels { expected: usize, found: usize },
+    /// The pixel array offset points inside the headers or past the end of the data.
+    OffsetOutOfBounds { offset: usize, len: usize },
+    /// The dimensions would need more memory than allowed, or overflow the format's size fields.
+    TooLarge {
+        width: usize,
+        height: usize,
+        limit: usize,
+    },
+    /// Data in another image format breaks that format's rules or uses a feature the
+    /// decoder does not handle.
+    Malformed {
+        format: &'static str,
+        reason: String,
+    },
+    /// Strict decoding found the file inconsistent, for every listed reason.
+    Invalid(Vec<String>),
+    /// A channel plane does not hold exactly one byte per pixel.
+    ChannelLength { expected: usize, found: usize },
+    /// A raw pixel buffer does not hold exactly `width * height` pixels.
+    BufferLength { expected: usize, found: usize },
+    /// A color literal is not of the form `#rrggbb` or `#rrggbbaa`.
+    BadColor(String),
+    /// A convolution kernel is empty or does not hold `size * size` weights.
+    KernelSize { size: usize, len: usize },
+    /// The image is wider or taller than an encoder's format can describe.
+    DimensionsExceedFormat {
+        format: &'static str,
+        width: usize,
+        height: usize,
+        max: usize,
+    },
+    /// A coordinate lies outside the image.
+    OutOfBounds {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ImageError::Io(error) => write!(f, "io error: {}", error),
+            ImageError::TruncatedHeader => write!(f, "data ends inside the bitmap header"),
+            ImageError::BadMagic(magic) => write!(f, "bad signature {:?}, expected \"BM\"", magic),
+            ImageError::UnsupportedHeader(size) => {
+                write!(f, "unsupported {} byte DIB header", size)
+            }
+            ImageError::UnsupportedBitDepth(bits) => {
+                write!(f, "unsupported bit depth {}", bits)
+            }
+            ImageError::UnsupportedCompression {
+                compression,
+                bits_per_pixel,
+            } => write!(
+                f,
+                "unsupported compression {} for {} bits per pixel",
+                compression, bits_per_pixel
+            ),
+            ImageError::TruncatedPixels { expected, found } => write!(
+                f,
+                "pixel array needs {} bytes but only {} are present",
+                expected, found
+            ),
+            ImageError::OffsetOutOfBounds { offset, len } => write!(
+                f,
+                "pixel offset {} is outside the {} bytes available",
+                offset, len
+            ),
+            ImageError::TooLarge {
+                width,
+                height,
+                limit,
+            } => write!(
+                f,
+                "{}x{} image exceeds the {} byte limit",
+                width, height, limit
+            ),
+            ImageError::Malformed { format, reason } => {
+                write!(f, "malformed {} data: {}", format, reason)
+            }
+            ImageError::Invalid(violations) => {
+                write!(f, "invalid bitmap: {}", violations.join("; "))
+            }
+            ImageError::ChannelLength { expected, found } => {
+                write!(f, "expected {} bytes per channel, got {}", expected, found)
+            }
+            ImageError::BufferLength { expected, found } => {
+                write!(f, "raw buffer should be {} bytes, got {}", expected, found)
+            }
+            ImageError::BadColor(literal) => write!(f, "invalid color literal {:?}", literal),
+            ImageError::KernelSize { size, len } => write!(
+                f,
+                "a {}x{} kernel needs {} weights, got {}",
+                size,
+                size,
+                size.saturating_mul(*size),
+                len
+            ),
+            ImageError::DimensionsExceedFormat {
+                format,
+                width,
+                height,
+                max,
+            } => write!(
+                f,
+                "{} cannot store a {}x{} image, whose sides are limited to {} pixels",
+                format, width, height, max
+            ),
+            ImageError::OutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "({}, {}) is outside the {}x{} image",
+                x, y, width, height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(error: std::io::Error) -> ImageError {
+        ImageError::Io(error)
+    }
+}
@@ -0,0 +1,167 @@
+//! What the fill operations paint shapes with: a single color, a gradient or a tiled image.
+//!
+//! Gradients are positioned in image coordinates with whole numbers on pixel centers, so a
+//! linear gradient from (0, 0) to (width - 1, 0) gives the first and last columns exactly
+//! the first and last stop colors.
+
+use crate::shape::Point;
+use crate::{Blending, BmpFile, BorderMode, Color};
+
+/// How a filled shape is colored. Every fill operation takes anything convertible into
+/// this, so a plain [`Color`] or a `&Gradient` can be passed directly.
+///
+/// ```no_run
+/// use image_manip::{BmpFile, BorderMode, Color, FillStyle, Image};
+///
+/// let texture = BmpFile::open("brick.bmp")?;
+/// let mut image = BmpFile::new(200, 100, Color::WHITE);
+/// image.fill_circle(50, 50, 40, FillStyle::Pattern(&texture, BorderMode::Wrap));
+/// # Ok::<(), image_manip::ImageError>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum FillStyle<'a> {
+    Solid(Color),
+    /// Each pixel takes the gradient's color at its center.
+    Gradient(&'a Gradient),
+    /// Each pixel takes the pattern pixel at the same position, with the pattern's top-left
+    /// corner on the image's. The [`BorderMode`] says what lies past the pattern's edges:
+    /// [`BorderMode::Wrap`] tiles it across the whole shape.
+    Pattern(&'a BmpFile, BorderMode),
+}
+
+impl FillStyle<'_> {
+    /// The color this style paints pixel (x, y) with.
+    pub(crate) fn color_at(&self, x: usize, y: usize) -> Color {
+        match self {
+            FillStyle::Solid(color) => *color,
+            FillStyle::Gradient(gradient) => gradient.color_at(x as f64, y as f64),
+            FillStyle::Pattern(pattern, _) if pattern.width() == 0 || pattern.height() == 0 => {
+                Color::TRANSPARENT
+            }
+            FillStyle::Pattern(pattern, tile_mode) => {
+                pattern.pixel_at(x as isize, y as isize, *tile_mode)
+            }
+        }
+    }
+}
+
+impl From<Color> for FillStyle<'_> {
+    fn from(color: Color) -> Self {
+        FillStyle::Solid(color)
+    }
+}
+
+impl<'a> From<&'a Gradient> for FillStyle<'a> {
+    fn from(gradient: &'a Gradient) -> Self {
+        FillStyle::Gradient(gradient)
+    }
+}
+
+/// A color ramp defined by stops along a line or outwards from a point. Past the first and
+/// last stops the end colors carry on unchanged.
+///
+/// ```
+/// use image_manip::{Blending, Color, Gradient};
+///
+/// let sky = Gradient::linear((0.0, 0.0), (0.0, 99.0))
+///     .stop(0.0, Color::rgb(40, 80, 200))
+///     .stop(1.0, Color::WHITE)
+///     .blending(Blending::Linear);
+/// assert_eq!(sky.color_at(0.0, 99.0), Color::WHITE);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    shape: GradientShape,
+    stops: Vec<(f64, Color)>,
+    blending: Blending,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GradientShape {
+    Linear { start: Point, end: Point },
+    Radial { center: Point, radius: f64 },
+}
+
+impl Gradient {
+    /// A gradient running from `start` (offset 0) to `end` (offset 1), constant along lines
+    /// perpendicular to it.
+    pub fn linear(start: (f64, f64), end: (f64, f64)) -> Gradient {
+        Gradient::new(GradientShape::Linear { start, end })
+    }
+    /// A gradient running outwards from `center` (offset 0) to the circle of `radius`
+    /// around it (offset 1).
+    pub fn radial(center: (f64, f64), radius: f64) -> Gradient {
+        Gradient::new(GradientShape::Radial { center, radius })
+    }
+    fn new(shape: GradientShape) -> Gradient {
+        Gradient {
+            shape,
+            stops: Vec::new(),
+            blending: Blending::Srgb,
+        }
+    }
+    /// Adds a color stop at `offset`, normally between 0 and 1. Stops may be added in any
+    /// order; two stops at the same offset make a hard edge, the one added first on the
+    /// near side.
+    pub fn stop(mut self, offset: f64, color: Color) -> Gradient {
+        let index = self
+            .stops
+            .partition_point(|&(existing, _)| existing <= offset);
+        self.stops.insert(index, (offset, color));
+        self
+    }
+    /// Which values are interpolated between stops, [`Blending::Srgb`] by default.
+    /// [`Blending::Linear`] avoids the dark band sRGB blending gives between bright colors.
+    pub fn blending(mut self, blending: Blending) -> Gradient {
+        self.blending = blending;
+        self
+    }
+    /// The color at (x, y), or transparent if the gradient has no stops.
+    pub fn color_at(&self, x: f64, y: f64) -> Color {
+        let offset = match self.shape {
+            GradientShape::Linear { start, end } => {
+                let axis = (end.0 - start.0, end.1 - start.1);
+                let length_squared = axis.0 * axis.0 + axis.1 * axis.1;
+                if length_squared == 0.0 {
+                    1.0
+                } else {
+                    ((x - start.0) * axis.0 + (y - start.1) * axis.1) / length_squared
+                }
+            }
+            GradientShape::Radial { center, radius } => {
+                let distance = (x - center.0).hypot(y - center.1);
+                if radius > 0.0 {
+                    distance / radius
+                } else {
+                    1.0
+                }
+            }
+        };
+        self.color_at_offset(offset)
+    }
+    fn color_at_offset(&self, offset: f64) -> Color {
+        let Some(&(_, first)) = self.stops.first() else {
+            return Color::TRANSPARENT;
+        };
+        let after = self
+            .stops
+            .partition_point(|&(stop_offset, _)| stop_offset <= offset);
+        if after == 0 {
+            return first;
+        }
+        let Some(&(to_offset, to)) = self.stops.get(after) else {
+            return self.stops[self.stops.len() - 1].1;
+        };
+        let (from_offset, from) = self.stops[after - 1];
+        let t = (offset - from_offset) / (to_offset - from_offset);
+        match self.blending {
+            Blending::Srgb => Color::lerp(from, to, t),
+            Blending::Linear => {
+                let ((r0, g0, b0), (r1, g1, b1)) = (from.to_linear(), to.to_linear());
+                let mix = |from: f64, to: f64| from + (to - from) * t;
+                Color::from_linear(mix(r0, r1), mix(g0, g1), mix(b0, b1))
+                    .with_alpha(Color::lerp(from, to, t).a())
+            }
+        }
+    }
+}
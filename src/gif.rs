@@ -0,0 +1,360 @@
+//! GIF import and export for single images.
+//!
+//! After a six-byte signature, the logical screen descriptor gives the canvas size and
+//! whether a global color table follows. Extension blocks may come next, then an image
+//! descriptor with an optional local color table and the palette indices, LZW-compressed
+//! with variable-width codes packed least significant bit first and split into sub-blocks of
+//! up to 255 bytes.
+
+use crate::{
+    median_cut, BmpFile, ByteOrder, Color, Header, ImageError, LittleEndian,
+    DEFAULT_MAX_PIXEL_BYTES,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+const EXTENSION: u8 = 0x21;
+const IMAGE: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+const GRAPHIC_CONTROL: u8 = 0xF9;
+const COLOR_TABLE: u8 = 0x80;
+const INTERLACED: u8 = 0x40;
+const MAX_CODES: usize = 4096;
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "GIF",
+        reason: reason.to_string(),
+    }
+}
+
+/// Reads the color table of `2 << (flags & 7)` RGB entries starting at `*position`.
+fn read_color_table(
+    bytes: &[u8],
+    position: &mut usize,
+    flags: u8,
+) -> Result<Vec<Color>, ImageError> {
+    let len = 3 * (2 << (flags & 0x07));
+    let table = bytes
+        .get(*position..*position + len)
+        .ok_or_else(|| malformed("data ends inside a color table"))?;
+    *position += len;
+    Ok(table
+        .chunks_exact(3)
+        .map(|rgb| Color::rgb(rgb[0], rgb[1], rgb[2]))
+        .collect())
+}
+
+/// Concatenates the sub-blocks starting at `*position`, leaving it after the terminator.
+fn read_sub_blocks(bytes: &[u8], position: &mut usize) -> Result<Vec<u8>, ImageError> {
+    let mut data = Vec::new();
+    loop {
+        let len = *bytes
+            .get(*position)
+            .ok_or_else(|| malformed("data ends inside a block"))? as usize;
+        *position += 1;
+        if len == 0 {
+            return Ok(data);
+        }
+        let block = bytes
+            .get(*position..*position + len)
+            .ok_or_else(|| malformed("data ends inside a block"))?;
+        data.extend_from_slice(block);
+        *position += len;
+    }
+}
+
+/// Decompresses the first `count` palette indices from `data`.
+fn lzw_decode(data: &[u8], min_code_size: u8, count: usize) -> Result<Vec<u8>, ImageError> {
+    if !(2..=11).contains(&min_code_size) {
+        return Err(malformed("LZW code size must be between 2 and 11"));
+    }
+    let clear = 1 << min_code_size;
+    let end = clear + 1;
+    // Each code stands for the string of its prefix code followed by one more index.
+    let mut prefix = [0u16; MAX_CODES];
+    let mut suffix = [0u8; MAX_CODES];
+    let mut first = [0u8; MAX_CODES];
+    let mut lengths = [0u16; MAX_CODES];
+    for code in 0..clear {
+        suffix[code] = code as u8;
+        first[code] = code as u8;
+        lengths[code] = 1;
+    }
+    let mut out = Vec::with_capacity(count);
+    let (mut next, mut width) = (end + 1, min_code_size as u32 + 1);
+    let mut previous: Option<usize> = None;
+    let (mut bits, mut bit_count, mut position) = (0u32, 0u32, 0);
+    while out.len() < count {
+        while bit_count < width {
+            let Some(&byte) = data.get(position) else {
+                return Err(malformed("pixel data ends early"));
+            };
+            bits |= (byte as u32) << bit_count;
+            bit_count += 8;
+            position += 1;
+        }
+        let code = (bits & ((1 << width) - 1)) as usize;
+        bits >>= width;
+        bit_count -= width;
+        if code == clear {
+            (next, width, previous) = (end + 1, min_code_size as u32 + 1, None);
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        if let Some(previous) = previous {
+            // A code one past the table repeats the previous string plus its first index.
+            let head = match code {
+                code if code < next => first[code],
+                code if code == next => first[previous],
+                _ => return Err(malformed("LZW code is not in the table yet")),
+            };
+            if next < MAX_CODES {
+                prefix[next] = previous as u16;
+                suffix[next] = head;
+                first[next] = first[previous];
+                lengths[next] = lengths[previous] + 1;
+                next += 1;
+                if next == 1 << width && width < 12 {
+                    width += 1;
+                }
+            }
+        } else if code >= clear {
+            return Err(malformed("LZW code is not in the table yet"));
+        }
+        let start = out.len();
+        out.resize(start + lengths[code] as usize, 0);
+        let mut string = code;
+        for index in out[start..].iter_mut().rev() {
+            *index = suffix[string];
+            string = prefix[string] as usize;
+        }
+        previous = Some(code);
+    }
+    if out.len() < count {
+        return Err(malformed("pixel data ends early"));
+    }
+    out.truncate(count);
+    Ok(out)
+}
+
+/// Compresses `indices`, each below `1 << min_code_size`, clearing the table whenever it
+/// fills up.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let (mut bits, mut bit_count) = (0u32, 0u32);
+    let mut emit = |out: &mut Vec<u8>, code: u16, width: u32| {
+        bits |= (code as u32) << bit_count;
+        bit_count += width;
+        while bit_count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let (mut next, mut width) = (end + 1, min_code_size as u32 + 1);
+    emit(&mut out, clear, width);
+    if let Some((&head, rest)) = indices.split_first() {
+        let mut current = head as u16;
+        for &index in rest {
+            if let Some(&code) = table.get(&(current, index)) {
+                current = code;
+                continue;
+            }
+            emit(&mut out, current, width);
+            if next as usize == MAX_CODES {
+                emit(&mut out, clear, width);
+                table.clear();
+                (next, width) = (end + 1, min_code_size as u32 + 1);
+            } else {
+                table.insert((current, index), next);
+                next += 1;
+                if next as u32 > 1 << width {
+                    width += 1;
+                }
+            }
+            current = index as u16;
+        }
+        emit(&mut out, current, width);
+        // The decoder adds an entry after that last code, which may widen the codes.
+        if next as u32 == 1 << width && width < 12 {
+            width += 1;
+        }
+    }
+    emit(&mut out, end, width);
+    if bit_count > 0 {
+        out.push(bits as u8);
+    }
+    out
+}
+
+/// Rows of an interlaced image in the order they are stored.
+fn interlaced_rows(height: usize) -> impl Iterator<Item = usize> {
+    [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .into_iter()
+        .flat_map(move |(start, step)| (start..height).step_by(step))
+}
+
+impl BmpFile {
+    /// Opens and decodes the GIF file at `path`.
+    pub fn open_gif<P: AsRef<Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_gif_bytes(&std::fs::read(path)?)
+    }
+    /// Encodes the image as a GIF and writes it to `path`, replacing any existing file.
+    pub fn save_gif<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        std::fs::write(path, self.to_gif_bytes()?)?;
+        Ok(())
+    }
+    /// Decodes the first image of a GIF held in memory onto a canvas the size of its logical
+    /// screen. Canvas pixels the image does not cover, and pixels using the transparent index
+    /// of a graphic control extension, are left fully transparent.
+    pub fn from_gif_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        if !(bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")) {
+            return Err(malformed("missing GIF87a or GIF89a signature"));
+        }
+        let screen = bytes
+            .get(6..13)
+            .ok_or_else(|| malformed("data ends inside the screen descriptor"))?;
+        let width = LittleEndian::read_u16(&screen[0..2]) as usize;
+        let height = LittleEndian::read_u16(&screen[2..4]) as usize;
+        Header::for_dimensions(width, height, 32).check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let mut position = 13;
+        let global = if screen[4] & COLOR_TABLE != 0 {
+            read_color_table(bytes, &mut position, screen[4])?
+        } else {
+            Vec::new()
+        };
+        let mut transparent = None;
+        loop {
+            match bytes.get(position) {
+                Some(&EXTENSION) => {
+                    let label = bytes.get(position + 1);
+                    position += 2;
+                    let data = read_sub_blocks(bytes, &mut position)?;
+                    // Packed flags, delay time, then the transparent index.
+                    if label == Some(&GRAPHIC_CONTROL) && data.len() >= 4 && data[0] & 1 != 0 {
+                        transparent = Some(data[3]);
+                    }
+                }
+                Some(&IMAGE) => break,
+                Some(&TRAILER) | None => return Err(malformed("the file holds no image")),
+                Some(_) => return Err(malformed("unknown block")),
+            }
+        }
+        let descriptor = bytes
+            .get(position + 1..position + 10)
+            .ok_or_else(|| malformed("data ends inside the image descriptor"))?;
+        let left = LittleEndian::read_u16(&descriptor[0..2]) as usize;
+        let top = LittleEndian::read_u16(&descriptor[2..4]) as usize;
+        let frame_width = LittleEndian::read_u16(&descriptor[4..6]) as usize;
+        let frame_height = LittleEndian::read_u16(&descriptor[6..8]) as usize;
+        let flags = descriptor[8];
+        position += 10;
+        let palette = if flags & COLOR_TABLE != 0 {
+            read_color_table(bytes, &mut position, flags)?
+        } else {
+            global
+        };
+        let min_code_size = *bytes
+            .get(position)
+            .ok_or_else(|| malformed("data ends before the pixel data"))?;
+        position += 1;
+        let data = read_sub_blocks(bytes, &mut position)?;
+        let indices = lzw_decode(&data, min_code_size, frame_width * frame_height)?;
+        let rows: Vec<usize> = if flags & INTERLACED != 0 {
+            interlaced_rows(frame_height).collect()
+        } else {
+            (0..frame_height).collect()
+        };
+        let mut pixels = vec![Color::TRANSPARENT; width * height];
+        for (row, &y) in indices.chunks_exact(frame_width.max(1)).zip(&rows) {
+            let y = top + y;
+            for (x, &index) in row.iter().enumerate() {
+                let x = left + x;
+                if x >= width || y >= height || transparent == Some(index) {
+                    continue;
+                }
+                // Indices past the end of the table show as black.
+                let color = palette.get(index as usize).copied().unwrap_or(Color::BLACK);
+                pixels[y * width + x] = color;
+            }
+        }
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+    /// The image encoded as a GIF. The palette is built by median cut from the opaque pixels,
+    /// so images with up to 256 colors keep them exactly. Pixels with alpha below 128 are
+    /// written as a transparent index. Fails with [`ImageError::DimensionsExceedFormat`] if
+    /// either side is over 65535 pixels.
+    pub fn to_gif_bytes(&self) -> Result<Vec<u8>, ImageError> {
+        let (width, height) = (self.header.width, self.header.height);
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(ImageError::DimensionsExceedFormat {
+                format: "GIF",
+                width,
+                height,
+                max: u16::MAX as usize,
+            });
+        }
+        let is_transparent = |pixel: &Color| pixel.a < 128;
+        let has_transparency = self.pixels.iter().any(is_transparent);
+        let mut histogram: HashMap<Color, u32> = HashMap::new();
+        for pixel in self.pixels.iter().filter(|pixel| !is_transparent(pixel)) {
+            *histogram.entry(pixel.with_alpha(255)).or_default() += 1;
+        }
+        let palette = median_cut(&histogram, 256 - has_transparency as usize);
+        let transparent_index = palette.len() as u8;
+        let table_len = (palette.len() + has_transparency as usize)
+            .max(2)
+            .next_power_of_two();
+        let table_bits = table_len.trailing_zeros() as u8;
+        let mut nearest: HashMap<Color, u8> = HashMap::new();
+        let indices: Vec<u8> = self
+            .pixels
+            .iter()
+            .map(|pixel| {
+                if is_transparent(pixel) {
+                    return transparent_index;
+                }
+                let color = pixel.with_alpha(255);
+                *nearest
+                    .entry(color)
+                    .or_insert_with(|| color.nearest_index(&palette) as u8)
+            })
+            .collect();
+        let mut out = b"GIF89a".to_vec();
+        let write_u16 = |out: &mut Vec<u8>, value: usize| {
+            out.extend_from_slice(&(value as u16).to_le_bytes());
+        };
+        write_u16(&mut out, width);
+        write_u16(&mut out, height);
+        // Global table flag, color resolution and table size, then the background index and
+        // pixel aspect ratio.
+        out.extend_from_slice(&[COLOR_TABLE | (table_bits - 1) << 4 | (table_bits - 1), 0, 0]);
+        for color in &palette {
+            out.extend_from_slice(&[color.r(), color.g(), color.b()]);
+        }
+        out.resize(out.len() + 3 * (table_len - palette.len()), 0);
+        if has_transparency {
+            out.extend_from_slice(&[EXTENSION, GRAPHIC_CONTROL, 4, 1, 0, 0, transparent_index, 0]);
+        }
+        out.push(IMAGE);
+        write_u16(&mut out, 0);
+        write_u16(&mut out, 0);
+        write_u16(&mut out, width);
+        write_u16(&mut out, height);
+        out.push(0);
+        let min_code_size = table_bits.max(2);
+        out.push(min_code_size);
+        for block in lzw_encode(&indices, min_code_size).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.extend_from_slice(&[0, TRAILER]);
+        Ok(out)
+    }
+}
@@ -0,0 +1,75 @@
+//! Windows icon export.
+//!
+//! An ICO file starts with a six-byte directory header and a 16-byte entry per image giving
+//! its size and where its data lives. Each image here is a 32-bit BGRA DIB: a
+//! BITMAPINFOHEADER whose height counts both the color rows and the rows of the 1-bit
+//! transparency mask that follows them.
+
+use crate::{
+    write_pixels, BmpFile, ByteOrder, Color, Header, ImageError, LittleEndian, ResizeMode,
+};
+use std::path::Path;
+
+const ICON: u16 = 1;
+
+impl BmpFile {
+    /// Encodes the image as an icon with one entry per size in `sizes` and writes it to
+    /// `path`, replacing any existing file.
+    pub fn save_ico<P: AsRef<Path>>(&self, path: P, sizes: &[usize]) -> Result<(), ImageError> {
+        std::fs::write(path, self.to_ico_bytes(sizes)?)?;
+        Ok(())
+    }
+    /// The image encoded as an icon holding a square 32-bit copy for each size in `sizes`,
+    /// which must each be between 1 and 256. Non-square images are stretched to fit.
+    pub fn to_ico_bytes(&self, sizes: &[usize]) -> Result<Vec<u8>, ImageError> {
+        if sizes.is_empty() || sizes.iter().any(|size| !(1..=256).contains(size)) {
+            return Err(ImageError::Malformed {
+                format: "ICO",
+                reason: "icon sizes must be between 1 and 256".to_string(),
+            });
+        }
+        let images: Vec<Vec<u8>> = sizes
+            .iter()
+            .map(|&size| {
+                let icon = self.resize_with(size, size, ResizeMode::Area);
+                icon_image(&icon.pixels, size)
+            })
+            .collect();
+        let mut out = vec![0; 6 + 16 * images.len()];
+        LittleEndian::write_u16(&mut out[2..4], ICON);
+        LittleEndian::write_u16(&mut out[4..6], images.len() as u16);
+        for (i, (image, &size)) in images.iter().zip(sizes).enumerate() {
+            let offset = out.len();
+            let entry = &mut out[6 + 16 * i..6 + 16 * (i + 1)];
+            // A size byte of zero stands for 256.
+            entry[0] = size as u8;
+            entry[1] = size as u8;
+            LittleEndian::write_u16(&mut entry[4..6], 1);
+            LittleEndian::write_u16(&mut entry[6..8], 32);
+            LittleEndian::write_u32(&mut entry[8..12], image.len() as u32);
+            LittleEndian::write_u32(&mut entry[12..16], offset as u32);
+            out.extend_from_slice(image);
+        }
+        Ok(out)
+    }
+}
+
+/// The DIB for one `size`x`size` icon: the info header, the bottom-up BGRA rows, then the
+/// mask rows, where a set bit marks a fully transparent pixel.
+fn icon_image(pixels: &[Color], size: usize) -> Vec<u8> {
+    let header = Header::for_dimensions(size, size, 32);
+    let mut out = Vec::from(&header).split_off(14);
+    LittleEndian::write_i32(&mut out[8..12], 2 * size as i32);
+    write_pixels(pixels, &header, &mut out);
+    let mask_stride = size.div_ceil(32) * 4;
+    for row in pixels.chunks_exact(size).rev() {
+        let row_start = out.len();
+        out.resize(row_start + mask_stride, 0);
+        for (x, pixel) in row.iter().enumerate() {
+            if pixel.a == 0 {
+                out[row_start + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,37 @@
+//! Conversions to and from the buffers of the `image` crate, enabled by the `image` feature.
+
+use crate::{BmpFile, Color, Header, ImageError, DEFAULT_MAX_PIXEL_BYTES};
+use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+/// Drops the alpha channel.
+impl From<BmpFile> for RgbImage {
+    fn from(bmp: BmpFile) -> RgbImage {
+        RgbImage::from_fn(bmp.header.width as u32, bmp.header.height as u32, |x, y| {
+            let pixel = bmp.pixels[bmp.index(x as usize, y as usize)];
+            Rgb([pixel.r, pixel.g, pixel.b])
+        })
+    }
+}
+impl From<BmpFile> for RgbaImage {
+    fn from(bmp: BmpFile) -> RgbaImage {
+        RgbaImage::from_fn(bmp.header.width as u32, bmp.header.height as u32, |x, y| {
+            let pixel = bmp.pixels[bmp.index(x as usize, y as usize)];
+            Rgba([pixel.r, pixel.g, pixel.b, pixel.a])
+        })
+    }
+}
+/// Converts through 8-bit RGBA, giving a 32-bit bitmap if any pixel is translucent and a
+/// 24-bit one otherwise. Fails with [`ImageError::TooLarge`] past the default size limit.
+impl TryFrom<DynamicImage> for BmpFile {
+    type Error = ImageError;
+    fn try_from(image: DynamicImage) -> Result<BmpFile, ImageError> {
+        let rgba = image.into_rgba8();
+        let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+        Header::for_dimensions(width, height, 32).check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let pixels = rgba
+            .pixels()
+            .map(|&Rgba([r, g, b, a])| Color { b, g, r, a })
+            .collect();
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+}
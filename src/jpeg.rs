@@ -0,0 +1,499 @@
+//! Baseline JPEG decoding, enabled by the `jpeg` feature.
+//!
+//! Sequential Huffman-coded files with 8-bit samples are supported, in grayscale or
+//! three-component color with any chroma subsampling and restart intervals. Progressive,
+//! lossless and arithmetic-coded files are rejected. Subsampled components are upsampled by
+//! repeating samples.
+
+use crate::{BmpFile, Color, Header, ImageError, DEFAULT_MAX_PIXEL_BYTES};
+use std::path::Path;
+
+/// Position in an 8x8 block, in row-major order, of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "JPEG",
+        reason: reason.to_string(),
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols
+/// in code order.
+#[derive(Default, Clone)]
+struct Huffman {
+    counts: [u8; 16],
+    symbols: Vec<u8>,
+}
+impl Huffman {
+    fn decode(&self, scan: &mut Scan) -> Result<u8, ImageError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts {
+            code |= scan.bit() as i32;
+            let count = count as i32;
+            if code - first < count {
+                return self
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied()
+                    .ok_or_else(|| malformed("invalid Huffman code"));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(malformed("invalid Huffman code"))
+    }
+}
+
+/// Reads entropy-coded data most significant bit first, dropping the zero byte stuffed
+/// after every `0xFF`. At a marker it stops advancing and reads zero bits.
+struct Scan<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    count: u32,
+}
+impl Scan<'_> {
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            self.byte = match self.data.get(self.position..self.position + 2) {
+                Some([0xFF, 0x00]) => {
+                    self.position += 2;
+                    0xFF
+                }
+                Some([0xFF, _]) => 0,
+                _ => match self.data.get(self.position) {
+                    Some(&byte) => {
+                        self.position += 1;
+                        byte
+                    }
+                    None => 0,
+                },
+            };
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.byte >> self.count) as u32 & 1
+    }
+    fn bits(&mut self, n: u8) -> u32 {
+        (0..n).fold(0, |value, _| value << 1 | self.bit())
+    }
+    /// Reads an `n`-bit magnitude, whose leading zero bit marks a negative value.
+    fn extend(&mut self, n: u8) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        let value = self.bits(n) as i32;
+        if value < 1 << (n - 1) {
+            value - (1 << n) + 1
+        } else {
+            value
+        }
+    }
+    /// Skips to just past the next restart marker, discarding any bits left over.
+    fn restart(&mut self) {
+        self.count = 0;
+        while let Some(window) = self.data.get(self.position..self.position + 2) {
+            self.position += 1;
+            if window[0] == 0xFF && (0xD0..=0xD7).contains(&window[1]) {
+                self.position += 1;
+                break;
+            }
+        }
+    }
+    /// Offset of the first marker other than a restart at or after the current position.
+    fn end(&self) -> usize {
+        (self.position..self.data.len().saturating_sub(1))
+            .find(|&i| {
+                self.data[i] == 0xFF && !matches!(self.data[i + 1], 0x00 | 0xD0..=0xD7 | 0xFF)
+            })
+            .unwrap_or(self.data.len())
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quantization: usize,
+    dc_table: usize,
+    ac_table: usize,
+    prediction: i32,
+    /// Decoded samples, padded out to whole MCUs.
+    plane: Vec<u8>,
+    stride: usize,
+}
+
+/// Reads one block's coefficients, dequantized and in row-major order, updating the
+/// component's DC prediction.
+fn decode_block(
+    scan: &mut Scan,
+    component: &mut Component,
+    dc: &Huffman,
+    ac: &Huffman,
+    quantization: &[u16; 64],
+) -> Result<[f32; 64], ImageError> {
+    let mut coefficients = [0.0; 64];
+    let size = dc.decode(scan)?;
+    if size > 11 {
+        return Err(malformed("DC difference is too large"));
+    }
+    component.prediction = component.prediction.wrapping_add(scan.extend(size));
+    coefficients[0] = component.prediction as f32 * quantization[0] as f32;
+    let mut k = 1;
+    while k < 64 {
+        let run_size = ac.decode(scan)?;
+        let (run, size) = ((run_size >> 4) as usize, run_size & 0x0F);
+        if size == 0 {
+            // Zero size ends the block, except for a run of sixteen zeros.
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 {
+            return Err(malformed("coefficient index out of range"));
+        }
+        coefficients[ZIGZAG[k]] = scan.extend(size) as f32 * quantization[k] as f32;
+        k += 1;
+    }
+    Ok(coefficients)
+}
+
+/// `cosines()[x][u]` is the weight of frequency `u` at sample `x` in the inverse DCT.
+fn cosines() -> [[f32; 8]; 8] {
+    let mut table = [[0.0; 8]; 8];
+    for (x, row) in table.iter_mut().enumerate() {
+        for (u, weight) in row.iter_mut().enumerate() {
+            let scale = if u == 0 {
+                std::f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.0
+            };
+            *weight =
+                scale * ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0).cos() / 2.0;
+        }
+    }
+    table
+}
+
+/// Turns dequantized coefficients in row-major order into level-shifted samples.
+fn inverse_dct(coefficients: &[f32; 64], cosines: &[[f32; 8]; 8]) -> [u8; 64] {
+    let mut rows = [0.0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8)
+                .map(|u| cosines[x][u] * coefficients[v * 8 + u])
+                .sum();
+        }
+    }
+    let mut out = [0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let value: f32 = (0..8).map(|v| cosines[y][v] * rows[v * 8 + x]).sum();
+            out[y * 8 + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out
+}
+
+impl BmpFile {
+    /// Opens and decodes the baseline JPEG at `path`.
+    pub fn open_jpeg<P: AsRef<Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_jpeg_bytes(&std::fs::read(path)?)
+    }
+    /// Decodes a complete baseline JPEG held in memory. Three-component images are taken to
+    /// be YCbCr unless an Adobe marker says they are stored as RGB.
+    pub fn from_jpeg_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        if !bytes.starts_with(&[0xFF, 0xD8]) {
+            return Err(malformed("missing start of image marker"));
+        }
+        let cosines = cosines();
+        let mut quantization = [[0u16; 64]; 4];
+        let mut dc_tables: [Huffman; 4] = Default::default();
+        let mut ac_tables: [Huffman; 4] = Default::default();
+        let mut components: Vec<Component> = Vec::new();
+        let (mut width, mut height) = (0, 0);
+        let mut restart_interval = 0;
+        let mut ycbcr = true;
+        let mut position = 2;
+        loop {
+            // Markers may be preceded by any number of 0xFF fill bytes.
+            while bytes.get(position) == Some(&0xFF) && bytes.get(position + 1) == Some(&0xFF) {
+                position += 1;
+            }
+            let marker = match bytes.get(position..position + 2) {
+                Some(&[0xFF, marker]) => marker,
+                _ => return Err(malformed("expected a marker")),
+            };
+            position += 2;
+            if matches!(marker, 0xD0..=0xD8) {
+                continue;
+            }
+            if marker == 0xD9 {
+                break;
+            }
+            let len = bytes
+                .get(position..position + 2)
+                .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+                .filter(|&len| len >= 2)
+                .ok_or_else(|| malformed("segment length is missing"))?;
+            let segment = bytes
+                .get(position + 2..position + len)
+                .ok_or_else(|| malformed("segment runs past the end of the data"))?;
+            position += len;
+            let byte = |index: usize| {
+                segment
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| malformed("segment is too short"))
+            };
+            let word = |index: usize| {
+                Ok::<_, ImageError>(u16::from_be_bytes([byte(index)?, byte(index + 1)?]))
+            };
+            match marker {
+                0xC0 | 0xC1 => {
+                    if byte(0)? != 8 {
+                        return Err(malformed("only 8-bit samples are supported"));
+                    }
+                    height = word(1)? as usize;
+                    width = word(3)? as usize;
+                    if width == 0 || height == 0 {
+                        return Err(malformed("image has no pixels"));
+                    }
+                    Header::for_dimensions(width, height, 24)
+                        .check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+                    components.clear();
+                    for index in 0..byte(5)? as usize {
+                        let sampling = byte(7 + index * 3)?;
+                        let (h, v) = ((sampling >> 4) as usize, (sampling & 0x0F) as usize);
+                        if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                            return Err(malformed("invalid sampling factor"));
+                        }
+                        components.push(Component {
+                            id: byte(6 + index * 3)?,
+                            h,
+                            v,
+                            quantization: (byte(8 + index * 3)? & 0x03) as usize,
+                            dc_table: 0,
+                            ac_table: 0,
+                            prediction: 0,
+                            plane: Vec::new(),
+                            stride: 0,
+                        });
+                    }
+                    if !matches!(components.len(), 1 | 3) {
+                        return Err(malformed(
+                            "only grayscale and three-component images are supported",
+                        ));
+                    }
+                    let h_max = components
+                        .iter()
+                        .map(|component| component.h)
+                        .max()
+                        .unwrap_or(1);
+                    let v_max = components
+                        .iter()
+                        .map(|component| component.v)
+                        .max()
+                        .unwrap_or(1);
+                    let mcus_x = width.div_ceil(8 * h_max);
+                    let mcus_y = height.div_ceil(8 * v_max);
+                    for component in &mut components {
+                        component.stride = mcus_x * component.h * 8;
+                        component.plane = vec![0; component.stride * mcus_y * component.v * 8];
+                    }
+                }
+                0xC2..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                    return Err(malformed(
+                        "progressive, lossless and arithmetic-coded JPEGs are not supported",
+                    ));
+                }
+                0xC4 => {
+                    let mut rest = segment;
+                    while let Some((&class_and_id, tail)) = rest.split_first() {
+                        let counts: [u8; 16] = tail
+                            .get(..16)
+                            .and_then(|counts| counts.try_into().ok())
+                            .ok_or_else(|| malformed("Huffman table is truncated"))?;
+                        let total: usize = counts.iter().map(|&count| count as usize).sum();
+                        let symbols = tail
+                            .get(16..16 + total)
+                            .ok_or_else(|| malformed("Huffman table is truncated"))?;
+                        let table = Huffman {
+                            counts,
+                            symbols: symbols.to_vec(),
+                        };
+                        let id = (class_and_id & 0x03) as usize;
+                        if class_and_id >> 4 == 0 {
+                            dc_tables[id] = table;
+                        } else {
+                            ac_tables[id] = table;
+                        }
+                        rest = &tail[16 + total..];
+                    }
+                }
+                0xDB => {
+                    let mut rest = segment;
+                    while let Some((&precision_and_id, tail)) = rest.split_first() {
+                        let wide = precision_and_id >> 4 != 0;
+                        let len = if wide { 128 } else { 64 };
+                        let values = tail
+                            .get(..len)
+                            .ok_or_else(|| malformed("quantization table is truncated"))?;
+                        let table = &mut quantization[(precision_and_id & 0x03) as usize];
+                        for (k, entry) in table.iter_mut().enumerate() {
+                            *entry = if wide {
+                                u16::from_be_bytes([values[k * 2], values[k * 2 + 1]])
+                            } else {
+                                values[k] as u16
+                            };
+                        }
+                        rest = &tail[len..];
+                    }
+                }
+                0xDD => restart_interval = word(0)? as usize,
+                // An Adobe marker with a zero transform flag means the components are RGB.
+                0xEE if segment.starts_with(b"Adobe") => ycbcr = byte(11)? != 0,
+                0xDA => {
+                    if components.is_empty() {
+                        return Err(malformed("scan comes before the frame header"));
+                    }
+                    let mut scan_components = Vec::new();
+                    for index in 0..byte(0)? as usize {
+                        let id = byte(1 + index * 2)?;
+                        let tables = byte(2 + index * 2)?;
+                        let component = components
+                            .iter()
+                            .position(|component| component.id == id)
+                            .ok_or_else(|| malformed("scan names an unknown component"))?;
+                        components[component].dc_table = (tables >> 4 & 0x03) as usize;
+                        components[component].ac_table = (tables & 0x03) as usize;
+                        components[component].prediction = 0;
+                        scan_components.push(component);
+                    }
+                    let mut scan = Scan {
+                        data: bytes,
+                        position,
+                        byte: 0,
+                        count: 0,
+                    };
+                    let h_max = components
+                        .iter()
+                        .map(|component| component.h)
+                        .max()
+                        .unwrap_or(1);
+                    let v_max = components
+                        .iter()
+                        .map(|component| component.v)
+                        .max()
+                        .unwrap_or(1);
+                    // A scan of one component walks its blocks in raster order; otherwise
+                    // each MCU holds h by v blocks of every component in turn.
+                    let (mcus_x, mcus_y) = match scan_components[..] {
+                        [single] => {
+                            let component = &components[single];
+                            (
+                                (width * component.h).div_ceil(h_max).div_ceil(8),
+                                (height * component.v).div_ceil(v_max).div_ceil(8),
+                            )
+                        }
+                        _ => (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max)),
+                    };
+                    let single = scan_components.len() == 1;
+                    for mcu in 0..mcus_x * mcus_y {
+                        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+                            scan.restart();
+                            for &index in &scan_components {
+                                components[index].prediction = 0;
+                            }
+                        }
+                        let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+                        for &index in &scan_components {
+                            let component = &mut components[index];
+                            let (h, v) = if single {
+                                (1, 1)
+                            } else {
+                                (component.h, component.v)
+                            };
+                            for block in 0..h * v {
+                                let coefficients = decode_block(
+                                    &mut scan,
+                                    component,
+                                    &dc_tables[component.dc_table],
+                                    &ac_tables[component.ac_table],
+                                    &quantization[component.quantization],
+                                )?;
+                                let samples = inverse_dct(&coefficients, &cosines);
+                                let x = (mcu_x * h + block % h) * 8;
+                                let y = (mcu_y * v + block / h) * 8;
+                                for (row, line) in samples.chunks_exact(8).enumerate() {
+                                    let start = (y + row) * component.stride + x;
+                                    if let Some(target) = component.plane.get_mut(start..start + 8)
+                                    {
+                                        target.copy_from_slice(line);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    position = scan.end();
+                }
+                _ => {}
+            }
+        }
+        if components.is_empty() {
+            return Err(malformed("frame header is missing"));
+        }
+        let h_max = components
+            .iter()
+            .map(|component| component.h)
+            .max()
+            .unwrap_or(1);
+        let v_max = components
+            .iter()
+            .map(|component| component.v)
+            .max()
+            .unwrap_or(1);
+        let sample = |component: &Component, x: usize, y: usize| {
+            component.plane[y * component.v / v_max * component.stride + x * component.h / h_max]
+                as f32
+        };
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let color = match &components[..] {
+                    [gray] => {
+                        let gray = sample(gray, x, y) as u8;
+                        Color::gray(gray)
+                    }
+                    [first, second, third] if ycbcr => {
+                        let luma = sample(first, x, y);
+                        let (cb, cr) = (sample(second, x, y) - 128.0, sample(third, x, y) - 128.0);
+                        let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+                        Color::rgb(
+                            channel(luma + 1.402 * cr),
+                            channel(luma - 0.344_136 * cb - 0.714_136 * cr),
+                            channel(luma + 1.772 * cb),
+                        )
+                    }
+                    [red, green, blue] => Color::rgb(
+                        sample(red, x, y) as u8,
+                        sample(green, x, y) as u8,
+                        sample(blue, x, y) as u8,
+                    ),
+                    _ => unreachable!("frame headers with other component counts are rejected"),
+                };
+                pixels.push(color);
+            }
+        }
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+}
@@ -1,74 +1,439 @@
+//! Bitmap image manipulation.
+//!
+//! Transform methods on [`BmpFile`] and the [`Image`] trait work in place through
+//! `&mut self`. Each of them also has a `*_copied` counterpart taking `&self` that returns
+//! the transformed image and leaves the source untouched, so operations can be chained
+//! without mutating the original.
+
 pub(crate) use byteorder::{ByteOrder, LittleEndian};
 use num::clamp;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::prelude::*;
-use std::ops::Mul;
-use std::path::Path;
+use std::ops::Range;
+use std::path::PathBuf;
 
-pub struct Color(u8, u8, u8);
+mod canvas;
+mod color;
+mod error;
+mod fill;
+mod gif;
+mod ico;
+#[cfg(feature = "image")]
+mod interop;
+#[cfg(feature = "jpeg")]
+mod jpeg;
+mod path;
+#[cfg(feature = "png")]
+mod png;
+mod pnm;
+mod qoi;
+mod raw;
+mod rle;
+mod shape;
+mod stroke;
+mod text;
+mod tga;
+mod tone;
+#[cfg(feature = "truetype")]
+mod truetype;
+mod view;
+#[cfg(feature = "png")]
+mod zlib;
 
-#[derive(Debug, Clone)]
-enum Pixel {
-    ColorData(u8, u8, u8),
-    Padding,
-}
-impl Pixel {
-    fn pixel2d_to_bytes(pixels: Vec<Vec<Pixel>>) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        for y in 0..pixels[0].len() {
-            for column in &pixels {
-                if let Pixel::ColorData(b, g, r) = column[y] {
-                    result.push(b);
-                    result.push(g);
-                    result.push(r);
-                } else {
-                    result.push(0);
-                }
-            }
+pub use canvas::{Image, ReadImage};
+pub use color::Color;
+pub use error::ImageError;
+pub use fill::{FillStyle, Gradient};
+pub use path::Path;
+pub use pnm::PnmFormat;
+pub use raw::PixelFormat;
+pub use shape::CornerRadii;
+pub use stroke::{LineCap, LineJoin, StrokeStyle, MITER_LIMIT};
+pub use text::{text_size, GLYPH_SIZE};
+pub use tone::Levels;
+#[cfg(feature = "truetype")]
+pub use truetype::Font;
+pub use view::{View, ViewMut};
+
+/// Largest decoded pixel buffer, in bytes, that [`BmpFile::try_from`] will allocate.
+pub const DEFAULT_MAX_PIXEL_BYTES: usize = 1 << 30;
+
+/// The edge of the image that the `from` multiplier of [`BmpFile::fade`] is applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// The values operations such as [`Image::fade_with`] scale and mix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blending {
+    /// The stored gamma-encoded sRGB values. Fast, but gradients come out too dark in the
+    /// middle.
+    Srgb,
+    /// Linear light, decoding each channel first and encoding the result, which matches how
+    /// light actually adds up.
+    Linear,
+}
+
+/// The weights [`Image::grayscale_with`] gives red, green and blue when computing luma.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Luma {
+    /// 0.299, 0.587 and 0.114, from Rec. 601 (standard-definition video and JPEG).
+    Rec601,
+    /// 0.2126, 0.7152 and 0.0722, from Rec. 709, matching the sRGB primaries.
+    Rec709,
+}
+
+/// The threshold matrix [`Image::ordered_dither`] tiles across the image. Larger matrices
+/// give more distinct shades between two levels but a coarser crosshatch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BayerMatrix {
+    Size2,
+    Size4,
+    Size8,
+}
+impl BayerMatrix {
+    /// The matrix entry for (x, y), from 0 to size² - 1, with the matrix repeating every
+    /// size pixels in both directions.
+    fn threshold(&self, x: usize, y: usize) -> usize {
+        let bits = match self {
+            BayerMatrix::Size2 => 1,
+            BayerMatrix::Size4 => 2,
+            BayerMatrix::Size8 => 3,
+        };
+        // Interleaving the bits of x ^ y and y, lowest bits first, builds the matrix
+        // recursively out of 2x2 blocks.
+        (0..bits).fold(0, |value, bit| {
+            let (x, y) = ((x >> bit) & 1, (y >> bit) & 1);
+            value << 2 | (x ^ y) << 1 | y
+        })
+    }
+    fn size(&self) -> usize {
+        match self {
+            BayerMatrix::Size2 => 2,
+            BayerMatrix::Size4 => 4,
+            BayerMatrix::Size8 => 8,
         }
-        result
     }
 }
 
-impl From<&Color> for Pixel {
-    fn from(color: &Color) -> Pixel {
-        let Color(b, g, r) = color;
-        Pixel::ColorData(*b, *g, *r)
+/// How operations that sample past the edge of an image pick a color for those positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderMode {
+    /// Every position outside the image reads as this color.
+    Constant(Color),
+    /// Positions are clamped to the nearest edge pixel.
+    Replicate,
+    /// Positions wrap around to the opposite edge, tiling the image.
+    Wrap,
+}
+impl BorderMode {
+    /// Maps a coordinate onto `0..len`, or `None` where a constant border applies or
+    /// `len` is zero, leaving nothing to replicate or wrap.
+    fn resolve(&self, coordinate: isize, len: usize) -> Option<usize> {
+        if (0..len as isize).contains(&coordinate) {
+            return Some(coordinate as usize);
+        }
+        if len == 0 {
+            return None;
+        }
+        match self {
+            BorderMode::Constant(_) => None,
+            BorderMode::Replicate => Some(coordinate.clamp(0, len as isize - 1) as usize),
+            BorderMode::Wrap => Some(coordinate.rem_euclid(len as isize) as usize),
+        }
     }
 }
 
-impl From<Color> for Pixel {
-    fn from(color: Color) -> Pixel {
-        let Color(b, g, r) = color;
-        Pixel::ColorData(b, g, r)
+/// Resampling filter used by [`BmpFile::resize_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Blends the four source pixels nearest to each destination pixel center.
+    Bilinear,
+    /// Averages every source pixel a destination pixel covers when shrinking, which avoids
+    /// aliasing on large reductions. Falls back to bilinear when enlarging.
+    Area,
+}
+
+/// Per-channel comparison of two equally sized images, see [`BmpFile::diff`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffStats {
+    pub max_channel_difference: u8,
+    pub mean_absolute_error: f64,
+    pub differing_pixels: usize,
+}
+
+/// Decodes the pixel array, also returning how many of its bytes the pixels took up.
+fn bytes_to_pixels(pixel_array: &[u8], header: &Header) -> Result<(Vec<Color>, usize), ImageError> {
+    let stride = header.row_stride();
+    let mut consumed = stride * header.height;
+    if header.compression != 1 && header.compression != 2 && pixel_array.len() < consumed {
+        return Err(ImageError::TruncatedPixels {
+            expected: consumed,
+            found: pixel_array.len(),
+        });
+    }
+    // Files usually store the bottom row first; pixels are kept top row first.
+    let mut rows: Vec<&[u8]> = pixel_array
+        .chunks_exact(stride.max(1))
+        .take(header.height)
+        .collect();
+    if !header.top_down {
+        rows.reverse();
+    }
+    let mut pixels: Vec<Color> = Vec::with_capacity(header.width * header.height);
+    let palette: Vec<Color> = header.palette();
+    // Indices past the end of a short color table read as black.
+    let lookup = |index: u8| palette.get(index as usize).copied().unwrap_or(Color::BLACK);
+    match (header.bits_per_pixel, header.compression) {
+        (24, 0) => {
+            for row in rows {
+                for bgr in row[..header.width * 3].chunks_exact(3) {
+                    pixels.push(Color {
+                        b: bgr[0],
+                        g: bgr[1],
+                        r: bgr[2],
+                        a: 255,
+                    });
+                }
+            }
+        }
+        (1 | 4 | 8, 0) => {
+            let bits = header.bits_per_pixel as usize;
+            let mask = ((1u16 << bits) - 1) as u8;
+            for row in rows {
+                for x in 0..header.width {
+                    // Sub-byte indices are packed starting from the most significant bit.
+                    let bit = x * bits;
+                    let index = (row[bit / 8] >> (8 - bits - bit % 8)) & mask;
+                    pixels.push(lookup(index));
+                }
+            }
+        }
+        (8, 1) | (4, 2) => {
+            let (indices, used) = rle::decode(
+                pixel_array,
+                header.width,
+                header.height,
+                header.bits_per_pixel,
+            );
+            consumed = used;
+            let mut rows: Vec<&[u8]> = indices.chunks(header.width.max(1)).collect();
+            if !header.top_down {
+                rows.reverse();
+            }
+            for row in rows {
+                pixels.extend(row.iter().map(|&index| lookup(index)));
+            }
+        }
+        (16 | 32, 0 | 3 | 6) => {
+            let bytes_per_pixel = header.bits_per_pixel as usize / 8;
+            let [red, green, blue, alpha] = header.channel_masks();
+            for row in rows {
+                for word in row[..header.width * bytes_per_pixel].chunks_exact(bytes_per_pixel) {
+                    let value = LittleEndian::read_uint(word, bytes_per_pixel) as u32;
+                    pixels.push(Color {
+                        b: blue.expand(value),
+                        g: green.expand(value),
+                        r: red.expand(value),
+                        a: alpha.expand_alpha(value),
+                    });
+                }
+            }
+            // The fourth byte of an uncompressed 32-bit pixel is officially unused and
+            // most writers leave it zero, so only trust it once something is visible.
+            if header.compression == 0 && pixels.iter().all(|pixel| pixel.a == 0) {
+                pixels.iter_mut().for_each(|pixel| pixel.a = 255);
+            }
+        }
+        (1 | 4 | 8 | 16 | 24 | 32, compression) => {
+            return Err(ImageError::UnsupportedCompression {
+                compression,
+                bits_per_pixel: header.bits_per_pixel,
+            })
+        }
+        (bits_per_pixel, _) => return Err(ImageError::UnsupportedBitDepth(bits_per_pixel)),
+    }
+    Ok((pixels, consumed))
+}
+
+/// Appends the encoded pixel array, including scanline padding, to `out`.
+/// Paletted images are re-quantized, mapping every pixel to its nearest table entry.
+fn write_pixels(pixels: &[Color], header: &Header, out: &mut Vec<u8>) {
+    let stride = header.row_stride();
+    let [red, green, blue, alpha] = header.channel_masks();
+    let bytes_per_pixel = header.bits_per_pixel as usize / 8;
+    let palette = header.palette();
+    let mut indices: HashMap<Color, u8> = HashMap::new();
+    let mut index_of = |&color: &Color| {
+        *indices
+            .entry(color)
+            .or_insert_with(|| color.nearest_index(&palette) as u8)
+    };
+    if let 1 | 2 = header.compression {
+        let indices: Vec<u8> = pixels.iter().map(index_of).collect();
+        let mut rows: Vec<&[u8]> = indices.chunks(header.width.max(1)).collect();
+        if !header.top_down {
+            rows.reverse();
+        }
+        rle::encode(rows.into_iter(), header.bits_per_pixel, out);
+        return;
+    }
+    out.reserve(stride * header.height);
+    let mut rows: Vec<&[Color]> = pixels.chunks(header.width.max(1)).collect();
+    if !header.top_down {
+        rows.reverse();
+    }
+    for row in rows {
+        let row_start = out.len();
+        match header.bits_per_pixel {
+            1 | 4 | 8 => {
+                let bits = header.bits_per_pixel as usize;
+                out.resize(row_start + (row.len() * bits).div_ceil(8), 0);
+                for (x, pixel) in row.iter().enumerate() {
+                    let index = index_of(pixel);
+                    let bit = x * bits;
+                    out[row_start + bit / 8] |= index << (8 - bits - bit % 8);
+                }
+            }
+            16 | 32 => {
+                for pixel in row {
+                    let value = red.compress(pixel.r)
+                        | green.compress(pixel.g)
+                        | blue.compress(pixel.b)
+                        | alpha.compress(pixel.a);
+                    out.extend_from_slice(&value.to_le_bytes()[..bytes_per_pixel]);
+                }
+            }
+            _ => {
+                for pixel in row {
+                    out.extend_from_slice(&[pixel.b, pixel.g, pixel.r]);
+                }
+            }
+        }
+        out.resize(row_start + stride, 0);
     }
 }
 
-impl From<&Pixel> for Color {
-    fn from(pixel: &Pixel) -> Color {
-        if let Pixel::ColorData(b, g, r) = pixel {
-            Color(*b, *g, *r)
+/// Location of one channel inside a packed 16- or 32-bit pixel.
+#[derive(Debug, Clone, Copy)]
+struct ChannelMask {
+    shift: u32,
+    max: u32,
+}
+impl ChannelMask {
+    fn new(mask: u32) -> ChannelMask {
+        if mask == 0 {
+            return ChannelMask { shift: 0, max: 0 };
+        }
+        let shift = mask.trailing_zeros();
+        ChannelMask {
+            shift,
+            max: mask >> shift,
+        }
+    }
+    fn expand(&self, value: u32) -> u8 {
+        if self.max == 0 {
+            return 0;
+        }
+        let (channel, max) = (((value >> self.shift) & self.max) as u64, self.max as u64);
+        ((channel * 255 + max / 2) / max) as u8
+    }
+    /// Like [`ChannelMask::expand`], but an absent alpha channel means fully opaque.
+    fn expand_alpha(&self, value: u32) -> u8 {
+        if self.max == 0 {
+            255
         } else {
-            Color(0, 0, 0)
+            self.expand(value)
         }
     }
+    fn compress(&self, channel: u8) -> u32 {
+        (((channel as u64 * self.max as u64 + 127) / 255) as u32) << self.shift
+    }
+}
+
+/// A CIE XYZ color in 2.30 fixed point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CieXyz {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
 }
 
-impl Mul<f64> for Color {
-    type Output = Color;
-    fn mul(self, rhs: f64) -> Self {
-        let Color(b, g, r) = self;
-        let b = clamp(b as f64 * rhs, 0.0, 255.0) as u8;
-        let g = clamp(g as f64 * rhs, 0.0, 255.0) as u8;
-        let r = clamp(r as f64 * rhs, 0.0, 255.0) as u8;
-        Color(b, g, r)
+/// The color space fields of a BITMAPV4HEADER or BITMAPV5HEADER.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorSpace {
+    /// `LCS_CALIBRATED_RGB` (zero) or a four-character tag such as `sRGB`, `MBED` or `LINK`.
+    pub cs_type: u32,
+    /// Red, green and blue endpoints, used with `LCS_CALIBRATED_RGB`.
+    pub endpoints: [CieXyz; 3],
+    /// Red, green and blue gamma in 16.16 fixed point, used with `LCS_CALIBRATED_RGB`.
+    pub gamma: [u32; 3],
+    /// Rendering intent. V4 headers have no such field and leave it zero.
+    pub intent: u32,
+    /// The embedded ICC profile, or the file name of a linked one. Only V5 headers have one.
+    pub profile: Vec<u8>,
+    /// Where the profile starts, counted from the start of the info header.
+    profile_offset: u32,
+    reserved: u32,
+}
+/// Length of the color space fields in a V4 header; V5 adds 16 bytes.
+const V4_COLOR_SPACE_LEN: usize = 52;
+impl ColorSpace {
+    /// Parses the fields that start 56 bytes into a V4 or V5 header, where `fields` holds
+    /// 52 bytes for V4 and 68 for V5. The profile itself is attached once the whole file
+    /// has been read.
+    fn read(fields: &[u8]) -> ColorSpace {
+        let word = |index: usize| LittleEndian::read_u32(&fields[index * 4..index * 4 + 4]);
+        let xyz = |index: usize| CieXyz {
+            x: word(index) as i32,
+            y: word(index + 1) as i32,
+            z: word(index + 2) as i32,
+        };
+        let v5 = fields.len() > V4_COLOR_SPACE_LEN;
+        let v5_word = |index: usize| if v5 { word(index) } else { 0 };
+        ColorSpace {
+            cs_type: word(0),
+            endpoints: [xyz(1), xyz(4), xyz(7)],
+            gamma: [word(10), word(11), word(12)],
+            intent: v5_word(13),
+            profile: Vec::new(),
+            profile_offset: v5_word(14),
+            reserved: v5_word(16),
+        }
+    }
+    /// Appends the fields in header order, with the V5 fields only when `v5` is set.
+    fn write(&self, v5: bool, out: &mut Vec<u8>) {
+        let mut words = vec![self.cs_type];
+        for endpoint in self.endpoints {
+            words.extend([endpoint.x as u32, endpoint.y as u32, endpoint.z as u32]);
+        }
+        words.extend(self.gamma);
+        if v5 {
+            let profile_size = u32::try_from(self.profile.len()).unwrap_or(u32::MAX);
+            words.extend([
+                self.intent,
+                self.profile_offset,
+                profile_size,
+                self.reserved,
+            ]);
+        }
+        for word in words {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
-struct Header {
+pub struct Header {
     bmp_ident: [u8; 2],
     file_size: u32,
     reserved1: [u8; 2],
@@ -77,38 +442,540 @@ struct Header {
     header_size: u32,
     width: usize,
     height: usize,
+    /// Set when the stored height is negative, meaning rows are stored top row first.
+    top_down: bool,
     color_planes: u16,
     bits_per_pixel: u16,
     compression: u32,
     pixel_image_size: u32,
     hres: i32,
     vres: i32,
+    colors_used: u32,
+    important_colors: u32,
+    /// Red, green, blue and alpha masks, stored inside V2+ headers or after a BITFIELDS
+    /// info header.
+    masks: [u32; 4],
+    /// Present for V4 and V5 headers.
+    color_space: Option<ColorSpace>,
+    /// Header bytes past the fields parsed here.
+    extra: Vec<u8>,
+    /// Everything between the header and the pixel array, starting with the color table.
     gap: Vec<u8>,
+    /// Read from a BITMAPCOREHEADER, which is written back as one while its fields can
+    /// still hold the image, see [`Header::writes_core`].
+    core: bool,
 }
+/// Size of the OS/2 BITMAPCOREHEADER.
+const CORE_HEADER_SIZE: u32 = 12;
+/// Size of BITMAPINFOHEADER, the layout every longer header extends.
+const INFO_HEADER_SIZE: u32 = 40;
+/// Largest header size accepted, well past the 124 bytes of BITMAPV5HEADER.
+const MAX_HEADER_SIZE: u32 = 1024;
 impl From<Vec<u8>> for Header {
+    /// Parses everything in front of the pixel array. The short OS/2 variants are widened to
+    /// a BITMAPINFOHEADER; core headers are widened too but remembered, see `Header::core`.
     fn from(header: Vec<u8>) -> Header {
+        let header_size = LittleEndian::read_u32(&header[14..18]);
+        if header_size == CORE_HEADER_SIZE {
+            return Header::from_core(&header);
+        }
+        let header_end = 14 + header_size as usize;
+        // Fields a short header leaves out read as zero.
+        let mut info = header[..header_end.min(header.len())].to_vec();
+        info.resize(info.len().max(54), 0);
+        let height = LittleEndian::read_i32(&info[22..26]);
+        let mut parsed = Header {
+            bmp_ident: info[0..2].try_into().unwrap(),
+            file_size: LittleEndian::read_u32(&info[2..6]),
+            reserved1: info[6..8].try_into().unwrap(),
+            reserved2: info[8..10].try_into().unwrap(),
+            offset: LittleEndian::read_u32(&info[10..14]),
+            header_size: header_size.max(INFO_HEADER_SIZE),
+            width: LittleEndian::read_i32(&info[18..22]) as usize,
+            height: height.unsigned_abs() as usize,
+            top_down: height < 0,
+            color_planes: LittleEndian::read_u16(&info[26..28]),
+            bits_per_pixel: LittleEndian::read_u16(&info[28..30]),
+            compression: LittleEndian::read_u32(&info[30..34]),
+            pixel_image_size: LittleEndian::read_u32(&info[34..38]),
+            hres: LittleEndian::read_i32(&info[38..42]),
+            vres: LittleEndian::read_i32(&info[42..46]),
+            colors_used: LittleEndian::read_u32(&info[46..50]),
+            important_colors: LittleEndian::read_u32(&info[50..54]),
+            masks: [0; 4],
+            color_space: None,
+            extra: Vec::new(),
+            gap: Vec::new(),
+            core: false,
+        };
+        let header_masks = parsed.header_masks();
+        let masks_end = 54 + header_masks * 4;
+        for (mask, word) in parsed
+            .masks
+            .iter_mut()
+            .zip(info[54..masks_end].chunks_exact(4))
+        {
+            *mask = LittleEndian::read_u32(word);
+        }
+        let color_space_end = masks_end + parsed.color_space_len();
+        if color_space_end > masks_end {
+            parsed.color_space = Some(ColorSpace::read(&info[masks_end..color_space_end]));
+        }
+        parsed.extra = info.get(color_space_end..).unwrap_or_default().to_vec();
+        let gap_start = (header_end + parsed.trailing_masks() * 4).min(header.len());
+        let trailing = header.get(header_end..gap_start).unwrap_or_default();
+        for (mask, word) in parsed.masks.iter_mut().zip(trailing.chunks_exact(4)) {
+            *mask = LittleEndian::read_u32(word);
+        }
+        parsed.gap = header[gap_start..].to_vec();
+        parsed
+    }
+}
+const INCHES_PER_METER: f64 = 1.0 / 0.0254;
+
+/// Entries in the color table of a core header, which always holds the full
+/// `2^bits_per_pixel`.
+fn core_entries(bits_per_pixel: u16) -> usize {
+    if bits_per_pixel <= 8 {
+        1 << bits_per_pixel
+    } else {
+        0
+    }
+}
+
+impl Header {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn bits_per_pixel(&self) -> u16 {
+        self.bits_per_pixel
+    }
+    pub fn compression(&self) -> u32 {
+        self.compression
+    }
+    /// Size of the whole file in bytes, as the header records it.
+    pub fn file_size(&self) -> u32 {
+        self.file_size
+    }
+    /// Where the pixel array starts, in bytes from the start of the file.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+    /// Size of the pixel array in bytes, including row padding.
+    pub fn pixel_image_size(&self) -> u32 {
+        self.pixel_image_size
+    }
+    /// Whether the file stores its rows top row first, signalled by a negative height.
+    pub fn top_down(&self) -> bool {
+        self.top_down
+    }
+    /// Horizontal resolution in pixels per meter.
+    pub fn hres(&self) -> i32 {
+        self.hres
+    }
+    /// Vertical resolution in pixels per meter.
+    pub fn vres(&self) -> i32 {
+        self.vres
+    }
+    /// The color table of a paletted image, empty for true-color images. It holds the number
+    /// of colors the header declares, or `2^bits_per_pixel` when it declares none.
+    pub fn palette(&self) -> Vec<Color> {
+        if self.bits_per_pixel > 8 {
+            return Vec::new();
+        }
+        let count = match self.colors_used as usize {
+            0 => 1 << self.bits_per_pixel,
+            count => count.min(1 << self.bits_per_pixel),
+        };
+        self.gap
+            .chunks_exact(4)
+            .take(count)
+            .map(|entry| Color::rgb(entry[2], entry[1], entry[0]))
+            .collect()
+    }
+    /// The V4/V5 color space, including any ICC profile.
+    pub fn color_space(&self) -> Option<&ColorSpace> {
+        self.color_space.as_ref()
+    }
+    /// Horizontal and vertical resolution converted to dots per inch.
+    pub fn dpi(&self) -> (f64, f64) {
+        (
+            self.hres as f64 / INCHES_PER_METER,
+            self.vres as f64 / INCHES_PER_METER,
+        )
+    }
+}
+#[allow(dead_code)]
+impl Header {
+    /// Reads everything in front of the pixel array, leaving `reader` positioned at the pixels.
+    fn read_from<R: Read>(reader: &mut R) -> Result<Header, ImageError> {
+        let mut bytes = Header::read_headers(reader)?;
+        let offset = LittleEndian::read_u32(&bytes[10..14]) as usize;
+        if offset < bytes.len() {
+            return Err(ImageError::OffsetOutOfBounds {
+                offset,
+                len: bytes.len(),
+            });
+        }
+        // Grows with the bytes that actually arrive, so a bogus offset allocates nothing.
+        let gap_len = (offset - bytes.len()) as u64;
+        if reader.take(gap_len).read_to_end(&mut bytes)? as u64 != gap_len {
+            return Err(ImageError::TruncatedHeader);
+        }
+        Ok(Header::from(bytes))
+    }
+    /// Reads the 14-byte file header and the DIB header after it, but nothing past them.
+    fn read_headers<R: Read>(reader: &mut R) -> Result<Vec<u8>, ImageError> {
+        let truncated = |error: std::io::Error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => ImageError::TruncatedHeader,
+            _ => ImageError::Io(error),
+        };
+        let mut bytes = vec![0; 18];
+        reader.read_exact(&mut bytes).map_err(truncated)?;
+        if bytes[0..2] != *b"BM" {
+            return Err(ImageError::BadMagic([bytes[0], bytes[1]]));
+        }
+        let header_size = LittleEndian::read_u32(&bytes[14..18]);
+        // Every header but the core one is at least as long as the 16-byte OS/2 variant.
+        if header_size != CORE_HEADER_SIZE && !(16..=MAX_HEADER_SIZE).contains(&header_size) {
+            return Err(ImageError::UnsupportedHeader(header_size));
+        }
+        bytes.resize(14 + header_size as usize, 0);
+        reader.read_exact(&mut bytes[18..]).map_err(truncated)?;
+        Ok(bytes)
+    }
+    /// Everything [`BmpFile::from_bytes_strict`] objects to in `file`, in header order.
+    fn violations(file: &[u8]) -> Vec<String> {
+        let mut violations = Vec::new();
+        if file.len() < 18 {
+            violations.push(format!(
+                "the file is {} bytes, too short for the 18 bytes every header needs",
+                file.len()
+            ));
+            return violations;
+        }
+        if file[0..2] != *b"BM" {
+            violations.push(format!("signature is {:?} rather than \"BM\"", &file[0..2]));
+        }
+        let file_size = LittleEndian::read_u32(&file[2..6]) as usize;
+        if file_size != file.len() {
+            violations.push(format!(
+                "file size field says {} bytes but the file is {}",
+                file_size,
+                file.len()
+            ));
+        }
+        let header_size = LittleEndian::read_u32(&file[14..18]);
+        let header_end = 14 + header_size as usize;
+        let offset = LittleEndian::read_u32(&file[10..14]) as usize;
+        if header_size != CORE_HEADER_SIZE && header_size < 16 {
+            violations.push(format!(
+                "header size {} matches no known layout",
+                header_size
+            ));
+            return violations;
+        }
+        if offset < header_end || offset > file.len() {
+            violations.push(format!(
+                "pixel offset {} is outside bytes {}..={}",
+                offset,
+                header_end,
+                file.len()
+            ));
+            return violations;
+        }
+        let header = Header::from(file[..offset].to_vec());
+        if header.color_planes != 1 {
+            violations.push(format!("{} color planes instead of 1", header.color_planes));
+        }
+        if !matches!(header.bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32) {
+            violations.push(format!("unsupported bit depth {}", header.bits_per_pixel));
+        }
+        let available = file.len() - offset;
+        let stored = header.pixel_image_size as usize;
+        if stored > available {
+            violations.push(format!(
+                "image size field says {} bytes but only {} follow the offset",
+                stored, available
+            ));
+        }
+        let expected = header.row_stride().saturating_mul(header.height);
+        match header.compression {
+            0 | 3 | 6 if stored != 0 && stored != expected => violations.push(format!(
+                "image size field says {} bytes but {}x{} at {} bits per pixel takes {}",
+                stored, header.width, header.height, header.bits_per_pixel, expected
+            )),
+            0 | 3 | 6 if expected > available => violations.push(format!(
+                "pixel array needs {} bytes but only {} follow the offset",
+                expected, available
+            )),
+            1 | 2 if stored == 0 => {
+                violations.push("compressed image has no image size".to_string())
+            }
+            _ => {}
+        }
+        violations
+    }
+    /// Parses a BITMAPCOREHEADER, whose 16-bit dimensions and three-byte color table
+    /// entries are widened to their BITMAPINFOHEADER equivalents.
+    fn from_core(header: &[u8]) -> Header {
+        let bits_per_pixel = LittleEndian::read_u16(&header[24..26]);
+        let table = header[26..]
+            .chunks_exact(3)
+            .take(core_entries(bits_per_pixel));
+        let table_end = 26 + table.len() * 3;
+        let gap = table
+            .flat_map(|entry| [entry[0], entry[1], entry[2], 0])
+            .chain(header[table_end..].iter().copied())
+            .collect();
         Header {
             bmp_ident: header[0..2].try_into().unwrap(),
             file_size: LittleEndian::read_u32(&header[2..6]),
             reserved1: header[6..8].try_into().unwrap(),
             reserved2: header[8..10].try_into().unwrap(),
             offset: LittleEndian::read_u32(&header[10..14]),
-            header_size: LittleEndian::read_u32(&header[14..18]),
-            width: LittleEndian::read_i32(&header[18..22]) as usize,
-            height: LittleEndian::read_i32(&header[22..26]) as usize,
-            color_planes: LittleEndian::read_u16(&header[26..28]),
-            bits_per_pixel: LittleEndian::read_u16(&header[28..30]),
-            compression: LittleEndian::read_u32(&header[30..34]),
-            pixel_image_size: LittleEndian::read_u32(&header[34..38]),
-            hres: LittleEndian::read_i32(&header[38..42]),
-            vres: LittleEndian::read_i32(&header[42..46]),
-            gap: header[46..].to_vec(),
+            header_size: INFO_HEADER_SIZE,
+            width: LittleEndian::read_u16(&header[18..20]) as usize,
+            height: LittleEndian::read_u16(&header[20..22]) as usize,
+            top_down: false,
+            color_planes: LittleEndian::read_u16(&header[22..24]),
+            bits_per_pixel,
+            compression: 0,
+            pixel_image_size: 0,
+            hres: 0,
+            vres: 0,
+            colors_used: 0,
+            important_colors: 0,
+            masks: [0; 4],
+            color_space: None,
+            extra: Vec::new(),
+            gap,
+            core: true,
+        }
+    }
+    /// A BITMAPINFOHEADER for an uncompressed bottom-up image at 72 DPI.
+    fn for_dimensions(width: usize, height: usize, bits_per_pixel: u16) -> Header {
+        let mut header = Header {
+            bmp_ident: *b"BM",
+            file_size: 0,
+            reserved1: [0; 2],
+            reserved2: [0; 2],
+            offset: 0,
+            header_size: 40,
+            width,
+            height,
+            top_down: false,
+            color_planes: 1,
+            bits_per_pixel,
+            compression: 0,
+            pixel_image_size: 0,
+            hres: 2835,
+            vres: 2835,
+            colors_used: 0,
+            important_colors: 0,
+            masks: [0; 4],
+            color_space: None,
+            extra: Vec::new(),
+            gap: Vec::new(),
+            core: false,
+        };
+        header.recompute_sizes();
+        header
+    }
+    /// Brings `offset`, `pixel_image_size` and `file_size` in line with the
+    /// dimensions and the length of everything in front of the pixel array.
+    /// Sizes that do not fit in 32 bits saturate.
+    fn recompute_sizes(&mut self) {
+        let offset = if self.writes_core() {
+            14 + CORE_HEADER_SIZE as usize + self.stored_gap().len()
+        } else {
+            14 + self.header_size as usize + self.trailing_masks() * 4 + self.gap.len()
+        };
+        self.offset = u32::try_from(offset).unwrap_or(u32::MAX);
+        self.pixel_image_size = self
+            .row_stride()
+            .checked_mul(self.height)
+            .and_then(|size| u32::try_from(size).ok())
+            .unwrap_or(u32::MAX);
+        self.file_size = self.offset.saturating_add(self.pixel_image_size);
+        if let Some(color_space) = self
+            .color_space
+            .as_mut()
+            .filter(|color_space| !color_space.profile.is_empty())
+        {
+            // Profiles are written straight after the pixel array.
+            color_space.profile_offset = self.file_size.saturating_sub(14);
+            let profile_size = u32::try_from(color_space.profile.len()).unwrap_or(u32::MAX);
+            self.file_size = self.file_size.saturating_add(profile_size);
+        }
+    }
+    /// Moves a V5 header's profile out of `file` into the color space, removing it from the
+    /// trailer or gap it was found in so that it is not written twice.
+    fn take_profile(&mut self, file: &[u8], trailer: &mut Vec<u8>) {
+        let Some(color_space) = self
+            .color_space
+            .as_mut()
+            .filter(|_| self.header_size >= 124)
+        else {
+            return;
+        };
+        let size = file.get(130..134).map_or(0, LittleEndian::read_u32) as usize;
+        let start = 14 + color_space.profile_offset as usize;
+        let Some(profile) = start.checked_add(size).and_then(|end| file.get(start..end)) else {
+            return;
+        };
+        color_space.profile = profile.to_vec();
+        let trailer_start = file.len() - trailer.len();
+        let gap_start = (self.offset as usize).checked_sub(self.gap.len());
+        if start >= trailer_start {
+            trailer.drain(start - trailer_start..start - trailer_start + size);
+        } else if let Some(gap_start) = gap_start
+            .filter(|&gap_start| start >= gap_start && start + size <= self.offset as usize)
+        {
+            self.gap.drain(start - gap_start..start - gap_start + size);
+        }
+    }
+    fn row_stride(&self) -> usize {
+        self.width
+            .saturating_mul(self.bits_per_pixel as usize)
+            .div_ceil(32)
+            .saturating_mul(4)
+    }
+    /// Rejects dimensions whose pixel array cannot be described by a 32-bit size field or
+    /// whose decoded pixels would exceed `max_pixel_bytes`, before anything is allocated.
+    fn check_size(&self, max_pixel_bytes: usize) -> Result<(), ImageError> {
+        let stored = self
+            .width
+            .checked_mul(self.bits_per_pixel as usize)
+            .map(|bits| bits.div_ceil(32) * 4)
+            .and_then(|stride| stride.checked_mul(self.height))
+            .filter(|&size| size <= u32::MAX as usize);
+        let decoded = self
+            .width
+            .checked_mul(self.height)
+            .and_then(|count| count.checked_mul(std::mem::size_of::<Color>()))
+            .filter(|&size| size <= max_pixel_bytes);
+        if stored.is_none() || decoded.is_none() {
+            return Err(ImageError::TooLarge {
+                width: self.width,
+                height: self.height,
+                limit: max_pixel_bytes,
+            });
+        }
+        Ok(())
+    }
+    /// How many of the masks are fields of the header itself: RGB in V2, RGBA from V3 on.
+    fn header_masks(&self) -> usize {
+        match self.header_size {
+            56.. => 4,
+            52.. => 3,
+            _ => 0,
+        }
+    }
+    /// Length of the color space fields inside the header, zero before V4.
+    fn color_space_len(&self) -> usize {
+        match self.header_size {
+            124.. => V4_COLOR_SPACE_LEN + 16,
+            108.. => V4_COLOR_SPACE_LEN,
+            _ => 0,
         }
     }
+    /// How many masks follow a header too short to hold them, which BITFIELDS and
+    /// ALPHABITFIELDS files need.
+    fn trailing_masks(&self) -> usize {
+        match (self.header_masks(), self.compression) {
+            (0, 3) => 3,
+            (0, 6) => 4,
+            _ => 0,
+        }
+    }
+    /// Red, green, blue and alpha masks. BITFIELDS files store them in or right after the
+    /// header, the alpha mask only with ALPHABITFIELDS or a V3+ header.
+    /// Uncompressed 16-bit files default to 5-5-5 without alpha, 32-bit files to BGRA.
+    fn channel_masks(&self) -> [ChannelMask; 4] {
+        let masks = match (self.compression, self.bits_per_pixel) {
+            (3 | 6, _) if self.masks[..3].iter().any(|&mask| mask != 0) => {
+                let has_alpha = self.compression == 6 || self.header_size >= 56;
+                let [red, green, blue, alpha] = self.masks;
+                [red, green, blue, if has_alpha { alpha } else { 0 }]
+            }
+            (_, 32) => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000],
+            _ => [0x7C00, 0x03E0, 0x001F, 0],
+        };
+        masks.map(ChannelMask::new)
+    }
+    /// Whether the pixel array has room for an alpha channel.
+    fn stores_alpha(&self) -> bool {
+        matches!(self.bits_per_pixel, 16 | 32)
+            && matches!(self.compression, 0 | 3 | 6)
+            && self.channel_masks()[3].max != 0
+    }
+    /// Whether a header read as a BITMAPCOREHEADER can still be written as one: it has no
+    /// room for compression, resolutions, a color count or a top-down flag, and holds
+    /// dimensions in 16 bits and only 1, 4, 8 and 24 bits per pixel.
+    fn writes_core(&self) -> bool {
+        self.core
+            && matches!(self.bits_per_pixel, 1 | 4 | 8 | 24)
+            && self.compression == 0
+            && [self.hres, self.vres] == [0, 0]
+            && [self.colors_used, self.important_colors] == [0, 0]
+            && !self.top_down
+            && self.width <= u16::MAX as usize
+            && self.height <= u16::MAX as usize
+    }
+    /// The bytes between the header and the pixel array as written: a core header's color
+    /// table goes back to three-byte entries.
+    fn stored_gap(&self) -> Vec<u8> {
+        if !self.writes_core() {
+            return self.gap.clone();
+        }
+        let table_end = (core_entries(self.bits_per_pixel) * 4).min(self.gap.len());
+        self.gap[..table_end]
+            .chunks_exact(4)
+            .flat_map(|entry| [entry[0], entry[1], entry[2]])
+            .chain(self.gap[table_end..].iter().copied())
+            .collect()
+    }
+    /// Switches to an uncompressed 32-bit BGRA pixel array, dropping any color table.
+    fn promote_to_bgra(&mut self) {
+        if self.bits_per_pixel <= 8 {
+            self.gap.clear();
+            self.colors_used = 0;
+            self.important_colors = 0;
+        }
+        self.bits_per_pixel = 32;
+        self.compression = 0;
+    }
 }
 impl From<Header> for Vec<u8> {
-    fn from(mut header: Header) -> Self {
-        let mut bytes: [u8; 46] = [0; 46];
+    fn from(header: Header) -> Self {
+        Vec::from(&header)
+    }
+}
+impl From<&Header> for Vec<u8> {
+    fn from(header: &Header) -> Self {
+        if header.writes_core() {
+            let mut bytes = header.bmp_ident.to_vec();
+            bytes.extend_from_slice(&header.file_size.to_le_bytes());
+            bytes.extend_from_slice(&header.reserved1);
+            bytes.extend_from_slice(&header.reserved2);
+            bytes.extend_from_slice(&header.offset.to_le_bytes());
+            bytes.extend_from_slice(&CORE_HEADER_SIZE.to_le_bytes());
+            for field in [
+                header.width as u16,
+                header.height as u16,
+                header.color_planes,
+                header.bits_per_pixel,
+            ] {
+                bytes.extend_from_slice(&field.to_le_bytes());
+            }
+            bytes.extend_from_slice(&header.stored_gap());
+            return bytes;
+        }
+        let mut bytes: [u8; 54] = [0; 54];
         bytes[0] = header.bmp_ident[0];
         bytes[1] = header.bmp_ident[1];
         let file_size: [u8; 4] = header.file_size.to_le_bytes();
@@ -135,7 +1002,8 @@ impl From<Header> for Vec<u8> {
         bytes[19] = width[1];
         bytes[20] = width[2];
         bytes[21] = width[3];
-        let height: [u8; 4] = (header.height as i32).to_le_bytes();
+        let height = header.height as i32;
+        let height: [u8; 4] = if header.top_down { -height } else { height }.to_le_bytes();
         bytes[22] = height[0];
         bytes[23] = height[1];
         bytes[24] = height[2];
@@ -150,24 +1018,49 @@ impl From<Header> for Vec<u8> {
         bytes[30] = compression[0];
         bytes[31] = compression[1];
         bytes[32] = compression[2];
-        bytes[33] = compression[2];
+        bytes[33] = compression[3];
         let pixel_image_size: [u8; 4] = header.pixel_image_size.to_le_bytes();
         bytes[34] = pixel_image_size[0];
         bytes[35] = pixel_image_size[1];
-        bytes[36] = pixel_image_size[1];
-        bytes[37] = pixel_image_size[1];
+        bytes[36] = pixel_image_size[2];
+        bytes[37] = pixel_image_size[3];
         let hres: [u8; 4] = header.hres.to_le_bytes();
         bytes[38] = hres[0];
         bytes[39] = hres[1];
         bytes[40] = hres[2];
-        bytes[41] = hres[2];
+        bytes[41] = hres[3];
         let vres: [u8; 4] = header.vres.to_le_bytes();
         bytes[42] = vres[0];
         bytes[43] = vres[1];
         bytes[44] = vres[2];
-        bytes[45] = vres[2];
-        let mut bytes_vec: Vec<u8> = bytes.to_vec();
-        bytes_vec.append(&mut header.gap);
+        bytes[45] = vres[3];
+        let colors_used: [u8; 4] = header.colors_used.to_le_bytes();
+        bytes[46] = colors_used[0];
+        bytes[47] = colors_used[1];
+        bytes[48] = colors_used[2];
+        bytes[49] = colors_used[3];
+        let important_colors: [u8; 4] = header.important_colors.to_le_bytes();
+        bytes[50] = important_colors[0];
+        bytes[51] = important_colors[1];
+        bytes[52] = important_colors[2];
+        bytes[53] = important_colors[3];
+        let header_masks = &header.masks[..header.header_masks()];
+        let trailing_masks = &header.masks[..header.trailing_masks()];
+        let mut bytes_vec: Vec<u8> = Vec::with_capacity(
+            bytes.len() + (header_masks.len() + trailing_masks.len()) * 4 + header.gap.len(),
+        );
+        bytes_vec.extend_from_slice(&bytes);
+        for mask in header_masks {
+            bytes_vec.extend_from_slice(&mask.to_le_bytes());
+        }
+        if let Some(color_space) = &header.color_space {
+            color_space.write(header.header_size >= 124, &mut bytes_vec);
+        }
+        bytes_vec.extend_from_slice(&header.extra);
+        for mask in trailing_masks {
+            bytes_vec.extend_from_slice(&mask.to_le_bytes());
+        }
+        bytes_vec.extend_from_slice(&header.gap);
         bytes_vec
     }
 }
@@ -177,67 +1070,260 @@ impl Display for Header {
         writeln!(f, "Raw File Size: {:#?}", self.file_size)?;
         writeln!(f, "Reserved 1: {:#?}", self.reserved1)?;
         writeln!(f, "Reserved 2: {:#?}", self.reserved2)?;
-        writeln!(f, "Pixel Start Offset: {:#?}", self.offset)?;
+        writeln!(f, "Color Start Offset: {:#?}", self.offset)?;
         writeln!(f, "Header Size: {:#?}", self.header_size)?;
         writeln!(f, "Width: {:#?}", self.width)?;
         writeln!(f, "Height: {:#?}", self.height)?;
         writeln!(f, "Color Planes: {:#?}", self.color_planes)?;
-        writeln!(f, "Bits Per Pixel: {:#?}", self.bits_per_pixel)?;
+        writeln!(f, "Bits Per Color: {:#?}", self.bits_per_pixel)?;
         writeln!(f, "Compression enum: {:#?}", self.compression)?;
         writeln!(f, "Image size (pixels only): {:#?}", self.pixel_image_size)?;
         writeln!(f, "Hres: {:#?}", self.hres)?;
         writeln!(f, "Vres: {:#?}", self.vres)?;
+        writeln!(f, "Colors Used: {:#?}", self.colors_used)?;
+        writeln!(f, "Important Colors: {:#?}", self.important_colors)?;
+        if let Some(color_space) = &self.color_space {
+            writeln!(f, "Color Space Type: {:#x}", color_space.cs_type)?;
+            writeln!(f, "Profile Size: {:#?}", color_space.profile.len())?;
+        }
+        writeln!(f, "Extra Header Bytes: {:#?}", self.extra.len())?;
         writeln!(f, "Gap Length: {:#?}", self.gap.len())?;
         Ok(())
     }
 }
 
-#[derive(Debug)]
-struct BmpFile {
+#[derive(Debug, Clone)]
+pub struct BmpFile {
     header: Header,
-    pixels: Vec<Vec<Pixel>>,
+    /// Row-major pixel data without scanline padding, top row first.
+    pixels: Vec<Color>,
+    /// Anything stored after the pixel array, such as a V5 ICC profile.
+    trailer: Vec<u8>,
+    /// (x, y, width, height) of the rectangle [`Image`] operations are limited to, if any.
+    clip: Option<(usize, usize, usize, usize)>,
 }
 impl TryFrom<File> for BmpFile {
-    type Error = std::io::Error;
-    fn try_from(mut file: File) -> Result<BmpFile, std::io::Error> {
+    type Error = ImageError;
+    fn try_from(file: File) -> Result<BmpFile, ImageError> {
+        BmpFile::read_with_limit(file, DEFAULT_MAX_PIXEL_BYTES)
+    }
+}
+impl TryFrom<&[u8]> for BmpFile {
+    type Error = ImageError;
+    fn try_from(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        BmpFile::from_bytes(bytes)
+    }
+}
+impl BmpFile {
+    /// A blank `width`x`height` canvas filled with `color`, 24 bits deep unless `color` is
+    /// translucent.
+    pub fn new(width: usize, height: usize, color: Color) -> BmpFile {
+        BmpFile::from_pixels(width, height, vec![color; width * height])
+    }
+    /// A bitmap holding `pixels`, 32 bits deep if any of them is translucent and 24 otherwise.
+    fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> BmpFile {
+        let translucent = pixels.iter().any(|pixel| pixel.a != 255);
+        BmpFile {
+            header: Header::for_dimensions(width, height, if translucent { 32 } else { 24 }),
+            pixels,
+            trailer: Vec::new(),
+            clip: None,
+        }
+    }
+    /// Opens and decodes the bitmap at `path`.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::try_from(File::open(path)?)
+    }
+    /// Like [`BmpFile::open`], but rejects files whose headers disagree with each other or
+    /// with the file's length, listing every problem found. See [`BmpFile::from_bytes_strict`].
+    pub fn open_strict<P: AsRef<std::path::Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_bytes_strict(&std::fs::read(path)?)
+    }
+    /// Decodes `bytes` after checking the signature, that the offset and size fields agree
+    /// with the length of the data, that there is exactly one color plane and that the bit
+    /// depth is supported. Every violation is reported in one [`ImageError::Invalid`].
+    pub fn from_bytes_strict(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        let violations = Header::violations(bytes);
+        if !violations.is_empty() {
+            return Err(ImageError::Invalid(violations));
+        }
+        BmpFile::from_bytes(bytes)
+    }
+    /// Encodes the image and writes it to `path`, replacing any existing file.
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), ImageError> {
+        self.write_to(File::create(path)?)
+    }
+    /// Decodes a bitmap from any reader, such as stdin or a socket, reading it to the end.
+    pub fn read_from<R: Read>(reader: R) -> Result<BmpFile, ImageError> {
+        BmpFile::read_with_limit(reader, DEFAULT_MAX_PIXEL_BYTES)
+    }
+    /// Encodes the image into `writer`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), ImageError> {
+        writer.write_all(&self.to_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+    /// The complete encoded file. The offset and size fields are recomputed from the
+    /// pixel data rather than copied from the header that was read. If any pixel is
+    /// translucent and the header's format has no alpha channel, the file is written as
+    /// 32-bit BGRA instead so that no alpha is lost.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = self.header.clone();
+        if !header.stores_alpha() && self.pixels.iter().any(|pixel| pixel.a != 255) {
+            header.promote_to_bgra();
+        }
+        header.recompute_sizes();
+        let mut bytes: Vec<u8> = Vec::from(&header);
+        let header_len = bytes.len();
+        bytes.reserve(header.pixel_image_size as usize + self.trailer.len());
+        write_pixels(&self.pixels, &header, &mut bytes);
+        // Run-length encoded pixel arrays are only sized once they have been written.
+        let to_u32 = |len: usize| u32::try_from(len).unwrap_or(u32::MAX);
+        let image_size = to_u32(bytes.len() - header_len);
+        if !header.writes_core() {
+            LittleEndian::write_u32(&mut bytes[34..38], image_size);
+        }
+        if let Some(color_space) = header
+            .color_space
+            .as_ref()
+            .filter(|color_space| header.header_size >= 124 && !color_space.profile.is_empty())
+        {
+            let profile_offset = to_u32(bytes.len() - 14);
+            LittleEndian::write_u32(&mut bytes[126..130], profile_offset);
+            bytes.extend_from_slice(&color_space.profile);
+        }
+        bytes.extend_from_slice(&self.trailer);
+        let file_size = to_u32(bytes.len());
+        LittleEndian::write_u32(&mut bytes[2..6], file_size);
+        bytes
+    }
+    pub fn width(&self) -> usize {
+        self.header.width
+    }
+    pub fn height(&self) -> usize {
+        self.header.height
+    }
+    /// Decodes everything `reader` yields, refusing images whose decoded pixels would
+    /// take more than `max_pixel_bytes` of memory.
+    pub fn read_with_limit<R: Read>(
+        mut reader: R,
+        max_pixel_bytes: usize,
+    ) -> Result<BmpFile, ImageError> {
         let mut bytes: Vec<u8> = Vec::new();
-        file.read_to_end(&mut bytes).unwrap();
-        let header = Header::from(bytes[0..138].to_vec());
-        let fpp: usize = header.offset as usize;
-        let pixel_array: Vec<u8> = bytes[fpp..].to_vec();
-        let mut padding: usize = 0;
-        if header.width * 3 % 4 != 0 {
-            padding = 4 - header.width * 3 % 4
-        }
-        let mut pixels: Vec<Vec<Pixel>> = Vec::new();
-        for _ in 0..header.width + padding {
-            pixels.push(Vec::new());
-        }
-        let mut pixel_array_index: usize = 2;
-        for _ in 0..header.height {
-            for column in &mut pixels {
-                column.push(Pixel::ColorData(
-                    pixel_array[pixel_array_index - 2],
-                    pixel_array[pixel_array_index - 1],
-                    pixel_array[pixel_array_index],
-                ));
-                pixel_array_index += 3
+        reader.read_to_end(&mut bytes)?;
+        BmpFile::from_bytes_with_limit(&bytes, max_pixel_bytes)
+    }
+    /// Decodes a complete bitmap held in memory.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        BmpFile::from_bytes_with_limit(bytes, DEFAULT_MAX_PIXEL_BYTES)
+    }
+    /// Like [`BmpFile::from_bytes`], with the memory limit of [`BmpFile::read_with_limit`].
+    pub fn from_bytes_with_limit(
+        mut bytes: &[u8],
+        max_pixel_bytes: usize,
+    ) -> Result<BmpFile, ImageError> {
+        if let Some(offset) = bytes.get(10..14).map(LittleEndian::read_u32) {
+            if offset as usize > bytes.len() {
+                return Err(ImageError::OffsetOutOfBounds {
+                    offset: offset as usize,
+                    len: bytes.len(),
+                });
+            }
+        }
+        let file = bytes;
+        let mut header = Header::read_from(&mut bytes)?;
+        header.check_size(max_pixel_bytes)?;
+        let pixel_array: &[u8] = bytes;
+        let (pixels, consumed) = bytes_to_pixels(pixel_array, &header)?;
+        let mut trailer = pixel_array.get(consumed..).unwrap_or_default().to_vec();
+        header.take_profile(file, &mut trailer);
+        Ok(BmpFile {
+            header,
+            pixels,
+            trailer,
+            clip: None,
+        })
+    }
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+    /// Sets both resolutions from dots per inch, stored in the header as pixels per meter.
+    pub fn set_resolution(&mut self, dpi: u32) {
+        let pixels_per_meter = (dpi as f64 * INCHES_PER_METER).round() as i32;
+        self.header.hres = pixels_per_meter;
+        self.header.vres = pixels_per_meter;
+    }
+    /// Chooses the bit depth the image is saved at: 1, 4 or 8 for a paletted file, 16 for
+    /// 5-5-5 RGB, 24 for RGB or 32 for BGRA, always uncompressed. Paletted depths get a
+    /// palette of up to 2, 16 or 256 colors chosen by median cut from the pixels as they are
+    /// now; on saving, every pixel is written as its nearest palette entry. Translucent
+    /// images are still saved at 32 bits, see [`BmpFile::to_bytes`].
+    ///
+    /// ```no_run
+    /// use image_manip::BmpFile;
+    ///
+    /// let mut image = BmpFile::open("photo.bmp")?;
+    /// image.set_bit_depth(8)?;
+    /// image.save("photo-8bit.bmp")?;
+    /// # Ok::<(), image_manip::ImageError>(())
+    /// ```
+    pub fn set_bit_depth(&mut self, bits_per_pixel: u16) -> Result<(), ImageError> {
+        if !matches!(bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32) {
+            return Err(ImageError::UnsupportedBitDepth(bits_per_pixel));
+        }
+        let palette = if bits_per_pixel <= 8 {
+            let mut histogram: HashMap<Color, u32> = HashMap::new();
+            for pixel in &self.pixels {
+                *histogram.entry(pixel.with_alpha(255)).or_default() += 1;
             }
-            for column in &mut pixels[header.width..header.width + padding] {
-                column.push(Pixel::Padding);
-                pixel_array_index += 1
+            median_cut(&histogram, 1 << bits_per_pixel)
+        } else {
+            Vec::new()
+        };
+        let header = &mut self.header;
+        header.bits_per_pixel = bits_per_pixel;
+        header.compression = 0;
+        header.gap = palette
+            .iter()
+            .flat_map(|color| [color.b, color.g, color.r, 0])
+            .collect();
+        header.colors_used = palette.len() as u32;
+        header.important_colors = 0;
+        header.recompute_sizes();
+        Ok(())
+    }
+    /// Turns run-length encoding on save on or off: RLE8 for 8-bit images and RLE4 for 4-bit
+    /// ones, see [`BmpFile::set_bit_depth`]. Encoded images are always stored bottom-up, as
+    /// the format requires. Other depths cannot be run-length encoded and are refused.
+    pub fn set_run_length_encoding(&mut self, enabled: bool) -> Result<(), ImageError> {
+        let header = &mut self.header;
+        header.compression = match (enabled, header.bits_per_pixel) {
+            (false, _) => 0,
+            (true, 8) => 1,
+            (true, 4) => 2,
+            (true, bits_per_pixel) => {
+                return Err(ImageError::UnsupportedCompression {
+                    compression: 1,
+                    bits_per_pixel,
+                })
             }
+        };
+        if enabled {
+            header.top_down = false;
         }
-        Ok(BmpFile { header, pixels })
+        Ok(())
+    }
+    /// Width and height of the bitmap at `path`, reading only its headers: the color table
+    /// and anything else before the pixel array are left unread.
+    pub fn dimensions<P: AsRef<std::path::Path>>(path: P) -> Result<(usize, usize), ImageError> {
+        let mut file = File::open(path)?;
+        let header = Header::from(Header::read_headers(&mut file)?);
+        Ok((header.width, header.height))
     }
 }
 impl From<BmpFile> for Vec<u8> {
     fn from(file: BmpFile) -> Self {
-        let mut header: Vec<u8> = Vec::from(file.header);
-        let mut pixels: Vec<u8> = Pixel::pixel2d_to_bytes(file.pixels);
-
-        header.append(&mut pixels);
-        header
+        file.to_bytes()
     }
 }
 impl Display for BmpFile {
@@ -245,80 +1331,698 @@ impl Display for BmpFile {
         writeln!(f, "Begin BmpFile Headerdump")?;
         writeln!(f, "{}", self.header)?;
         writeln!(f, "Begin BmpFile Pixeldump\n")?;
-        for row in self.pixels.iter() {
-            for pixel in row.iter() {
-                if let Pixel::ColorData(_, _, _) = pixel {
-                    write!(f, "P")?
-                } else {
-                    writeln!(f, " Padding")?
-                }
-            }
-        }
+        write!(f, "{}", self.to_ascii(64))?;
         write!(f, "fileend")
     }
 }
-#[allow(dead_code)]
 impl BmpFile {
-    fn change_pixel(&mut self, x: usize, y: usize, color: Color) {
-        self.pixels[x][y] = Pixel::from(color);
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.header.width + x
     }
-    fn draw_vline(&mut self, pos: usize, thickness: usize, color: Color) {
-        for column in pos - (thickness / 2)..pos + (thickness / 2) {
-            for row in 0..self.header.height {
-                self.pixels[column][row] = Pixel::from(&color);
-            }
+    /// Runs `f` over every row along with its y coordinate, see [`for_each_row`].
+    fn for_each_row_mut<F>(&mut self, f: F)
+    where
+        F: Fn(usize, &mut [Color]) + Sync,
+    {
+        for_each_row(&mut self.pixels, self.header.width, f);
+    }
+    /// A copy of this image with new dimensions and pixel data, keeping the rest of the header.
+    fn with_pixels(&self, width: usize, height: usize, pixels: Vec<Color>) -> BmpFile {
+        let mut header = self.header.clone();
+        header.width = width;
+        header.height = height;
+        header.recompute_sizes();
+        BmpFile {
+            header,
+            pixels,
+            trailer: self.trailer.clone(),
+            clip: None,
+        }
+    }
+    /// Copies out the `width`x`height` region whose top-left corner is (x, y), failing with
+    /// [`ImageError::OutOfBounds`] if any of it lies outside the image.
+    pub fn crop(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<BmpFile, ImageError> {
+        if width == 0 || height == 0 {
+            return Ok(self.with_pixels(width, height, Vec::new()));
+        }
+        // Saturating keeps corners past usize::MAX out of bounds instead of overflowing.
+        self.check_bounds(x.saturating_add(width - 1), y.saturating_add(height - 1))?;
+        Ok(self.region(x, y, width, height))
+    }
+    fn region(&self, x: usize, y: usize, width: usize, height: usize) -> BmpFile {
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in y..y + height {
+            let start = self.index(x, row);
+            pixels.extend_from_slice(&self.pixels[start..start + width]);
+        }
+        self.with_pixels(width, height, pixels)
+    }
+    /// The pixel at a possibly out-of-range position, resolved through `border`. Replicated
+    /// and wrapped borders of an empty image read as transparent.
+    fn pixel_at(&self, x: isize, y: isize, border: BorderMode) -> Color {
+        match (
+            border.resolve(x, self.header.width),
+            border.resolve(y, self.header.height),
+        ) {
+            (Some(x), Some(y)) => self.pixels[self.index(x, y)],
+            _ => match border {
+                BorderMode::Constant(color) => color,
+                _ => Color::TRANSPARENT,
+            },
         }
     }
-    fn draw_hline(&mut self, pos: usize, thickness: usize, color: Color) {
-        for row in pos - (thickness / 2)..pos + (thickness / 2) {
-            for column in 0..self.header.width {
-                self.pixels[column][row] = Pixel::from(&color);
+    /// Samples the image at a fractional position, blending the four nearest pixels.
+    fn sample_bilinear(&self, x: f64, y: f64, border: BorderMode) -> Color {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let corners = [
+            (self.pixel_at(x0, y0, border), (1.0 - fx) * (1.0 - fy)),
+            (self.pixel_at(x0 + 1, y0, border), fx * (1.0 - fy)),
+            (self.pixel_at(x0, y0 + 1, border), (1.0 - fx) * fy),
+            (self.pixel_at(x0 + 1, y0 + 1, border), fx * fy),
+        ];
+        let channel = |select: fn(&Color) -> u8| {
+            let value: f64 = corners
+                .iter()
+                .map(|(pixel, weight)| select(pixel) as f64 * weight)
+                .sum();
+            clamp(value.round(), 0.0, 255.0) as u8
+        };
+        Color {
+            b: channel(|pixel| pixel.b),
+            g: channel(|pixel| pixel.g),
+            r: channel(|pixel| pixel.r),
+            a: channel(|pixel| pixel.a),
+        }
+    }
+    fn out_of_bounds(&self, x: usize, y: usize) -> ImageError {
+        ImageError::OutOfBounds {
+            x,
+            y,
+            width: self.header.width,
+            height: self.header.height,
+        }
+    }
+    fn check_bounds(&self, x: usize, y: usize) -> Result<(), ImageError> {
+        if x < self.header.width && y < self.header.height {
+            Ok(())
+        } else {
+            Err(self.out_of_bounds(x, y))
+        }
+    }
+    /// The color at (x, y), or `None` outside the image.
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        (x < self.header.width && y < self.header.height).then(|| self.pixels[self.index(x, y)])
+    }
+    /// Sets the pixel at (x, y) to `color`, failing with [`ImageError::OutOfBounds`] outside
+    /// the image.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) -> Result<(), ImageError> {
+        self.check_bounds(x, y)?;
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+        Ok(())
+    }
+    /// Limits every later [`Image`] drawing and filter operation on this bitmap to the
+    /// `width`x`height` rectangle whose top-left corner is (x, y), leaving pixels outside it
+    /// untouched. The rectangle may extend past the image. Direct pixel access such as
+    /// [`BmpFile::set_pixel`] and [`BmpFile::pixels_mut`] ignores the clip.
+    pub fn set_clip_rect(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.clip = Some((x, y, width, height));
+    }
+    /// Removes the clip set by [`BmpFile::set_clip_rect`], so operations reach the whole
+    /// image again.
+    pub fn clear_clip_rect(&mut self) {
+        self.clip = None;
+    }
+    /// The clip rectangle as (x, y, width, height), if one is set.
+    pub fn clip_rect(&self) -> Option<(usize, usize, usize, usize)> {
+        self.clip
+    }
+    /// The columns and rows [`Image`] operations may change: the clip rectangle cut down to
+    /// the image, or the whole image without one.
+    pub(crate) fn clip_ranges(&self) -> (Range<usize>, Range<usize>) {
+        let (width, height) = (self.header.width, self.header.height);
+        let (x, y, clip_width, clip_height) = self.clip.unwrap_or((0, 0, width, height));
+        (
+            x.min(width)..x.saturating_add(clip_width).min(width),
+            y.min(height)..y.saturating_add(clip_height).min(height),
+        )
+    }
+    /// Same as [`BmpFile::set_pixel`].
+    pub fn change_pixel(&mut self, x: usize, y: usize, color: Color) -> Result<(), ImageError> {
+        self.set_pixel(x, y, color)
+    }
+    /// Every pixel, row by row from the top left. Scanline padding is not included.
+    pub fn pixels(&self) -> impl Iterator<Item = &Color> + '_ {
+        self.pixels.iter()
+    }
+    /// Every pixel in the order of [`BmpFile::pixels`], for editing in place.
+    pub fn pixels_mut(&mut self) -> impl Iterator<Item = &mut Color> + '_ {
+        self.pixels.iter_mut()
+    }
+    /// Every pixel along with its (x, y) position, in the order of [`BmpFile::pixels`].
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &Color)> + '_ {
+        let width = self.header.width.max(1);
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(index, color)| (index % width, index / width, color))
+    }
+    /// Every pixel along with its (x, y) position, for editing in place.
+    pub fn enumerate_pixels_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (usize, usize, &mut Color)> + '_ {
+        let width = self.header.width.max(1);
+        self.pixels
+            .iter_mut()
+            .enumerate()
+            .map(move |(index, color)| (index % width, index / width, color))
+    }
+    /// A copy with every pixel replaced by `f(x, y, color)`, see [`Image::update`].
+    pub fn map_pixels<F>(&self, f: F) -> BmpFile
+    where
+        F: Fn(usize, usize, Color) -> Color + Sync,
+    {
+        let mut copy = self.clone();
+        copy.update(f);
+        copy
+    }
+    /// Calls `f` with the position of every pixel and a mutable reference to it, in the order
+    /// of [`BmpFile::pixels`].
+    pub fn for_each_pixel_mut<F: FnMut(usize, usize, &mut Color)>(&mut self, mut f: F) {
+        self.enumerate_pixels_mut()
+            .for_each(|(x, y, color)| f(x, y, color));
+    }
+    /// Every row from top to bottom as a slice of its pixels. Unlike [`ReadImage::rows`], this
+    /// borrows the pixels instead of copying them.
+    pub fn rows(&self) -> impl Iterator<Item = &[Color]> + '_ {
+        self.pixels.chunks_exact(self.header.width.max(1))
+    }
+    /// Every row from top to bottom, for editing in place.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [Color]> + '_ {
+        self.pixels.chunks_exact_mut(self.header.width.max(1))
+    }
+    /// Every column from left to right, each yielding its pixels from top to bottom. Columns
+    /// are not contiguous in memory, so they come as iterators rather than slices.
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &Color> + '_> + '_ {
+        let width = self.header.width;
+        (0..width).map(move |x| self.pixels[x..].iter().step_by(width))
+    }
+    /// Trims rows and columns on every edge that match `background` within `tolerance`.
+    /// An image that is background everywhere is returned unchanged.
+    pub fn autocrop(&self, background: Color, tolerance: u8) -> BmpFile {
+        let (width, height) = (self.header.width, self.header.height);
+        let is_background = |pixel: &Color| pixel.within_tolerance(&background, tolerance);
+        let row_is_background = |y: usize| {
+            self.pixels[y * width..(y + 1) * width]
+                .iter()
+                .all(is_background)
+        };
+        let Some(top) = (0..height).find(|&y| !row_is_background(y)) else {
+            return self.clone();
+        };
+        let bottom = (0..height).rfind(|&y| !row_is_background(y)).unwrap_or(top);
+        let column_is_background =
+            |x: usize| (top..=bottom).all(|y| is_background(&self.pixels[self.index(x, y)]));
+        let left = (0..width).find(|&x| !column_is_background(x)).unwrap_or(0);
+        let right = (0..width)
+            .rfind(|&x| !column_is_background(x))
+            .unwrap_or(left);
+        self.region(left, top, right - left + 1, bottom - top + 1)
+    }
+    /// Bilinear resampling to a new size, see [`ResizeMode::Bilinear`].
+    pub fn resize(&self, width: usize, height: usize) -> BmpFile {
+        self.resize_with(width, height, ResizeMode::Bilinear)
+    }
+    pub fn resize_with(&self, width: usize, height: usize, mode: ResizeMode) -> BmpFile {
+        let enlarging = width >= self.header.width && height >= self.header.height;
+        match mode {
+            ResizeMode::Area if !enlarging => self.resize_area(width, height),
+            _ => self.resize_bilinear(width, height),
+        }
+    }
+    /// Averages every source pixel under each destination pixel, weighting partially
+    /// covered source pixels by the covered fraction.
+    fn resize_area(&self, width: usize, height: usize) -> BmpFile {
+        let scale_x = self.header.width as f64 / width as f64;
+        let scale_y = self.header.height as f64 / height as f64;
+        // Source index range and per-index coverage of the span [start, end).
+        let spans = |count: usize, scale: f64, limit: usize| -> Vec<Vec<(usize, f64)>> {
+            (0..count)
+                .map(|i| {
+                    let (start, end) =
+                        (i as f64 * scale, ((i + 1) as f64 * scale).min(limit as f64));
+                    (start.floor() as usize..(end.ceil() as usize).min(limit))
+                        .map(|source| {
+                            let covered = end.min(source as f64 + 1.0) - start.max(source as f64);
+                            (source, covered)
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        let columns = spans(width, scale_x, self.header.width);
+        let rows = spans(height, scale_y, self.header.height);
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in &rows {
+            for column in &columns {
+                let mut sums = [0.0; 4];
+                let mut total = 0.0;
+                for &(y, weight_y) in row {
+                    for &(x, weight_x) in column {
+                        let pixel = self.pixels[self.index(x, y)];
+                        let weight = weight_x * weight_y;
+                        sums[0] += pixel.b as f64 * weight;
+                        sums[1] += pixel.g as f64 * weight;
+                        sums[2] += pixel.r as f64 * weight;
+                        sums[3] += pixel.a as f64 * weight;
+                        total += weight;
+                    }
+                }
+                let [b, g, r, a] = sums.map(|sum| clamp((sum / total).round(), 0.0, 255.0) as u8);
+                pixels.push(Color { b, g, r, a });
             }
         }
+        self.with_pixels(width, height, pixels)
     }
-    fn mirror_horizontal_left(&mut self) {
-        for y in 0..self.header.height {
-            for i in 0..self.header.width / 2 {
-                self.pixels[self.header.width - i - 1][y] = self.pixels[i][y].clone();
+    /// Samples past the edges use [`BorderMode::Replicate`].
+    fn resize_bilinear(&self, width: usize, height: usize) -> BmpFile {
+        let scale_x = self.header.width as f64 / width as f64;
+        let scale_y = self.header.height as f64 / height as f64;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let source_x = (x as f64 + 0.5) * scale_x - 0.5;
+                let source_y = (y as f64 + 0.5) * scale_y - 0.5;
+                pixels.push(self.sample_bilinear(source_x, source_y, BorderMode::Replicate));
             }
         }
+        self.with_pixels(width, height, pixels)
+    }
+    /// Shrinks (or grows) the image so its longest side is `max_dim`, keeping the aspect ratio.
+    /// Uses [`ResizeMode::Area`] to avoid aliasing when shrinking.
+    pub fn thumbnail(&self, max_dim: usize) -> BmpFile {
+        let (width, height) = (self.header.width as f64, self.header.height as f64);
+        let scale = max_dim as f64 / width.max(height);
+        let target = |side: f64| ((side * scale).round() as usize).max(1);
+        self.resize_with(target(width), target(height), ResizeMode::Area)
+    }
+    pub fn fade_copied(&self, direction: FadeDirection, from: f64, to: f64) -> BmpFile {
+        let mut copy = self.clone();
+        copy.fade(direction, from, to);
+        copy
+    }
+    /// Rotates counterclockwise about the center, growing the canvas to fit the result and
+    /// filling the uncovered corners with `background`.
+    pub fn rotate_degrees(&self, deg: f64, background: Color) -> BmpFile {
+        self.rotate(deg, BorderMode::Constant(background), true)
+    }
+    /// Rotates counterclockwise about the center, sampling outside the source through
+    /// `border`. Without `expand` the canvas keeps its size and the corners are clipped.
+    pub fn rotate(&self, deg: f64, border: BorderMode, expand: bool) -> BmpFile {
+        let (width, height) = (self.header.width, self.header.height);
+        let (sin, cos) = deg.to_radians().sin_cos();
+        let (new_width, new_height) = if expand {
+            let fit = |a: usize, b: usize| {
+                ((a as f64 * cos.abs() + b as f64 * sin.abs()) - 1e-9)
+                    .ceil()
+                    .max(1.0) as usize
+            };
+            (fit(width, height), fit(height, width))
+        } else {
+            (width, height)
+        };
+        let (center_x, center_y) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+        let (new_center_x, new_center_y) = (
+            (new_width as f64 - 1.0) / 2.0,
+            (new_height as f64 - 1.0) / 2.0,
+        );
+        // With y pointing down, this matrix turns the image counterclockwise.
+        let matrix = [
+            cos,
+            sin,
+            new_center_x - cos * center_x - sin * center_y,
+            -sin,
+            cos,
+            new_center_y + sin * center_x - cos * center_y,
+        ];
+        self.warp_affine(matrix, new_width, new_height, border)
     }
-    fn vertical_fade_left(&mut self) {
-        for y in 0..self.header.height {
-            for x in 0..self.header.width {
-                let factor = x as f64 / (self.header.width - 1) as f64;
-                let color = Color::from(&self.pixels[x][y]) * factor;
-                self.pixels[x][y] = Pixel::from(color);
+    /// Applies the 2x3 affine `matrix` `[a, b, c, d, e, f]`, which maps a source position
+    /// (x, y) to (a*x + b*y + c, d*x + e*y + f) in an `out_w`x`out_h` output. Each output
+    /// pixel is bilinearly sampled from the inverse-mapped source position through `border`.
+    /// A singular matrix has no inverse, so every output pixel is then read as if it fell
+    /// outside the source.
+    pub fn warp_affine(
+        &self,
+        matrix: [f64; 6],
+        out_w: usize,
+        out_h: usize,
+        border: BorderMode,
+    ) -> BmpFile {
+        let [a, b, c, d, e, f] = matrix;
+        let determinant = a * e - b * d;
+        let mut pixels = Vec::with_capacity(out_w * out_h);
+        if determinant.abs() < f64::EPSILON {
+            pixels.resize(out_w * out_h, self.pixel_at(-1, -1, border));
+            return self.with_pixels(out_w, out_h, pixels);
+        }
+        let (ia, ib, id, ie) = (
+            e / determinant,
+            -b / determinant,
+            -d / determinant,
+            a / determinant,
+        );
+        for y in 0..out_h {
+            for x in 0..out_w {
+                let (dx, dy) = (x as f64 - c, y as f64 - f);
+                let source_x = ia * dx + ib * dy;
+                let source_y = id * dx + ie * dy;
+                pixels.push(self.sample_bilinear(source_x, source_y, border));
             }
         }
+        self.with_pixels(out_w, out_h, pixels)
     }
 
-    fn make_red(&mut self) {
-        for y in 0..self.header.height {
-            for x in 0..self.header.width {
-                self.pixels[x][y] = Pixel::from(Color(0, 0, 255));
+    pub fn invert_copied(&self) -> BmpFile {
+        let mut copy = self.clone();
+        copy.invert();
+        copy
+    }
+    pub fn grayscale_copied(&self) -> BmpFile {
+        let mut copy = self.clone();
+        copy.grayscale();
+        copy
+    }
+    pub fn grayscale_with_copied(&self, luma: Luma) -> BmpFile {
+        let mut copy = self.clone();
+        copy.grayscale_with(luma);
+        copy
+    }
+    pub fn diff(&self, other: &BmpFile) -> Option<DiffStats> {
+        if self.header.width != other.header.width || self.header.height != other.header.height {
+            return None;
+        }
+        let mut max_channel_difference = 0;
+        let mut total_difference: u64 = 0;
+        let mut differing_pixels = 0;
+        for (a, b) in self.pixels.iter().zip(&other.pixels) {
+            let differences = [a.b.abs_diff(b.b), a.g.abs_diff(b.g), a.r.abs_diff(b.r)];
+            let pixel_max = differences.into_iter().max().unwrap_or(0);
+            if pixel_max > 0 {
+                differing_pixels += 1;
             }
+            max_channel_difference = max_channel_difference.max(pixel_max);
+            total_difference += differences.iter().map(|&d| d as u64).sum::<u64>();
         }
+        let channel_count = (self.pixels.len() * 3).max(1);
+        Some(DiffStats {
+            max_channel_difference,
+            mean_absolute_error: total_difference as f64 / channel_count as f64,
+            differing_pixels,
+        })
     }
-    fn make_blue(&mut self) {
-        for y in 0..self.header.height {
-            for x in 0..self.header.width {
-                self.pixels[x][y] = Pixel::from(Color(255, 0, 0));
+    pub fn is_equal_within(&self, other: &BmpFile, tolerance: u8) -> bool {
+        self.diff(other)
+            .is_some_and(|stats| stats.max_channel_difference <= tolerance)
+    }
+    /// Renders a preview `cols` characters wide, using denser characters for brighter areas.
+    /// Cells are twice as tall as they are wide to make up for the shape of terminal glyphs.
+    pub fn to_ascii(&self, cols: usize) -> String {
+        const RAMP: &[u8] = b" .:-=+*#%@";
+        let (width, height) = (self.header.width, self.header.height);
+        let cols = cols.clamp(1, width.max(1));
+        let cell_width = width as f64 / cols as f64;
+        let rows = ((height as f64 / (cell_width * 2.0)).round() as usize).max(1);
+        let cell_height = height as f64 / rows as f64;
+        let mut result = String::with_capacity((cols + 1) * rows);
+        for row in 0..rows {
+            let y_range = (row as f64 * cell_height) as usize
+                ..(((row + 1) as f64 * cell_height) as usize)
+                    .max(row + 1)
+                    .min(height);
+            for col in 0..cols {
+                let x_range = (col as f64 * cell_width) as usize
+                    ..(((col + 1) as f64 * cell_width) as usize)
+                        .max(col + 1)
+                        .min(width);
+                let mut total = 0;
+                let mut count = 0;
+                for y in y_range.clone() {
+                    for x in x_range.clone() {
+                        total += self.pixels[self.index(x, y)].luminance() as usize;
+                        count += 1;
+                    }
+                }
+                let luminance = total / count.max(1);
+                result.push(RAMP[luminance * (RAMP.len() - 1) / 255] as char);
             }
+            result.push('\n');
+        }
+        result
+    }
+    pub fn blur_copied(&self, radius: usize) -> BmpFile {
+        let mut copy = self.clone();
+        copy.blur(radius);
+        copy
+    }
+    /// Grayscale edge map holding the Sobel gradient magnitude of the luminance.
+    /// Uses [`BorderMode::Replicate`] at the edges.
+    pub fn sobel(&self) -> BmpFile {
+        const HORIZONTAL: [f64; 9] = [-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0];
+        const VERTICAL: [f64; 9] = [-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0];
+        let (width, height) = (self.header.width, self.header.height);
+        let luminance: Vec<f64> = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel.luminance() as f64)
+            .collect();
+        let border = BorderMode::Replicate;
+        let gx = convolve_plane(&luminance, width, height, &HORIZONTAL, 3, border, 0.0);
+        let gy = convolve_plane(&luminance, width, height, &VERTICAL, 3, border, 0.0);
+        let pixels = gx
+            .iter()
+            .zip(&gy)
+            .zip(&self.pixels)
+            .map(|((gx, gy), pixel)| {
+                let magnitude = clamp((gx * gx + gy * gy).sqrt().round(), 0.0, 255.0) as u8;
+                Color::gray(magnitude).with_alpha(pixel.a)
+            })
+            .collect();
+        self.with_pixels(width, height, pixels)
+    }
+    /// Red, green and blue planes, each `width * height` bytes in pixel order.
+    pub fn split_channels(&self) -> [Vec<u8>; 3] {
+        [
+            self.pixels.iter().map(|pixel| pixel.r).collect(),
+            self.pixels.iter().map(|pixel| pixel.g).collect(),
+            self.pixels.iter().map(|pixel| pixel.b).collect(),
+        ]
+    }
+    /// Builds a 24-bit image from red, green and blue planes laid out like [`BmpFile::split_channels`].
+    pub fn merge_channels(
+        width: usize,
+        height: usize,
+        r: &[u8],
+        g: &[u8],
+        b: &[u8],
+    ) -> Result<BmpFile, ImageError> {
+        let header = Header::for_dimensions(width, height, 24);
+        header.check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let len = width * height;
+        if let Some(found) = [r.len(), g.len(), b.len()]
+            .into_iter()
+            .find(|&found| found != len)
+        {
+            return Err(ImageError::ChannelLength {
+                expected: len,
+                found,
+            });
+        }
+        let pixels = (0..len)
+            .map(|index| Color {
+                b: b[index],
+                g: g[index],
+                r: r[index],
+                a: 255,
+            })
+            .collect();
+        Ok(BmpFile {
+            header,
+            pixels,
+            trailer: Vec::new(),
+            clip: None,
+        })
+    }
+}
+
+/// Outcome of [`process_dir`].
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Number of bitmaps that were loaded, processed and written.
+    pub processed: usize,
+    /// Bitmaps that could not be read or written, with the error for each.
+    pub failed: Vec<(PathBuf, ImageError)>,
+}
+
+/// Loads every `.bmp` file in `input_dir`, applies `op` and writes the result under the same
+/// name in `output_dir`, creating it if needed. Other files are skipped. A failure on one
+/// file is recorded in the report and does not stop the run; only problems with the
+/// directories themselves return an error.
+pub fn process_dir<P, Q, F>(input_dir: P, output_dir: Q, op: F) -> Result<BatchReport, ImageError>
+where
+    P: AsRef<std::path::Path>,
+    Q: AsRef<std::path::Path>,
+    F: Fn(&mut BmpFile),
+{
+    std::fs::create_dir_all(&output_dir)?;
+    let mut report = BatchReport::default();
+    for entry in std::fs::read_dir(input_dir)? {
+        let path = entry?.path();
+        let is_bmp = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("bmp"));
+        if !is_bmp || !path.is_file() {
+            continue;
         }
+        let result = File::open(&path)
+            .map_err(ImageError::from)
+            .and_then(BmpFile::try_from)
+            .and_then(|mut bmp| {
+                op(&mut bmp);
+                let output = output_dir
+                    .as_ref()
+                    .join(path.file_name().unwrap_or_default());
+                File::create(output)?.write_all(&Vec::from(bmp))?;
+                Ok(())
+            });
+        match result {
+            Ok(()) => report.processed += 1,
+            Err(error) => report.failed.push((path, error)),
+        }
+    }
+    Ok(report)
+}
+
+/// Runs `f` over every `width`-long row of `data` along with its y coordinate.
+/// With the `rayon` feature the rows are spread over rayon's thread pool.
+fn for_each_row<T, F>(data: &mut [T], width: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    let width = width.max(1);
+    #[cfg(feature = "rayon")]
+    data.par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+    #[cfg(not(feature = "rayon"))]
+    for (y, row) in data.chunks_mut(width).enumerate() {
+        f(y, row);
     }
 }
 
-pub fn test() {
-    let file_name = "bear.bmp";
-    let path_str = &("src/".to_owned() + file_name);
-    let path = Path::new(path_str);
+/// Convolves a single `width`x`height` plane with a square kernel. Samples that `border`
+/// places outside the plane read as `outside`.
+fn convolve_plane(
+    plane: &[f64],
+    width: usize,
+    height: usize,
+    kernel: &[f64],
+    size: usize,
+    border: BorderMode,
+    outside: f64,
+) -> Vec<f64> {
+    let radius = (size / 2) as isize;
+    let mut result = vec![0.0; plane.len()];
+    for_each_row(&mut result, width, |y, row| {
+        for (x, output) in row.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for (ky, kernel_row) in kernel.chunks(size).enumerate() {
+                let sy = border.resolve(y as isize + ky as isize - radius, height);
+                for (kx, weight) in kernel_row.iter().enumerate() {
+                    let sx = border.resolve(x as isize + kx as isize - radius, width);
+                    let value = match (sx, sy) {
+                        (Some(sx), Some(sy)) => plane[sy * width + sx],
+                        _ => outside,
+                    };
+                    sum += value * weight;
+                }
+            }
+            *output = sum;
+        }
+    });
+    result
+}
 
-    let file = File::open(path).unwrap();
-    let mut bmp = BmpFile::try_from(file).unwrap();
-    bmp.draw_hline(10, 10, Color(255, 255, 255));
-    let bytes = Vec::from(bmp);
+/// Rounds a channel value to the nearest of `levels` evenly spaced values in 0..=255.
+fn quantize_channel(value: f64, levels: u8) -> u8 {
+    let steps = levels.max(2) as f64 - 1.0;
+    let level = (clamp(value, 0.0, 255.0) / 255.0 * steps).round();
+    (level * 255.0 / steps).round() as u8
+}
 
-    let mut new_file = File::create("src/manipulated-".to_owned() + file_name).unwrap();
-    new_file.write_all(&bytes).unwrap();
+/// Up to `count` colors chosen by median cut over `histogram`, which maps each distinct
+/// color to how often it occurs. The box of colors with the widest channel range is split at
+/// its weighted median until there are `count` boxes, and each box contributes its weighted
+/// mean. Histograms with at most `count` colors come back exactly.
+fn median_cut(histogram: &HashMap<Color, u32>, count: usize) -> Vec<Color> {
+    let channel = |color: &Color, index: usize| [color.b(), color.g(), color.r()][index];
+    let mut colors: Vec<(Color, u32)> = histogram.iter().map(|(&c, &n)| (c, n)).collect();
+    // Sorting keeps the result independent of the map's iteration order.
+    colors.sort_by_key(|(color, _)| (color.b(), color.g(), color.r()));
+    let mut boxes = vec![colors];
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .flat_map(|(i, colors)| {
+                (0..3).map(move |index| {
+                    let values = colors.iter().map(|(color, _)| channel(color, index));
+                    let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                    (range, i, index)
+                })
+            })
+            .max_by_key(|&(range, _, _)| range);
+        let Some((_, i, index)) = widest else {
+            break;
+        };
+        let colors = &mut boxes[i];
+        colors.sort_by_key(|(color, _)| channel(color, index));
+        let total: u64 = colors.iter().map(|&(_, n)| n as u64).sum();
+        let mut seen = 0;
+        let median = colors
+            .iter()
+            .position(|&(_, n)| {
+                seen += n as u64;
+                seen * 2 >= total
+            })
+            .unwrap_or(0);
+        let split = (median + 1).clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(upper);
+    }
+    boxes
+        .iter()
+        .filter(|colors| !colors.is_empty())
+        .map(|colors| {
+            let total: u64 = colors.iter().map(|&(_, n)| n as u64).sum::<u64>().max(1);
+            let mean = |index| {
+                let sum: u64 = colors
+                    .iter()
+                    .map(|(color, n)| channel(color, index) as u64 * *n as u64)
+                    .sum();
+                ((sum + total / 2) / total) as u8
+            };
+            Color::rgb(mean(2), mean(1), mean(0))
+        })
+        .collect()
 }
@@ -1,3 +0,0 @@
-fn main() {
-    image_manip::test();
-}
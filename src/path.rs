@@ -0,0 +1,144 @@
+//! Vector outlines built from lines and Bézier curves, which [`Image::stroke_path`] and
+//! [`Image::fill_path`] draw.
+//!
+//! Curves are flattened into straight segments as they are added, split finely enough that
+//! none strays more than a tenth of a pixel from the true curve.
+//!
+//! [`Image::stroke_path`]: crate::Image::stroke_path
+//! [`Image::fill_path`]: crate::Image::fill_path
+
+use crate::shape::Point;
+
+const TOLERANCE: f64 = 0.1;
+
+/// A shape made of one or more subpaths, each a run of connected segments that may be
+/// closed back to its start. Coordinates are in pixels with whole numbers on pixel centers,
+/// as for the stroke methods.
+///
+/// ```
+/// use image_manip::Path;
+///
+/// let heart = Path::new()
+///     .move_to(50.0, 30.0)
+///     .cubic_to(50.0, 10.0, 10.0, 10.0, 10.0, 35.0)
+///     .cubic_to(10.0, 60.0, 50.0, 75.0, 50.0, 90.0)
+///     .cubic_to(50.0, 75.0, 90.0, 60.0, 90.0, 35.0)
+///     .cubic_to(90.0, 10.0, 50.0, 10.0, 50.0, 30.0)
+///     .close();
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Path {
+    pub(crate) subpaths: Vec<Subpath>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Subpath {
+    pub(crate) points: Vec<Point>,
+    pub(crate) closed: bool,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+    /// Starts a new subpath at (x, y).
+    pub fn move_to(mut self, x: f64, y: f64) -> Path {
+        self.subpaths.push(Subpath {
+            points: vec![(x, y)],
+            closed: false,
+        });
+        self
+    }
+    /// A straight segment from the current point to (x, y). Without a current point this
+    /// starts a subpath there instead.
+    pub fn line_to(mut self, x: f64, y: f64) -> Path {
+        match self.open_subpath() {
+            Some(subpath) => subpath.points.push((x, y)),
+            None => return self.move_to(x, y),
+        }
+        self
+    }
+    /// A quadratic Bézier curve from the current point to (x, y), pulled towards the control
+    /// point (cx, cy).
+    pub fn quad_to(mut self, cx: f64, cy: f64, x: f64, y: f64) -> Path {
+        let Some(subpath) = self.open_subpath() else {
+            return self.move_to(x, y);
+        };
+        let start = *subpath.points.last().unwrap_or(&(x, y));
+        let control = (cx, cy);
+        let end = (x, y);
+        let steps = segments(2, &[start, control, end]);
+        subpath.points.extend((1..=steps).map(|i| {
+            let t = i as f64 / steps as f64;
+            let u = 1.0 - t;
+            let point = |a: f64, b: f64, c: f64| u * u * a + 2.0 * u * t * b + t * t * c;
+            (
+                point(start.0, control.0, end.0),
+                point(start.1, control.1, end.1),
+            )
+        }));
+        self
+    }
+    /// A cubic Bézier curve from the current point to (x, y), leaving towards (c1x, c1y) and
+    /// arriving from the direction of (c2x, c2y).
+    pub fn cubic_to(mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) -> Path {
+        let Some(subpath) = self.open_subpath() else {
+            return self.move_to(x, y);
+        };
+        let start = *subpath.points.last().unwrap_or(&(x, y));
+        let (first, second, end) = ((c1x, c1y), (c2x, c2y), (x, y));
+        let steps = segments(3, &[start, first, second, end]);
+        subpath.points.extend((1..=steps).map(|i| {
+            let t = i as f64 / steps as f64;
+            let u = 1.0 - t;
+            let point = |a: f64, b: f64, c: f64, d: f64| {
+                u * u * u * a + 3.0 * u * u * t * b + 3.0 * u * t * t * c + t * t * t * d
+            };
+            (
+                point(start.0, first.0, second.0, end.0),
+                point(start.1, first.1, second.1, end.1),
+            )
+        }));
+        self
+    }
+    /// Closes the current subpath with a segment back to its start. Drawing after this
+    /// starts a new subpath from the same start point.
+    pub fn close(mut self) -> Path {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.closed = true;
+        }
+        self
+    }
+    /// The subpath new segments extend, starting a fresh one at the start of the last
+    /// subpath if that was closed.
+    fn open_subpath(&mut self) -> Option<&mut Subpath> {
+        let last = self.subpaths.last()?;
+        if last.closed {
+            let start = last.points[0];
+            self.subpaths.push(Subpath {
+                points: vec![start],
+                closed: false,
+            });
+        }
+        self.subpaths.last_mut()
+    }
+}
+
+/// How many straight segments a Bézier curve of `degree` with control points `points` needs
+/// to stay within [`TOLERANCE`] of the curve, by Wang's formula.
+fn segments(degree: usize, points: &[Point]) -> usize {
+    let largest_second_difference = points
+        .windows(3)
+        .map(|w| {
+            let x = w[0].0 - 2.0 * w[1].0 + w[2].0;
+            let y = w[0].1 - 2.0 * w[1].1 + w[2].1;
+            x.hypot(y)
+        })
+        .fold(0.0, f64::max);
+    let factor = (degree * (degree - 1)) as f64 / 8.0;
+    ((factor * largest_second_difference / TOLERANCE)
+        .sqrt()
+        .ceil()
+        .min(1000.0) as usize)
+        .max(1)
+}
@@ -0,0 +1,400 @@
+//! PNG import and export, enabled by the `png` feature.
+//!
+//! Every color type, bit depth and Adam7 interlacing can be read, with 16-bit samples
+//! reduced to 8 bits. Images are written as 8-bit RGB, or RGBA when any pixel is not fully
+//! opaque, and the resolution travels in a `pHYs` chunk.
+
+use crate::zlib;
+use crate::{BmpFile, Color, Header, ImageError, DEFAULT_MAX_PIXEL_BYTES};
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// First column, first row, column step and row step of each Adam7 pass.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "PNG",
+        reason: reason.to_string(),
+    }
+}
+
+/// One pass over the image: the pixels at `x0 + i * dx`, `y0 + j * dy` for `i` below
+/// `width` and `j` below `height`.
+struct Pass {
+    x0: usize,
+    y0: usize,
+    dx: usize,
+    dy: usize,
+    width: usize,
+    height: usize,
+}
+
+/// The fields of an `IHDR` chunk.
+struct ImageHeader {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+impl ImageHeader {
+    fn parse(data: &[u8]) -> Result<ImageHeader, ImageError> {
+        let [w0, w1, w2, w3, h0, h1, h2, h3, depth, color_type, 0, 0, interlace] = *data else {
+            return Err(malformed("IHDR chunk is malformed"));
+        };
+        let valid_depths: &[u8] = match color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => &[],
+        };
+        if !valid_depths.contains(&depth) || interlace > 1 {
+            return Err(malformed(
+                "unsupported color type, bit depth or interlace method",
+            ));
+        }
+        Ok(ImageHeader {
+            width: u32::from_be_bytes([w0, w1, w2, w3]) as usize,
+            height: u32::from_be_bytes([h0, h1, h2, h3]) as usize,
+            depth,
+            color_type,
+            interlaced: interlace == 1,
+        })
+    }
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.channels() * self.depth as usize).div_ceil(8)
+    }
+    /// Distance in bytes to the corresponding byte of the previous pixel, at least one.
+    fn filter_step(&self) -> usize {
+        (self.channels() * self.depth as usize).div_ceil(8)
+    }
+    /// The passes the pixel data is split into, skipping empty ones.
+    fn passes(&self) -> Vec<Pass> {
+        let grids = if self.interlaced {
+            ADAM7.to_vec()
+        } else {
+            vec![(0, 0, 1, 1)]
+        };
+        grids
+            .into_iter()
+            .map(|(x0, y0, dx, dy)| Pass {
+                x0,
+                y0,
+                dx,
+                dy,
+                width: (self.width + dx - 1 - x0) / dx,
+                height: (self.height + dy - 1 - y0) / dy,
+            })
+            .filter(|pass| pass.width > 0 && pass.height > 0)
+            .collect()
+    }
+    /// Reads sample `channel` of pixel `x` from an unfiltered row.
+    fn sample(&self, row: &[u8], x: usize, channel: usize) -> u16 {
+        let index = x * self.channels() + channel;
+        match self.depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            8 => row[index] as u16,
+            depth => {
+                let bit = index * depth as usize;
+                let mask = (1u16 << depth) - 1;
+                (row[bit / 8] as u16 >> (8 - depth as usize - bit % 8)) & mask
+            }
+        }
+    }
+    /// Scales a sample of this image's depth to eight bits.
+    fn to_u8(&self, sample: u16) -> u8 {
+        match self.depth {
+            16 => (sample >> 8) as u8,
+            8 => sample as u8,
+            depth => (sample as u32 * 255 / ((1 << depth) - 1)) as u8,
+        }
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// What filter `kind` predicts a byte to be from its neighbours.
+fn predict(kind: u8, left: u8, up: u8, up_left: u8) -> u8 {
+    match kind {
+        0 => 0,
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        _ => paeth(left, up, up_left),
+    }
+}
+
+/// Applies filter `kind` to `row`, given the unfiltered row above it.
+fn filter(kind: u8, row: &[u8], prior: &[u8], step: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let (left, up_left) = match i.checked_sub(step) {
+                Some(j) => (row[j], prior[j]),
+                None => (0, 0),
+            };
+            row[i].wrapping_sub(predict(kind, left, prior[i], up_left))
+        })
+        .collect()
+}
+
+/// Undoes the per-row filters of one pass, returning its unfiltered rows back to back.
+fn unfilter(
+    data: &[u8],
+    row_bytes: usize,
+    rows: usize,
+    step: usize,
+) -> Result<Vec<u8>, ImageError> {
+    let mut out = vec![0; row_bytes * rows];
+    for (y, line) in data.chunks_exact(row_bytes + 1).take(rows).enumerate() {
+        let kind = line[0];
+        if kind > 4 {
+            return Err(malformed("unknown row filter"));
+        }
+        let start = y * row_bytes;
+        for i in 0..row_bytes {
+            let left = i.checked_sub(step).map_or(0, |j| out[start + j]);
+            let up = if y > 0 { out[start - row_bytes + i] } else { 0 };
+            let up_left = match (y, i.checked_sub(step)) {
+                (1.., Some(j)) => out[start - row_bytes + j],
+                _ => 0,
+            };
+            out[start + i] = line[1 + i].wrapping_add(predict(kind, left, up, up_left));
+        }
+    }
+    Ok(out)
+}
+
+/// Appends a chunk with its length and checksum.
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = zlib::crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+impl BmpFile {
+    /// Opens and decodes the PNG at `path`.
+    pub fn open_png<P: AsRef<Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_png_bytes(&std::fs::read(path)?)
+    }
+    /// Encodes the image as a PNG and writes it to `path`, replacing any existing file.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        std::fs::write(path, self.to_png_bytes())?;
+        Ok(())
+    }
+    /// Decodes a complete PNG held in memory. Chunk checksums are verified, and unknown
+    /// ancillary chunks are skipped.
+    pub fn from_png_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        if !bytes.starts_with(&SIGNATURE) {
+            return Err(malformed("missing PNG signature"));
+        }
+        let mut position = SIGNATURE.len();
+        let mut header = None;
+        let mut palette: Vec<Color> = Vec::new();
+        let mut transparency: Vec<u8> = Vec::new();
+        let mut resolution = None;
+        let mut compressed: Vec<u8> = Vec::new();
+        while let Some(prefix) = bytes.get(position..position + 8) {
+            let len = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+            let end = position + 8 + len;
+            let (Some(data), Some(crc)) = (bytes.get(position + 8..end), bytes.get(end..end + 4))
+            else {
+                return Err(malformed("chunk runs past the end of the file"));
+            };
+            if zlib::crc32(&bytes[position + 4..end]).to_be_bytes() != crc {
+                return Err(malformed("chunk checksum mismatch"));
+            }
+            position = end + 4;
+            match &prefix[4..8] {
+                b"IHDR" => header = Some(ImageHeader::parse(data)?),
+                b"PLTE" => {
+                    palette = data
+                        .chunks_exact(3)
+                        .map(|rgb| Color {
+                            b: rgb[2],
+                            g: rgb[1],
+                            r: rgb[0],
+                            a: 255,
+                        })
+                        .collect()
+                }
+                b"tRNS" => transparency = data.to_vec(),
+                b"pHYs" if data.len() == 9 && data[8] == 1 => {
+                    let x = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                    let y = i32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                    resolution = Some((x, y));
+                }
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                // A clear fifth bit in the first letter marks a chunk as critical.
+                kind if kind[0] & 0x20 == 0 => {
+                    return Err(malformed("unknown critical chunk"));
+                }
+                _ => {}
+            }
+        }
+        let header = header.ok_or_else(|| malformed("IHDR chunk is missing"))?;
+        Header::for_dimensions(header.width, header.height, 32)
+            .check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        for (alpha, entry) in transparency.iter().zip(&mut palette) {
+            if header.color_type == 3 {
+                entry.a = *alpha;
+            }
+        }
+        // Gray and RGB images may name one exact sample value as fully transparent.
+        let key: Vec<u16> = transparency
+            .chunks_exact(2)
+            .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+            .collect();
+        let passes = header.passes();
+        let expected = passes
+            .iter()
+            .map(|pass| (header.row_bytes(pass.width) + 1) * pass.height)
+            .sum();
+        let data = zlib::decompress(&compressed, expected).map_err(malformed)?;
+        if data.len() < expected {
+            return Err(malformed("pixel data ends early"));
+        }
+        let mut pixels = vec![Color::BLACK; header.width * header.height];
+        let mut start = 0;
+        for pass in passes {
+            let row_bytes = header.row_bytes(pass.width);
+            let rows = unfilter(&data[start..], row_bytes, pass.height, header.filter_step())?;
+            start += (row_bytes + 1) * pass.height;
+            for (y, row) in rows.chunks_exact(row_bytes).enumerate() {
+                for x in 0..pass.width {
+                    let sample = |channel| header.sample(row, x, channel);
+                    let opaque = |samples: &[u16]| {
+                        if !key.is_empty() && key == samples {
+                            0
+                        } else {
+                            255
+                        }
+                    };
+                    let pixel = match header.color_type {
+                        0 => {
+                            let gray = header.to_u8(sample(0));
+                            Color {
+                                b: gray,
+                                g: gray,
+                                r: gray,
+                                a: opaque(&[sample(0)]),
+                            }
+                        }
+                        2 => Color {
+                            b: header.to_u8(sample(2)),
+                            g: header.to_u8(sample(1)),
+                            r: header.to_u8(sample(0)),
+                            a: opaque(&[sample(0), sample(1), sample(2)]),
+                        },
+                        3 => palette
+                            .get(sample(0) as usize)
+                            .copied()
+                            .ok_or_else(|| malformed("palette index out of range"))?,
+                        4 => {
+                            let gray = header.to_u8(sample(0));
+                            Color {
+                                b: gray,
+                                g: gray,
+                                r: gray,
+                                a: header.to_u8(sample(1)),
+                            }
+                        }
+                        _ => Color {
+                            b: header.to_u8(sample(2)),
+                            g: header.to_u8(sample(1)),
+                            r: header.to_u8(sample(0)),
+                            a: header.to_u8(sample(3)),
+                        },
+                    };
+                    pixels[(pass.y0 + y * pass.dy) * header.width + pass.x0 + x * pass.dx] = pixel;
+                }
+            }
+        }
+        let mut image = BmpFile::from_pixels(header.width, header.height, pixels);
+        if let Some((hres, vres)) = resolution {
+            image.header.hres = hres;
+            image.header.vres = vres;
+        }
+        Ok(image)
+    }
+    /// The image encoded as a PNG. Each row uses whichever filter leaves the smallest sum of
+    /// absolute byte values, the usual heuristic for compressing well.
+    pub fn to_png_bytes(&self) -> Vec<u8> {
+        let (width, height) = (self.header.width, self.header.height);
+        let channels = if self.pixels.iter().any(|pixel| pixel.a != 255) {
+            4
+        } else {
+            3
+        };
+        let mut filtered = Vec::with_capacity((width * channels + 1) * height);
+        let mut prior = vec![0; width * channels];
+        for row in self.pixels.chunks_exact(width.max(1)) {
+            let row: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| {
+                    [pixel.r, pixel.g, pixel.b, pixel.a]
+                        .into_iter()
+                        .take(channels)
+                })
+                .collect();
+            let cost = |line: &Vec<u8>| {
+                line.iter()
+                    .map(|&byte| (byte as i8).unsigned_abs() as usize)
+                    .sum::<usize>()
+            };
+            let (kind, line) = (0..5)
+                .map(|kind| (kind, filter(kind, &row, &prior, channels)))
+                .min_by_key(|(_, line)| cost(line))
+                .unwrap_or_default();
+            filtered.push(kind);
+            filtered.extend_from_slice(&line);
+            prior = row;
+        }
+        let mut out = SIGNATURE.to_vec();
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, if channels == 4 { 6 } else { 2 }, 0, 0, 0]);
+        write_chunk(&mut out, b"IHDR", &ihdr);
+        if self.header.hres > 0 && self.header.vres > 0 {
+            let mut phys = Vec::with_capacity(9);
+            phys.extend_from_slice(&self.header.hres.to_be_bytes());
+            phys.extend_from_slice(&self.header.vres.to_be_bytes());
+            phys.push(1);
+            write_chunk(&mut out, b"pHYs", &phys);
+        }
+        write_chunk(&mut out, b"IDAT", &zlib::compress(&filtered));
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
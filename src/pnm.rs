@@ -0,0 +1,238 @@
+//! Netpbm import and export: PBM, PGM and PPM in both their plain (ASCII) and raw
+//! (binary) forms.
+//!
+//! A file starts with its magic number, width, height and, except for bitmaps, the largest
+//! sample value, separated by whitespace and `#` comments. Samples follow, as decimal text
+//! in plain files and as bytes in raw ones, two bytes big-endian when the maximum exceeds 255.
+
+use crate::{BmpFile, Color, Header, ImageError, DEFAULT_MAX_PIXEL_BYTES};
+use std::path::Path;
+
+/// The Netpbm variant [`BmpFile::save_ppm`] writes, named after its magic number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PnmFormat {
+    /// Plain PBM: black and white as `0` and `1` digits, `1` being black.
+    P1,
+    /// Plain PGM: decimal gray values.
+    P2,
+    /// Plain PPM: decimal red, green and blue values.
+    P3,
+    /// Raw PBM: eight pixels per byte, each row padded to a whole byte.
+    P4,
+    /// Raw PGM: one gray byte per pixel.
+    P5,
+    /// Raw PPM: red, green and blue bytes per pixel.
+    P6,
+}
+impl PnmFormat {
+    fn magic(&self) -> &'static [u8; 2] {
+        match self {
+            PnmFormat::P1 => b"P1",
+            PnmFormat::P2 => b"P2",
+            PnmFormat::P3 => b"P3",
+            PnmFormat::P4 => b"P4",
+            PnmFormat::P5 => b"P5",
+            PnmFormat::P6 => b"P6",
+        }
+    }
+}
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "Netpbm",
+        reason: reason.to_string(),
+    }
+}
+
+/// Walks the header fields and plain samples, skipping whitespace and comments.
+struct Tokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl Tokens<'_> {
+    fn skip_separators(&mut self) {
+        while let Some(&byte) = self.data.get(self.position) {
+            if byte == b'#' {
+                while self
+                    .data
+                    .get(self.position)
+                    .is_some_and(|&byte| byte != b'\n')
+                {
+                    self.position += 1;
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+    fn number(&mut self) -> Result<u32, ImageError> {
+        self.skip_separators();
+        let start = self.position;
+        while self.data.get(self.position).is_some_and(u8::is_ascii_digit) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| malformed("expected a number"))
+    }
+    /// A single `0` or `1`, which plain bitmaps may write without separators.
+    fn bit(&mut self) -> Result<u32, ImageError> {
+        self.skip_separators();
+        match self.data.get(self.position) {
+            Some(&digit @ (b'0' | b'1')) => {
+                self.position += 1;
+                Ok((digit - b'0') as u32)
+            }
+            _ => Err(malformed("expected a 0 or 1")),
+        }
+    }
+}
+
+/// Writes `values` as decimal text, `per_line` to a line so that lines stay under the
+/// 70 characters Netpbm recommends.
+fn write_plain(out: &mut Vec<u8>, values: impl Iterator<Item = u8>, per_line: usize) {
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            out.push(if i % per_line == 0 { b'\n' } else { b' ' });
+        }
+        out.extend_from_slice(value.to_string().as_bytes());
+    }
+    out.push(b'\n');
+}
+
+impl BmpFile {
+    /// Opens and decodes the PBM, PGM or PPM file at `path`.
+    pub fn open_ppm<P: AsRef<Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_ppm_bytes(&std::fs::read(path)?)
+    }
+    /// Encodes the image as `format` and writes it to `path`, replacing any existing file.
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P, format: PnmFormat) -> Result<(), ImageError> {
+        std::fs::write(path, self.to_ppm_bytes(format))?;
+        Ok(())
+    }
+    /// Decodes any of the six Netpbm variants held in memory. Samples are scaled from the
+    /// file's maximum value to 8 bits.
+    pub fn from_ppm_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        let format = match bytes.get(0..2) {
+            Some(b"P1") => PnmFormat::P1,
+            Some(b"P2") => PnmFormat::P2,
+            Some(b"P3") => PnmFormat::P3,
+            Some(b"P4") => PnmFormat::P4,
+            Some(b"P5") => PnmFormat::P5,
+            Some(b"P6") => PnmFormat::P6,
+            _ => return Err(malformed("missing P1 to P6 magic number")),
+        };
+        let mut tokens = Tokens {
+            data: bytes,
+            position: 2,
+        };
+        let width = tokens.number()? as usize;
+        let height = tokens.number()? as usize;
+        let max = match format {
+            PnmFormat::P1 | PnmFormat::P4 => 1,
+            _ => tokens.number()?,
+        };
+        if !(1..=65535).contains(&max) {
+            return Err(malformed(
+                "maximum sample value must be between 1 and 65535",
+            ));
+        }
+        Header::for_dimensions(width, height, 24).check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let channels = match format {
+            PnmFormat::P3 | PnmFormat::P6 => 3,
+            _ => 1,
+        };
+        let count = width * height * channels;
+        let samples: Vec<u32> = match format {
+            PnmFormat::P1 => (0..count).map(|_| tokens.bit()).collect::<Result<_, _>>()?,
+            PnmFormat::P2 | PnmFormat::P3 => (0..count)
+                .map(|_| tokens.number())
+                .collect::<Result<_, _>>()?,
+            raw => {
+                // A single whitespace byte separates the header from raw samples.
+                let data = bytes.get(tokens.position + 1..).unwrap_or_default();
+                if raw == PnmFormat::P4 {
+                    let row_bytes = width.div_ceil(8);
+                    if data.len() < row_bytes * height {
+                        return Err(malformed("pixel data ends early"));
+                    }
+                    (0..height)
+                        .flat_map(|y| (0..width).map(move |x| (y, x)))
+                        .map(|(y, x)| (data[y * row_bytes + x / 8] >> (7 - x % 8)) as u32 & 1)
+                        .collect()
+                } else {
+                    let sample_bytes = if max > 255 { 2 } else { 1 };
+                    if data.len() < count * sample_bytes {
+                        return Err(malformed("pixel data ends early"));
+                    }
+                    data.chunks_exact(sample_bytes)
+                        .take(count)
+                        .map(|sample| {
+                            sample
+                                .iter()
+                                .fold(0, |value, &byte| value << 8 | byte as u32)
+                        })
+                        .collect()
+                }
+            }
+        };
+        let scale = |sample: u32| (sample.min(max) * 255 + max / 2) / max;
+        let pixels = samples
+            .chunks_exact(channels)
+            .map(|sample| {
+                let [r, g, b] = match format {
+                    // Bitmaps store ink, so a set bit is black.
+                    PnmFormat::P1 | PnmFormat::P4 => [255 * (1 - sample[0]); 3],
+                    PnmFormat::P3 | PnmFormat::P6 => [sample[0], sample[1], sample[2]].map(scale),
+                    _ => [scale(sample[0]); 3],
+                };
+                Color::rgb(r as u8, g as u8, b as u8)
+            })
+            .collect();
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+    /// The image encoded as `format`. Graymaps use each pixel's luminance, bitmaps mark
+    /// pixels darker than mid-gray as black, and alpha is dropped.
+    pub fn to_ppm_bytes(&self, format: PnmFormat) -> Vec<u8> {
+        let (width, height) = (self.header.width, self.header.height);
+        let mut out = format.magic().to_vec();
+        out.extend_from_slice(format!("\n{} {}\n", width, height).as_bytes());
+        if !matches!(format, PnmFormat::P1 | PnmFormat::P4) {
+            out.extend_from_slice(b"255\n");
+        }
+        let luminance = |pixel: &Color| pixel.luminance();
+        let ink = |pixel: &Color| (luminance(pixel) < 128) as u8;
+        match format {
+            PnmFormat::P1 => write_plain(&mut out, self.pixels.iter().map(ink), 32),
+            PnmFormat::P2 => write_plain(&mut out, self.pixels.iter().map(luminance), 16),
+            PnmFormat::P3 => write_plain(
+                &mut out,
+                self.pixels
+                    .iter()
+                    .flat_map(|pixel| [pixel.r, pixel.g, pixel.b]),
+                15,
+            ),
+            PnmFormat::P4 => {
+                for row in self.pixels.chunks_exact(width.max(1)) {
+                    for byte in row.chunks(8) {
+                        let bits = byte
+                            .iter()
+                            .enumerate()
+                            .fold(0, |bits, (i, pixel)| bits | ink(pixel) << (7 - i));
+                        out.push(bits);
+                    }
+                }
+            }
+            PnmFormat::P5 => out.extend(self.pixels.iter().map(luminance)),
+            PnmFormat::P6 => out.extend(
+                self.pixels
+                    .iter()
+                    .flat_map(|pixel| [pixel.r, pixel.g, pixel.b]),
+            ),
+        }
+        out
+    }
+}
@@ -0,0 +1,162 @@
+//! QOI ("Quite OK Image") import and export.
+//!
+//! After a 14-byte header, each pixel is coded relative to the previous one: as a run of
+//! repeats, an index into a 64-entry table of recently seen colors, a small difference, or
+//! the full value. The stream ends with seven zero bytes and a one.
+
+use crate::{BmpFile, Color, Header, ImageError, DEFAULT_MAX_PIXEL_BYTES};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"qoif";
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const TAG_MASK: u8 = 0xC0;
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "QOI",
+        reason: reason.to_string(),
+    }
+}
+
+/// Slot of `pixel` in the table of recently seen colors.
+fn hash(pixel: &Color) -> usize {
+    (pixel.r as usize * 3 + pixel.g as usize * 5 + pixel.b as usize * 7 + pixel.a as usize * 11)
+        % 64
+}
+
+impl BmpFile {
+    /// Opens and decodes the QOI file at `path`.
+    pub fn open_qoi<P: AsRef<Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_qoi_bytes(&std::fs::read(path)?)
+    }
+    /// Encodes the image as a QOI and writes it to `path`, replacing any existing file.
+    pub fn save_qoi<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        std::fs::write(path, self.to_qoi_bytes())?;
+        Ok(())
+    }
+    /// Decodes a complete QOI held in memory.
+    pub fn from_qoi_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        let header = bytes
+            .get(0..14)
+            .filter(|header| header.starts_with(MAGIC))
+            .ok_or_else(|| malformed("missing qoif header"))?;
+        let width = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let height = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+        Header::for_dimensions(width, height, 32).check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let count = width * height;
+        let mut pixels: Vec<Color> = Vec::with_capacity(count);
+        let mut seen = [Color::TRANSPARENT; 64];
+        let mut previous = Color::BLACK;
+        let mut data = &bytes[14..];
+        let mut next = || -> Result<u8, ImageError> {
+            let (&byte, rest) = data
+                .split_first()
+                .ok_or_else(|| malformed("pixel data ends early"))?;
+            data = rest;
+            Ok(byte)
+        };
+        while pixels.len() < count {
+            let op = next()?;
+            let mut pixel = previous;
+            match op {
+                OP_RGB => {
+                    (pixel.r, pixel.g, pixel.b) = (next()?, next()?, next()?);
+                }
+                OP_RGBA => {
+                    (pixel.r, pixel.g, pixel.b, pixel.a) = (next()?, next()?, next()?, next()?);
+                }
+                _ => match op & TAG_MASK {
+                    OP_INDEX => pixel = seen[op as usize],
+                    OP_DIFF => {
+                        pixel.r = pixel.r.wrapping_add((op >> 4 & 0x03).wrapping_sub(2));
+                        pixel.g = pixel.g.wrapping_add((op >> 2 & 0x03).wrapping_sub(2));
+                        pixel.b = pixel.b.wrapping_add((op & 0x03).wrapping_sub(2));
+                    }
+                    OP_LUMA => {
+                        let green = (op & 0x3F).wrapping_sub(32);
+                        let second = next()?;
+                        pixel.r = pixel
+                            .r
+                            .wrapping_add(green.wrapping_add(second >> 4).wrapping_sub(8));
+                        pixel.g = pixel.g.wrapping_add(green);
+                        pixel.b = pixel
+                            .b
+                            .wrapping_add(green.wrapping_add(second & 0x0F).wrapping_sub(8));
+                    }
+                    _ => {
+                        let run = (op & 0x3F) as usize + 1;
+                        let run = run.min(count - pixels.len());
+                        pixels.extend(std::iter::repeat_n(previous, run));
+                        seen[hash(&previous)] = previous;
+                        continue;
+                    }
+                },
+            }
+            seen[hash(&pixel)] = pixel;
+            pixels.push(pixel);
+            previous = pixel;
+        }
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+    /// The image encoded as a QOI, with four channels if any pixel is translucent and three
+    /// otherwise.
+    pub fn to_qoi_bytes(&self) -> Vec<u8> {
+        let has_alpha = self.pixels.iter().any(|pixel| pixel.a != 255);
+        let mut out = Vec::with_capacity(14 + self.pixels.len() + END_MARKER.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&(self.header.width as u32).to_be_bytes());
+        out.extend_from_slice(&(self.header.height as u32).to_be_bytes());
+        // Channel count, then the sRGB color space.
+        out.extend_from_slice(&[if has_alpha { 4 } else { 3 }, 0]);
+        let mut seen = [Color::TRANSPARENT; 64];
+        let mut previous = Color::BLACK;
+        let mut run = 0;
+        for (i, &pixel) in self.pixels.iter().enumerate() {
+            if pixel == previous {
+                run += 1;
+                // Runs of 63 and 64 would collide with the RGB and RGBA tags.
+                if run == 62 || i + 1 == self.pixels.len() {
+                    out.push(OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue;
+            }
+            if run > 0 {
+                out.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+            let slot = hash(&pixel);
+            if seen[slot] == pixel {
+                out.push(OP_INDEX | slot as u8);
+            } else if pixel.a != previous.a {
+                out.extend_from_slice(&[OP_RGBA, pixel.r, pixel.g, pixel.b, pixel.a]);
+            } else {
+                let dr = pixel.r.wrapping_sub(previous.r) as i8;
+                let dg = pixel.g.wrapping_sub(previous.g) as i8;
+                let db = pixel.b.wrapping_sub(previous.b) as i8;
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+                if [dr, dg, db].iter().all(|d| (-2..=1).contains(d)) {
+                    out.push(OP_DIFF | ((dr + 2) << 4 | (dg + 2) << 2 | (db + 2)) as u8);
+                } else if (-32..=31).contains(&dg)
+                    && (-8..=7).contains(&dr_dg)
+                    && (-8..=7).contains(&db_dg)
+                {
+                    out.push(OP_LUMA | (dg + 32) as u8);
+                    out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    out.extend_from_slice(&[OP_RGB, pixel.r, pixel.g, pixel.b]);
+                }
+            }
+            seen[slot] = pixel;
+            previous = pixel;
+        }
+        out.extend_from_slice(&END_MARKER);
+        out
+    }
+}
@@ -0,0 +1,72 @@
+//! Raw pixel buffers, as used for texture uploads and camera frames.
+//!
+//! Rows are tightly packed from the top row down, with none of the padding that rounds BMP
+//! rows up to a multiple of four bytes.
+
+use crate::{BmpFile, Color, Header, ImageError, DEFAULT_MAX_PIXEL_BYTES};
+
+/// Channel order of each pixel in a raw buffer, one byte per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb8,
+    Rgba8,
+    Bgr8,
+    Bgra8,
+}
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgb8 | PixelFormat::Bgr8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+        }
+    }
+}
+
+impl BmpFile {
+    /// Builds an image from a `width`x`height` buffer laid out as `format`, 32 bits deep if
+    /// any pixel is translucent and 24 otherwise.
+    pub fn from_raw(
+        width: usize,
+        height: usize,
+        data: &[u8],
+        format: PixelFormat,
+    ) -> Result<BmpFile, ImageError> {
+        Header::for_dimensions(width, height, 32).check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let expected = width * height * format.bytes_per_pixel();
+        if data.len() != expected {
+            return Err(ImageError::BufferLength {
+                expected,
+                found: data.len(),
+            });
+        }
+        let pixels = data
+            .chunks_exact(format.bytes_per_pixel())
+            .map(|bytes| {
+                let (r, b) = match format {
+                    PixelFormat::Rgb8 | PixelFormat::Rgba8 => (bytes[0], bytes[2]),
+                    PixelFormat::Bgr8 | PixelFormat::Bgra8 => (bytes[2], bytes[0]),
+                };
+                let a = bytes.get(3).copied().unwrap_or(255);
+                Color {
+                    b,
+                    g: bytes[1],
+                    r,
+                    a,
+                }
+            })
+            .collect();
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+    /// The pixels laid out as `format`. Formats without alpha drop it.
+    pub fn to_raw(&self, format: PixelFormat) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.pixels.len() * format.bytes_per_pixel());
+        for pixel in &self.pixels {
+            let bytes = match format {
+                PixelFormat::Rgb8 | PixelFormat::Rgba8 => [pixel.r, pixel.g, pixel.b, pixel.a],
+                PixelFormat::Bgr8 | PixelFormat::Bgra8 => [pixel.b, pixel.g, pixel.r, pixel.a],
+            };
+            out.extend_from_slice(&bytes[..format.bytes_per_pixel()]);
+        }
+        out
+    }
+}
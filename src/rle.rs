@@ -0,0 +1,92 @@
+//! Run-length encoding of 8-bit (RLE8) and 4-bit (RLE4) palette indices.
+//!
+//! Both schemes work on pairs of bytes. A nonzero first byte is a run of that many pixels
+//! drawn from the second byte; RLE4 alternates its high and low nibble. A zero first byte
+//! is an escape: 0 ends the line, 1 ends the bitmap, 2 moves the cursor right and up by the
+//! next two bytes, and anything larger starts that many literal indices padded to 16 bits.
+
+const END_OF_LINE: u8 = 0;
+const END_OF_BITMAP: u8 = 1;
+const DELTA: u8 = 2;
+
+/// Expands `data` into `width * height` indices, bottom row first like the file stores them.
+/// Pixels skipped by deltas or early line ends stay at index 0. Also returns how many bytes
+/// were consumed, which is all of `data` if it ends without an end-of-bitmap marker.
+pub(crate) fn decode(data: &[u8], width: usize, height: usize, bits: u16) -> (Vec<u8>, usize) {
+    let mut indices = vec![0; width * height];
+    let (mut x, mut y) = (0, 0);
+    let mut put = |x: usize, y: usize, index: u8| {
+        if x < width && y < height {
+            indices[y * width + x] = index;
+        }
+    };
+    let nibble = |value: u8, i: usize| match bits {
+        4 if i.is_multiple_of(2) => value >> 4,
+        4 => value & 0x0F,
+        _ => value,
+    };
+    let mut position = 0;
+    while let Some(&[count, value]) = data.get(position..position + 2) {
+        position += 2;
+        match (count, value) {
+            (0, END_OF_LINE) => {
+                x = 0;
+                y += 1;
+            }
+            (0, END_OF_BITMAP) => break,
+            (0, DELTA) => {
+                let Some(&[dx, dy]) = data.get(position..position + 2) else {
+                    position = data.len();
+                    break;
+                };
+                position += 2;
+                x += dx as usize;
+                y += dy as usize;
+            }
+            (0, literal) => {
+                let literal = literal as usize;
+                let len = if bits == 4 {
+                    literal.div_ceil(2)
+                } else {
+                    literal
+                };
+                for i in 0..literal {
+                    let offset = if bits == 4 { i / 2 } else { i };
+                    if let Some(&byte) = data.get(position + offset) {
+                        put(x, y, nibble(byte, i));
+                    }
+                    x += 1;
+                }
+                // Literal runs are padded to a 16-bit boundary.
+                position = (position + len + len % 2).min(data.len());
+            }
+            (count, value) => {
+                for i in 0..count as usize {
+                    put(x, y, nibble(value, i));
+                    x += 1;
+                }
+            }
+        }
+    }
+    (indices, position)
+}
+
+/// Appends the RLE8 or RLE4 encoding of `rows`, which must be given bottom row first.
+/// Only repeat runs are emitted; a row of all-different indices costs two bytes per pixel.
+pub(crate) fn encode<'a>(rows: impl Iterator<Item = &'a [u8]>, bits: u16, out: &mut Vec<u8>) {
+    for row in rows {
+        let mut rest = row;
+        while let Some(&index) = rest.first() {
+            let run = rest
+                .iter()
+                .take(255)
+                .take_while(|&&other| other == index)
+                .count();
+            let value = if bits == 4 { index << 4 | index } else { index };
+            out.extend_from_slice(&[run as u8, value]);
+            rest = &rest[run..];
+        }
+        out.extend_from_slice(&[0, END_OF_LINE]);
+    }
+    out.extend_from_slice(&[0, END_OF_BITMAP]);
+}
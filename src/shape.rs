@@ -0,0 +1,115 @@
+//! Outlines of curved shapes, flattened into polygons for filling and stroking.
+//!
+//! Curves are split into enough straight edges that none strays more than a tenth of a
+//! pixel from the true outline.
+
+use std::f64::consts::PI;
+
+pub(crate) type Point = (f64, f64);
+
+/// The radius of each corner of a rounded rectangle, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CornerRadii {
+    pub top_left: f64,
+    pub top_right: f64,
+    pub bottom_right: f64,
+    pub bottom_left: f64,
+}
+
+impl CornerRadii {
+    /// The same radius at every corner.
+    pub fn uniform(radius: f64) -> CornerRadii {
+        CornerRadii {
+            top_left: radius,
+            top_right: radius,
+            bottom_right: radius,
+            bottom_left: radius,
+        }
+    }
+}
+
+impl From<f64> for CornerRadii {
+    fn from(radius: f64) -> CornerRadii {
+        CornerRadii::uniform(radius)
+    }
+}
+
+/// How many straight edges a full turn of a circle of `radius` needs.
+fn sides(radius: f64) -> usize {
+    if radius <= 0.1 {
+        8
+    } else {
+        (PI / (1.0 - 0.1 / radius).acos()).ceil().clamp(8.0, 1024.0) as usize
+    }
+}
+
+/// Points along the circle of `radius` around `center` from angle `start` to `end`, in
+/// radians measured clockwise from the positive x axis (y grows downwards), both ends
+/// included.
+pub(crate) fn arc(center: Point, radius: f64, start: f64, end: f64) -> impl Iterator<Item = Point> {
+    let steps = ((sides(radius) as f64 * (end - start).abs() / (2.0 * PI)).ceil() as usize).max(1);
+    (0..=steps).map(move |i| {
+        let angle = start + (end - start) * i as f64 / steps as f64;
+        (
+            center.0 + radius * angle.cos(),
+            center.1 + radius * angle.sin(),
+        )
+    })
+}
+
+/// A polygon approximating the circle.
+pub(crate) fn circle(center: Point, radius: f64) -> Vec<Point> {
+    let sides = sides(radius);
+    (0..sides)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f64 / sides as f64;
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+/// The outline of the rectangle from (left, top) to (right, bottom) with rounded corners.
+/// Negative radii count as zero, and radii too large for the sides they share are scaled
+/// down together, as CSS does.
+pub(crate) fn rounded_rect(
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+    radii: CornerRadii,
+) -> Vec<Point> {
+    let (width, height) = (right - left, bottom - top);
+    let [top_left, top_right, bottom_right, bottom_left] = [
+        radii.top_left,
+        radii.top_right,
+        radii.bottom_right,
+        radii.bottom_left,
+    ]
+    .map(|radius| radius.max(0.0));
+    let scale = [
+        (width, top_left + top_right),
+        (width, bottom_left + bottom_right),
+        (height, top_left + bottom_left),
+        (height, top_right + bottom_right),
+    ]
+    .iter()
+    .filter(|&&(_, radii)| radii > 0.0)
+    .fold(1.0f64, |scale, &(side, radii)| scale.min(side / radii));
+    let corners = [
+        (top_left, (left, top), (1.0, 1.0), PI),
+        (top_right, (right, top), (-1.0, 1.0), 1.5 * PI),
+        (bottom_right, (right, bottom), (-1.0, -1.0), 0.0),
+        (bottom_left, (left, bottom), (1.0, -1.0), 0.5 * PI),
+    ];
+    corners
+        .iter()
+        .flat_map(|&(radius, (x, y), (toward_x, toward_y), start)| {
+            let radius = radius * scale;
+            let center = (x + toward_x * radius, y + toward_y * radius);
+            arc(center, radius, start, start + PI / 2.0)
+        })
+        .collect()
+}
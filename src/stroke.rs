@@ -0,0 +1,276 @@
+//! Outlines of lines and polylines wider than one pixel, optionally dashed.
+//!
+//! A stroke is turned into a set of convex polygons — one quad per segment plus the caps at
+//! open ends and the joins between segments — and each polygon is filled on its own, so
+//! overlapping pieces simply paint the same pixels twice. Dashed strokes are first cut into
+//! one open path per dash, each stroked with its own caps.
+
+use crate::shape::{circle, Point};
+
+/// How the open ends of a stroke are finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    /// The stroke stops exactly at the end point.
+    Butt,
+    /// A half circle centered on the end point.
+    Round,
+    /// The stroke extends past the end point by half its width.
+    Square,
+}
+
+/// How the outer corner where two segments meet is filled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges are extended until they meet, falling back to a bevel when the
+    /// miter would be more than [`MITER_LIMIT`] times as long as the stroke is wide.
+    Miter,
+    /// A circle centered on the corner.
+    Round,
+    /// The outer edges are connected by a straight cut.
+    Bevel,
+}
+
+/// Longest miter allowed, relative to the stroke width, before [`LineJoin::Miter`] bevels
+/// instead. Matches the SVG default.
+pub const MITER_LIMIT: f64 = 4.0;
+
+/// Shortest non-zero dash or gap, in pixels. Shorter ones are lengthened to this so that a
+/// pattern of tiny lengths cannot cut a path into an unbounded number of pieces.
+const MIN_DASH_LENGTH: f64 = 1e-3;
+
+/// The width, end and corner shapes and dash pattern used by [`Image::stroke_polyline`] and
+/// friends.
+///
+/// [`Image::stroke_polyline`]: crate::Image::stroke_polyline
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    /// Full width in pixels, centered on the path.
+    pub width: f64,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Alternating lengths in pixels of drawn and skipped stretches along the path, repeated
+    /// to its end. An odd-length pattern is repeated twice over, as in SVG. An empty pattern,
+    /// or one with a negative length or no positive lengths, gives a solid stroke.
+    ///
+    /// Every dash gets the stroke's caps, so zero-length dashes with round caps make a
+    /// dotted line. Other lengths below a thousandth of a pixel count as that.
+    pub dash: Vec<f64>,
+    /// How far into the dash pattern the path starts.
+    pub dash_offset: f64,
+}
+
+impl Default for StrokeStyle {
+    /// A solid one pixel wide stroke with butt caps and miter joins.
+    fn default() -> StrokeStyle {
+        StrokeStyle {
+            width: 1.0,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            dash: Vec::new(),
+            dash_offset: 0.0,
+        }
+    }
+}
+
+/// The convex polygons covering the stroke of `points`, joining the last point back to the
+/// first when `closed`.
+pub(crate) fn stroke_polygons(
+    points: &[Point],
+    closed: bool,
+    style: &StrokeStyle,
+) -> Vec<Vec<Point>> {
+    let dashed = !style.dash.is_empty()
+        && style.dash.iter().all(|&len| len >= 0.0 && len.is_finite())
+        && style.dash.iter().any(|&len| len > 0.0);
+    if !dashed {
+        return solid_polygons(points, closed, style);
+    }
+    let mut path = points.to_vec();
+    if closed {
+        path.extend(points.first());
+    }
+    let pattern: Vec<f64> = style
+        .dash
+        .iter()
+        .map(|&len| {
+            if len > 0.0 {
+                len.max(MIN_DASH_LENGTH)
+            } else {
+                len
+            }
+        })
+        .collect();
+    dashes(&path, &pattern, style.dash_offset)
+        .iter()
+        .flat_map(|dash| solid_polygons(dash, false, style))
+        .collect()
+}
+
+/// Cuts the open path through `points` into the stretches `pattern` draws, each an open
+/// path of its own. Zero-length dashes come back as a repeated point.
+fn dashes(points: &[Point], pattern: &[f64], offset: f64) -> Vec<Vec<Point>> {
+    let pattern: Vec<f64> = if pattern.len() % 2 == 1 {
+        pattern.repeat(2)
+    } else {
+        pattern.to_vec()
+    };
+    let mut index = 0;
+    let mut remaining = offset.rem_euclid(pattern.iter().sum());
+    // A zero-length dash exactly at the start still counts.
+    while remaining >= pattern[index] && !(remaining == 0.0 && pattern[index] == 0.0) {
+        remaining -= pattern[index];
+        index = (index + 1) % pattern.len();
+    }
+    remaining = pattern[index] - remaining;
+    let mut dashes = Vec::new();
+    let mut current = Vec::new();
+    if index % 2 == 0 {
+        current.extend(points.first());
+    }
+    for pair in points.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let len = (end.0 - start.0).hypot(end.1 - start.1);
+        let mut travelled = 0.0;
+        // Each pattern boundary inside this segment starts or ends a dash.
+        while len - travelled >= remaining {
+            travelled += remaining;
+            let t = if len == 0.0 { 0.0 } else { travelled / len };
+            current.push((
+                start.0 + (end.0 - start.0) * t,
+                start.1 + (end.1 - start.1) * t,
+            ));
+            if index % 2 == 0 {
+                dashes.push(std::mem::take(&mut current));
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= len - travelled;
+        if index % 2 == 0 {
+            current.push(end);
+        }
+    }
+    if current.len() > 1 {
+        dashes.push(current);
+    }
+    dashes
+}
+
+/// The polygons for an undashed stroke. Repeated points are ignored, and a path that comes
+/// down to a single point draws a dot for round and square caps, as in SVG.
+fn solid_polygons(points: &[Point], closed: bool, style: &StrokeStyle) -> Vec<Vec<Point>> {
+    let mut path: Vec<Point> = Vec::with_capacity(points.len());
+    for &point in points {
+        if path.last() != Some(&point) {
+            path.push(point);
+        }
+    }
+    if closed && path.len() > 1 && path.first() == path.last() {
+        path.pop();
+    }
+    let half = style.width / 2.0;
+    if half <= 0.0 {
+        return Vec::new();
+    }
+    if let [(x, y)] = path[..] {
+        return match style.cap {
+            LineCap::Butt => Vec::new(),
+            LineCap::Round => vec![circle((x, y), half)],
+            LineCap::Square => vec![vec![
+                (x - half, y - half),
+                (x + half, y - half),
+                (x + half, y + half),
+                (x - half, y + half),
+            ]],
+        };
+    }
+    if path.len() < 2 {
+        return Vec::new();
+    }
+    let segment_count = if closed { path.len() } else { path.len() - 1 };
+    let segments: Vec<(Point, Point)> = (0..segment_count)
+        .map(|i| (path[i], path[(i + 1) % path.len()]))
+        .collect();
+    let mut polygons = Vec::new();
+    for (index, &(start, end)) in segments.iter().enumerate() {
+        let direction = unit(start, end);
+        let normal = (-direction.1 * half, direction.0 * half);
+        // Square caps lengthen the first and last segments of an open path.
+        let extend = |at_end: bool| match style.cap {
+            LineCap::Square if !closed && at_end => half,
+            _ => 0.0,
+        };
+        let start = offset(start, direction, -extend(index == 0));
+        let end = offset(end, direction, extend(index == segment_count - 1));
+        polygons.push(vec![
+            (start.0 + normal.0, start.1 + normal.1),
+            (end.0 + normal.0, end.1 + normal.1),
+            (end.0 - normal.0, end.1 - normal.1),
+            (start.0 - normal.0, start.1 - normal.1),
+        ]);
+    }
+    if !closed && style.cap == LineCap::Round {
+        polygons.push(circle(path[0], half));
+        polygons.push(circle(path[path.len() - 1], half));
+    }
+    let corners = if closed {
+        0..segments.len()
+    } else {
+        1..segments.len()
+    };
+    for index in corners {
+        let incoming = segments[(index + segments.len() - 1) % segments.len()];
+        let outgoing = segments[index];
+        if let Some(join) = join_polygon(incoming, outgoing, half, style.join) {
+            polygons.push(join);
+        }
+    }
+    polygons
+}
+
+/// The piece filling the outer corner between two consecutive segments, if any.
+fn join_polygon(
+    incoming: (Point, Point),
+    outgoing: (Point, Point),
+    half: f64,
+    join: LineJoin,
+) -> Option<Vec<Point>> {
+    let corner = incoming.1;
+    if join == LineJoin::Round {
+        return Some(circle(corner, half));
+    }
+    let (d0, d1) = (unit(incoming.0, incoming.1), unit(outgoing.0, outgoing.1));
+    let cross = d0.0 * d1.1 - d0.1 * d1.0;
+    if cross.abs() < 1e-9 {
+        return None;
+    }
+    // The outer side is the one the path turns away from.
+    let side = if cross > 0.0 { -half } else { half };
+    let (n0, n1) = ((-d0.1, d0.0), (-d1.1, d1.0));
+    let outer_in = (corner.0 + n0.0 * side, corner.1 + n0.1 * side);
+    let outer_out = (corner.0 + n1.0 * side, corner.1 + n1.1 * side);
+    let bisector = (n0.0 + n1.0, n0.1 + n1.1);
+    let bisector_len = bisector.0.hypot(bisector.1);
+    // The miter point lies along the bisector at half / cos(θ/2), where cos(θ/2) is
+    // half the bisector's length.
+    let miter_ratio = 2.0 / bisector_len;
+    if join == LineJoin::Miter && miter_ratio <= MITER_LIMIT {
+        let scale = side * miter_ratio / bisector_len;
+        let miter = (corner.0 + bisector.0 * scale, corner.1 + bisector.1 * scale);
+        return Some(vec![corner, outer_in, miter, outer_out]);
+    }
+    Some(vec![corner, outer_in, outer_out])
+}
+
+fn unit(from: Point, to: Point) -> Point {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len = dx.hypot(dy);
+    (dx / len, dy / len)
+}
+
+fn offset(point: Point, direction: Point, distance: f64) -> Point {
+    (
+        point.0 + direction.0 * distance,
+        point.1 + direction.1 * distance,
+    )
+}
@@ -0,0 +1,177 @@
+//! Truevision TGA import and export for 24- and 32-bit true-color images, uncompressed or
+//! run-length encoded.
+//!
+//! An 18-byte header is followed by an optional image ID and color map, then the pixels as
+//! BGR or BGRA. Rows are stored bottom row first unless bit 5 of the descriptor byte is set.
+//! RLE packets start with a byte whose top bit picks between repeating the next pixel and
+//! copying the next pixels literally, and whose low seven bits hold the count minus one.
+
+use crate::{BmpFile, ByteOrder, Color, Header, ImageError, LittleEndian, DEFAULT_MAX_PIXEL_BYTES};
+use std::path::Path;
+
+const UNCOMPRESSED: u8 = 2;
+const RUN_LENGTH: u8 = 10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+const RIGHT_TO_LEFT: u8 = 0x10;
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "TGA",
+        reason: reason.to_string(),
+    }
+}
+
+fn pixel(bytes: &[u8], has_alpha: bool) -> Color {
+    Color {
+        b: bytes[0],
+        g: bytes[1],
+        r: bytes[2],
+        a: if has_alpha { bytes[3] } else { 255 },
+    }
+}
+
+impl BmpFile {
+    /// Opens and decodes the TGA file at `path`.
+    pub fn open_tga<P: AsRef<Path>>(path: P) -> Result<BmpFile, ImageError> {
+        BmpFile::from_tga_bytes(&std::fs::read(path)?)
+    }
+    /// Encodes the image as a TGA, run-length encoded if `rle` is set, and writes it to
+    /// `path`, replacing any existing file.
+    pub fn save_tga<P: AsRef<Path>>(&self, path: P, rle: bool) -> Result<(), ImageError> {
+        std::fs::write(path, self.to_tga_bytes(rle)?)?;
+        Ok(())
+    }
+    /// Decodes an uncompressed or run-length encoded 24- or 32-bit TGA held in memory.
+    /// The alpha channel is only used when the descriptor declares alpha bits.
+    pub fn from_tga_bytes(bytes: &[u8]) -> Result<BmpFile, ImageError> {
+        let header = bytes
+            .get(0..18)
+            .ok_or_else(|| malformed("data ends inside the header"))?;
+        let (id_len, color_map_type, image_type) = (header[0], header[1], header[2]);
+        let color_map_len = LittleEndian::read_u16(&header[5..7]) as usize;
+        let color_map_bits = header[7] as usize;
+        let width = LittleEndian::read_u16(&header[12..14]) as usize;
+        let height = LittleEndian::read_u16(&header[14..16]) as usize;
+        let (bits_per_pixel, descriptor) = (header[16], header[17]);
+        if !matches!(image_type, UNCOMPRESSED | RUN_LENGTH) || !matches!(bits_per_pixel, 24 | 32) {
+            return Err(malformed(
+                "only 24- and 32-bit true-color images are supported",
+            ));
+        }
+        Header::for_dimensions(width, height, 32).check_size(DEFAULT_MAX_PIXEL_BYTES)?;
+        let has_alpha = bits_per_pixel == 32 && descriptor & 0x0F != 0;
+        let bytes_per_pixel = bits_per_pixel as usize / 8;
+        // True-color images may still carry a color map, which is skipped.
+        let color_map_bytes = if color_map_type == 1 {
+            (color_map_len * color_map_bits).div_ceil(8)
+        } else {
+            0
+        };
+        let mut data = bytes
+            .get(18 + id_len as usize + color_map_bytes..)
+            .ok_or_else(|| malformed("data ends inside the color map"))?;
+        let count = width * height;
+        let mut pixels: Vec<Color> = Vec::with_capacity(count);
+        if image_type == UNCOMPRESSED {
+            if data.len() < count * bytes_per_pixel {
+                return Err(malformed("pixel data ends early"));
+            }
+            pixels.extend(
+                data.chunks_exact(bytes_per_pixel)
+                    .take(count)
+                    .map(|bytes| pixel(bytes, has_alpha)),
+            );
+        } else {
+            while pixels.len() < count {
+                let (&packet, rest) = data
+                    .split_first()
+                    .ok_or_else(|| malformed("pixel data ends early"))?;
+                let len = (packet & 0x7F) as usize + 1;
+                let stored = if packet & 0x80 != 0 { 1 } else { len };
+                let values = rest
+                    .get(..stored * bytes_per_pixel)
+                    .ok_or_else(|| malformed("pixel data ends early"))?;
+                if packet & 0x80 != 0 {
+                    let repeated = pixel(values, has_alpha);
+                    pixels.extend(std::iter::repeat_n(repeated, len));
+                } else {
+                    let literal = values.chunks_exact(bytes_per_pixel);
+                    pixels.extend(literal.map(|bytes| pixel(bytes, has_alpha)));
+                }
+                data = &rest[stored * bytes_per_pixel..];
+            }
+            // A packet may run past the last pixel of a malformed file.
+            pixels.truncate(count);
+        }
+        let width_or_one = width.max(1);
+        if descriptor & TOP_TO_BOTTOM == 0 {
+            let mut rows: Vec<&[Color]> = pixels.chunks(width_or_one).collect();
+            rows.reverse();
+            pixels = rows.concat();
+        }
+        if descriptor & RIGHT_TO_LEFT != 0 {
+            pixels
+                .chunks_mut(width_or_one)
+                .for_each(|row| row.reverse());
+        }
+        Ok(BmpFile::from_pixels(width, height, pixels))
+    }
+    /// The image encoded as a bottom-up TGA, 32-bit if any pixel is translucent and 24-bit
+    /// otherwise. Run-length packets never span two rows. Fails with
+    /// [`ImageError::DimensionsExceedFormat`] if either side is over 65535 pixels.
+    pub fn to_tga_bytes(&self, rle: bool) -> Result<Vec<u8>, ImageError> {
+        let (width, height) = (self.header.width, self.header.height);
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(ImageError::DimensionsExceedFormat {
+                format: "TGA",
+                width,
+                height,
+                max: u16::MAX as usize,
+            });
+        }
+        let has_alpha = self.pixels.iter().any(|pixel| pixel.a != 255);
+        let bytes_per_pixel = if has_alpha { 4 } else { 3 };
+        let mut out = vec![0; 18];
+        out[2] = if rle { RUN_LENGTH } else { UNCOMPRESSED };
+        LittleEndian::write_u16(&mut out[12..14], width as u16);
+        LittleEndian::write_u16(&mut out[14..16], height as u16);
+        out[16] = bytes_per_pixel as u8 * 8;
+        out[17] = if has_alpha { 8 } else { 0 };
+        out.reserve(width * height * bytes_per_pixel);
+        let write = |out: &mut Vec<u8>, pixel: &Color| {
+            out.extend_from_slice(&[pixel.b, pixel.g, pixel.r, pixel.a][..bytes_per_pixel]);
+        };
+        for row in self.pixels.chunks_exact(width.max(1)).rev() {
+            if !rle {
+                row.iter().for_each(|pixel| write(&mut out, pixel));
+                continue;
+            }
+            let mut rest = row;
+            while let Some(first) = rest.first() {
+                let run = rest
+                    .iter()
+                    .take(128)
+                    .take_while(|&pixel| pixel == first)
+                    .count();
+                if run > 1 {
+                    out.push(0x80 | (run - 1) as u8);
+                    write(&mut out, first);
+                    rest = &rest[run..];
+                    continue;
+                }
+                // A literal packet stops where a run of at least two identical pixels starts.
+                let literal = rest
+                    .windows(2)
+                    .take(128)
+                    .position(|pair| pair[0] == pair[1])
+                    .unwrap_or(rest.len().min(128));
+                out.push((literal - 1) as u8);
+                rest[..literal]
+                    .iter()
+                    .for_each(|pixel| write(&mut out, pixel));
+                rest = &rest[literal..];
+            }
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,140 @@
+//! Tone adjustments that map every channel value through a 256-entry lookup table, see
+//! [`Image::levels`] and [`Image::curves`].
+//!
+//! [`Image::levels`]: crate::Image::levels
+//! [`Image::curves`]: crate::Image::curves
+
+use num::clamp;
+use std::collections::BTreeMap;
+
+/// A levels adjustment as found in photo editors: input values from `in_black` to
+/// `in_white` are stretched over the full range, bent by `gamma`, then compressed into
+/// `out_black..=out_white`. Values outside the input range are clipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub in_black: u8,
+    pub in_white: u8,
+    /// Midtone adjustment; above 1 brightens and below 1 darkens, as in [`Image::gamma`].
+    ///
+    /// [`Image::gamma`]: crate::Image::gamma
+    pub gamma: f64,
+    pub out_black: u8,
+    pub out_white: u8,
+}
+
+impl Default for Levels {
+    /// The identity: full input and output ranges with a gamma of 1.
+    fn default() -> Levels {
+        Levels {
+            in_black: 0,
+            in_white: 255,
+            gamma: 1.0,
+            out_black: 0,
+            out_white: 255,
+        }
+    }
+}
+
+impl Levels {
+    pub(crate) fn lut(&self) -> [u8; 256] {
+        let (in_black, in_white) = (self.in_black as f64, self.in_white as f64);
+        let (out_black, out_white) = (self.out_black as f64, self.out_white as f64);
+        let mut lut = [0; 256];
+        for (value, entry) in lut.iter_mut().enumerate() {
+            let t = if in_white > in_black {
+                clamp((value as f64 - in_black) / (in_white - in_black), 0.0, 1.0)
+            } else if value as f64 >= in_black {
+                1.0
+            } else {
+                0.0
+            };
+            let t = t.powf(1.0 / self.gamma);
+            *entry = clamp(
+                (out_black + t * (out_white - out_black)).round(),
+                0.0,
+                255.0,
+            ) as u8;
+        }
+        lut
+    }
+}
+
+/// The lookup table for a tone curve through the control points `(input, output)`, joined
+/// by a monotone cubic so the curve never overshoots between points. Points may come in any
+/// order; of several with the same input the last wins. Past the first and last points the
+/// curve stays flat, and no points at all give the identity.
+pub(crate) fn curve_lut(points: &[(u8, u8)]) -> [u8; 256] {
+    let mut lut = [0; 256];
+    let sorted: Vec<(f64, f64)> = points
+        .iter()
+        .copied()
+        .collect::<BTreeMap<u8, u8>>()
+        .into_iter()
+        .map(|(input, output)| (input as f64, output as f64))
+        .collect();
+    if sorted.is_empty() {
+        for (value, entry) in lut.iter_mut().enumerate() {
+            *entry = value as u8;
+        }
+        return lut;
+    }
+    let tangents = monotone_tangents(&sorted);
+    for (value, entry) in lut.iter_mut().enumerate() {
+        let x = value as f64;
+        let segment = sorted.partition_point(|&(input, _)| input <= x);
+        let output = if segment == 0 {
+            sorted[0].1
+        } else if segment == sorted.len() {
+            sorted[sorted.len() - 1].1
+        } else {
+            let ((x0, y0), (x1, y1)) = (sorted[segment - 1], sorted[segment]);
+            let (m0, m1) = (tangents[segment - 1], tangents[segment]);
+            let h = x1 - x0;
+            let t = (x - x0) / h;
+            let (t2, t3) = (t * t, t * t * t);
+            (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * m0
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * m1
+        };
+        *entry = clamp(output.round(), 0.0, 255.0) as u8;
+    }
+    lut
+}
+
+/// Slopes at each of `points` (sorted by distinct x) for a Fritsch-Carlson monotone cubic.
+fn monotone_tangents(points: &[(f64, f64)]) -> Vec<f64> {
+    let count = points.len();
+    let secants: Vec<f64> = points
+        .windows(2)
+        .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+        .collect();
+    let mut tangents = vec![0.0; count];
+    if count < 2 {
+        return tangents;
+    }
+    tangents[0] = secants[0];
+    tangents[count - 1] = secants[count - 2];
+    for index in 1..count - 1 {
+        let (before, after) = (secants[index - 1], secants[index]);
+        tangents[index] = if before * after <= 0.0 {
+            0.0
+        } else {
+            (before + after) / 2.0
+        };
+    }
+    for (index, &secant) in secants.iter().enumerate() {
+        if secant == 0.0 {
+            tangents[index] = 0.0;
+            tangents[index + 1] = 0.0;
+            continue;
+        }
+        let (alpha, beta) = (tangents[index] / secant, tangents[index + 1] / secant);
+        let length = alpha.hypot(beta);
+        if length > 3.0 {
+            tangents[index] = 3.0 * alpha / length * secant;
+            tangents[index + 1] = 3.0 * beta / length * secant;
+        }
+    }
+    tangents
+}
@@ -0,0 +1,638 @@
+//! TrueType font loading and anti-aliased text, enabled by the `truetype` feature.
+//!
+//! Fonts with TrueType (quadratic) outlines are supported, whether named `.ttf` or `.otf`.
+//! CFF-flavoured OpenType fonts and font collections are rejected. Characters are mapped
+//! through a Unicode `cmap` subtable in format 4 or 12, and pairs are kerned from the legacy
+//! `kern` table; kerning only present in `GPOS` is not applied, and no hinting is done.
+//!
+//! Each glyph outline is flattened with [`Path`] and rasterized by accumulating the signed
+//! area every edge covers in each pixel, which gives exact coverage for the non-overlapping
+//! contours fonts are drawn with.
+
+use crate::shape::Point;
+use crate::{ImageError, Path};
+use std::collections::HashMap;
+
+/// How deeply composite glyphs may nest before the rest is ignored.
+const MAX_COMPONENT_DEPTH: usize = 8;
+/// How many components one glyph may draw in total, counting every nesting level, before
+/// the rest are ignored. Bounds the work of components that reuse a glyph many times over.
+const MAX_COMPONENTS: usize = 256;
+
+fn malformed(reason: &str) -> ImageError {
+    ImageError::Malformed {
+        format: "TrueType",
+        reason: reason.to_string(),
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *data.get(offset)?,
+        *data.get(offset + 1)?,
+    ]))
+}
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    u16_at(data, offset).map(|value| value as i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// A glyph contour point in font units, and whether it lies on the curve rather than being
+/// a quadratic control point.
+type OutlinePoint = (f64, f64, bool);
+
+/// A TrueType font, see [`Image::draw_text_ttf`].
+///
+/// [`Image::draw_text_ttf`]: crate::Image::draw_text_ttf
+#[derive(Debug, Clone)]
+pub struct Font {
+    data: Vec<u8>,
+    units_per_em: f64,
+    /// Line metrics from `hhea`, in font units with y growing upwards.
+    ascent: f64,
+    descent: f64,
+    line_gap: f64,
+    glyph_count: u16,
+    long_loca: bool,
+    loca: usize,
+    glyf: usize,
+    hmtx: usize,
+    metric_count: u16,
+    /// Offset of the chosen `cmap` subtable and its format.
+    cmap: (usize, u16),
+    kerning: HashMap<(u16, u16), i16>,
+}
+
+impl Font {
+    /// Reads and parses the font file at `path`.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Font, ImageError> {
+        Font::from_bytes(std::fs::read(path)?)
+    }
+    /// Parses a font from the contents of a font file.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font, ImageError> {
+        let truncated = || malformed("a table is truncated");
+        match u32_at(&data, 0).ok_or_else(truncated)? {
+            0x0001_0000 | 0x7472_7565 => {}
+            0x4F54_544F => return Err(malformed("CFF outlines are not supported")),
+            0x7474_6366 => return Err(malformed("font collections are not supported")),
+            _ => return Err(malformed("not a TrueType font")),
+        }
+        let table_count = u16_at(&data, 4).ok_or_else(truncated)? as usize;
+        let mut tables = HashMap::new();
+        for index in 0..table_count {
+            let record = 12 + 16 * index;
+            let tag = data.get(record..record + 4).ok_or_else(truncated)?;
+            let offset = u32_at(&data, record + 8).ok_or_else(truncated)? as usize;
+            tables.insert(tag.to_vec(), offset);
+        }
+        let table = |tag: &[u8]| {
+            tables.get(tag).copied().ok_or_else(|| {
+                malformed(&format!(
+                    "missing {} table",
+                    String::from_utf8_lossy(tag).trim_end()
+                ))
+            })
+        };
+        let (head, maxp, hhea) = (table(b"head")?, table(b"maxp")?, table(b"hhea")?);
+        let units_per_em = u16_at(&data, head + 18).ok_or_else(truncated)?;
+        if units_per_em == 0 {
+            return Err(malformed("zero units per em"));
+        }
+        let font = Font {
+            units_per_em: units_per_em as f64,
+            ascent: i16_at(&data, hhea + 4).ok_or_else(truncated)? as f64,
+            descent: i16_at(&data, hhea + 6).ok_or_else(truncated)? as f64,
+            line_gap: i16_at(&data, hhea + 8).ok_or_else(truncated)? as f64,
+            glyph_count: u16_at(&data, maxp + 4).ok_or_else(truncated)?,
+            long_loca: i16_at(&data, head + 50).ok_or_else(truncated)? != 0,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            hmtx: table(b"hmtx")?,
+            metric_count: u16_at(&data, hhea + 34).ok_or_else(truncated)?,
+            cmap: unicode_subtable(&data, table(b"cmap")?)
+                .ok_or_else(|| malformed("no Unicode cmap subtable in format 4 or 12"))?,
+            kerning: tables
+                .get(&b"kern"[..])
+                .map(|&kern| kerning_pairs(&data, kern))
+                .unwrap_or_default(),
+            data,
+        };
+        Ok(font)
+    }
+    /// The width and height in pixels [`Image::draw_text_ttf`] covers with `text` at `size`
+    /// pixels per em: the widest line by the line height times the number of lines.
+    ///
+    /// [`Image::draw_text_ttf`]: crate::Image::draw_text_ttf
+    pub fn text_size(&self, text: &str, size: f64) -> (usize, usize) {
+        let scale = size / self.units_per_em;
+        let width = text
+            .lines()
+            .map(|line| {
+                let glyphs: Vec<u16> = line.chars().map(|c| self.glyph_index(c)).collect();
+                let advances: f64 = glyphs.iter().map(|&glyph| self.advance(glyph)).sum();
+                let kerning: f64 = glyphs
+                    .windows(2)
+                    .map(|pair| self.kerning(pair[0], pair[1]))
+                    .sum();
+                (advances + kerning) * scale
+            })
+            .fold(0.0, f64::max);
+        let height = self.line_height() * scale * text.lines().count() as f64;
+        (width.ceil() as usize, height.ceil() as usize)
+    }
+    /// Calls `visit` with the position relative to the text's top-left corner and the
+    /// coverage of every pixel `text` touches at `size` pixels per em, among the columns
+    /// `left..right` and rows `top..bottom` of `clip`, given in the same coordinates. Pixels
+    /// where glyphs meet may be visited more than once.
+    pub(crate) fn rasterize<F>(&self, text: &str, size: f64, clip: [isize; 4], mut visit: F)
+    where
+        F: FnMut(isize, isize, f64),
+    {
+        let scale = size / self.units_per_em;
+        for (line_index, line) in text.lines().enumerate() {
+            let baseline = (self.ascent + self.line_height() * line_index as f64) * scale;
+            let mut pen = 0.0;
+            let mut previous = None;
+            for character in line.chars() {
+                let glyph = self.glyph_index(character);
+                if let Some(previous) = previous {
+                    pen += self.kerning(previous, glyph) * scale;
+                }
+                let mut path = Path::new();
+                let mut components = MAX_COMPONENTS;
+                for contour in self.outline(glyph, 0, &mut components) {
+                    path = contour_path(path, &contour, |x, y| {
+                        (pen + x * scale, baseline - y * scale)
+                    });
+                }
+                rasterize_path(&path, clip, &mut visit);
+                pen += self.advance(glyph) * scale;
+                previous = Some(glyph);
+            }
+        }
+    }
+    fn line_height(&self) -> f64 {
+        self.ascent - self.descent + self.line_gap
+    }
+    /// The glyph for `character`, or 0 (the missing glyph) if the font has none.
+    fn glyph_index(&self, character: char) -> u16 {
+        let (subtable, format) = self.cmap;
+        let data = &self.data;
+        let code = character as u32;
+        let glyph = match format {
+            4 => (|| {
+                let code = u16::try_from(code).ok()?;
+                let segments = u16_at(data, subtable + 6)? as usize / 2;
+                let ends = subtable + 14;
+                let starts = ends + 2 * segments + 2;
+                let deltas = starts + 2 * segments;
+                let range_offsets = deltas + 2 * segments;
+                let segment = (0..segments).find(|&i| u16_at(data, ends + 2 * i) >= Some(code))?;
+                let start = u16_at(data, starts + 2 * segment)?;
+                if code < start {
+                    return None;
+                }
+                let delta = u16_at(data, deltas + 2 * segment)?;
+                let range_offset_at = range_offsets + 2 * segment;
+                match u16_at(data, range_offset_at)? {
+                    0 => Some(code.wrapping_add(delta)),
+                    range_offset => {
+                        let at =
+                            range_offset_at + range_offset as usize + 2 * (code - start) as usize;
+                        let glyph = u16_at(data, at)?;
+                        (glyph != 0).then(|| glyph.wrapping_add(delta))
+                    }
+                }
+            })(),
+            _ => (|| {
+                let groups = u32_at(data, subtable + 12)? as usize;
+                (0..groups).find_map(|i| {
+                    let group = subtable + 16 + 12 * i;
+                    let (start, end) = (u32_at(data, group)?, u32_at(data, group + 4)?);
+                    let first_glyph = u32_at(data, group + 8)?;
+                    (start..=end)
+                        .contains(&code)
+                        .then(|| first_glyph.wrapping_add(code - start) as u16)
+                })
+            })(),
+        };
+        glyph.filter(|&glyph| glyph < self.glyph_count).unwrap_or(0)
+    }
+    /// Horizontal advance of `glyph` in font units.
+    fn advance(&self, glyph: u16) -> f64 {
+        let metric = glyph.min(self.metric_count.saturating_sub(1)) as usize;
+        u16_at(&self.data, self.hmtx + 4 * metric).unwrap_or(0) as f64
+    }
+    /// Kerning adjustment between `left` and `right` in font units.
+    fn kerning(&self, left: u16, right: u16) -> f64 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0) as f64
+    }
+    /// The byte range of `glyph` in the `glyf` table, `None` for empty glyphs.
+    fn glyph_range(&self, glyph: u16) -> Option<(usize, usize)> {
+        let glyph = glyph as usize;
+        let (start, end) = if self.long_loca {
+            (
+                u32_at(&self.data, self.loca + 4 * glyph)? as usize,
+                u32_at(&self.data, self.loca + 4 * glyph + 4)? as usize,
+            )
+        } else {
+            (
+                u16_at(&self.data, self.loca + 2 * glyph)? as usize * 2,
+                u16_at(&self.data, self.loca + 2 * glyph + 2)? as usize * 2,
+            )
+        };
+        (end > start).then_some((self.glyf + start, self.glyf + end))
+    }
+    /// The contours of `glyph` in font units. Malformed glyphs come back empty. Each
+    /// component drawn takes one from `components`, and none are drawn once it runs out.
+    fn outline(&self, glyph: u16, depth: usize, components: &mut usize) -> Vec<Vec<OutlinePoint>> {
+        let Some((start, end)) = self.glyph_range(glyph) else {
+            return Vec::new();
+        };
+        let Some(data) = self.data.get(start..end) else {
+            return Vec::new();
+        };
+        match i16_at(data, 0) {
+            Some(contours) if contours >= 0 => {
+                simple_outline(data, contours as usize).unwrap_or_default()
+            }
+            Some(_) if depth < MAX_COMPONENT_DEPTH => {
+                self.composite_outline(data, depth, components)
+            }
+            _ => Vec::new(),
+        }
+    }
+    /// The contours of a composite glyph: its components, each transformed into place.
+    fn composite_outline(
+        &self,
+        data: &[u8],
+        depth: usize,
+        components: &mut usize,
+    ) -> Vec<Vec<OutlinePoint>> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAVE_A_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAVE_X_AND_Y_SCALE: u16 = 0x0040;
+        const HAVE_TWO_BY_TWO: u16 = 0x0080;
+        let f2dot14 = |offset: usize| i16_at(data, offset).map(|value| value as f64 / 16384.0);
+        let mut contours = Vec::new();
+        let mut offset = 10;
+        loop {
+            let component = || -> Option<(u16, u16, [f64; 6], usize)> {
+                let flags = u16_at(data, offset)?;
+                let glyph = u16_at(data, offset + 2)?;
+                let mut at = offset + 4;
+                let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+                    at += 4;
+                    (i16_at(data, at - 4)? as f64, i16_at(data, at - 2)? as f64)
+                } else {
+                    at += 2;
+                    (
+                        *data.get(at - 2)? as i8 as f64,
+                        *data.get(at - 1)? as i8 as f64,
+                    )
+                };
+                // Anchoring components by matching points is not supported.
+                let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                    (dx, dy)
+                } else {
+                    (0.0, 0.0)
+                };
+                let [a, b, c, d] = if flags & HAVE_A_SCALE != 0 {
+                    at += 2;
+                    let scale = f2dot14(at - 2)?;
+                    [scale, 0.0, 0.0, scale]
+                } else if flags & HAVE_X_AND_Y_SCALE != 0 {
+                    at += 4;
+                    [f2dot14(at - 4)?, 0.0, 0.0, f2dot14(at - 2)?]
+                } else if flags & HAVE_TWO_BY_TWO != 0 {
+                    at += 8;
+                    [
+                        f2dot14(at - 8)?,
+                        f2dot14(at - 6)?,
+                        f2dot14(at - 4)?,
+                        f2dot14(at - 2)?,
+                    ]
+                } else {
+                    [1.0, 0.0, 0.0, 1.0]
+                };
+                Some((flags, glyph, [a, b, c, d, dx, dy], at))
+            };
+            let Some((flags, glyph, [a, b, c, d, dx, dy], next)) = component() else {
+                break;
+            };
+            if *components == 0 {
+                break;
+            }
+            *components -= 1;
+            for contour in self.outline(glyph, depth + 1, components) {
+                contours.push(
+                    contour
+                        .into_iter()
+                        .map(|(x, y, on)| (a * x + c * y + dx, b * x + d * y + dy, on))
+                        .collect(),
+                );
+            }
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+            offset = next;
+        }
+        contours
+    }
+}
+
+/// The offset and format of the best Unicode subtable of the `cmap` table at `cmap`,
+/// preferring format 12, which reaches beyond the Basic Multilingual Plane.
+fn unicode_subtable(data: &[u8], cmap: usize) -> Option<(usize, u16)> {
+    let count = u16_at(data, cmap + 2)? as usize;
+    let mut best: Option<(usize, u16)> = None;
+    for index in 0..count {
+        let record = cmap + 4 + 8 * index;
+        let platform = u16_at(data, record)?;
+        let encoding = u16_at(data, record + 2)?;
+        let subtable = cmap + u32_at(data, record + 4)? as usize;
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        let format = u16_at(data, subtable)?;
+        if unicode && (format == 12 || (format == 4 && best.is_none())) {
+            best = Some((subtable, format));
+        }
+    }
+    best
+}
+
+/// Horizontal kerning pairs from every format 0 subtable of the `kern` table at `kern`.
+fn kerning_pairs(data: &[u8], kern: usize) -> HashMap<(u16, u16), i16> {
+    let mut pairs = HashMap::new();
+    let subtable_count = u16_at(data, kern + 2).unwrap_or(0);
+    let mut subtable = kern + 4;
+    for _ in 0..subtable_count {
+        let (Some(length), Some(coverage)) =
+            (u16_at(data, subtable + 2), u16_at(data, subtable + 4))
+        else {
+            break;
+        };
+        // Horizontal, format 0, holding kerning values rather than minimums or cross-stream.
+        if coverage & 0xFF07 == 0x0001 {
+            let pair_count = u16_at(data, subtable + 6).unwrap_or(0) as usize;
+            for index in 0..pair_count {
+                let pair = subtable + 14 + 6 * index;
+                if let (Some(left), Some(right), Some(value)) = (
+                    u16_at(data, pair),
+                    u16_at(data, pair + 2),
+                    i16_at(data, pair + 4),
+                ) {
+                    pairs.insert((left, right), value);
+                }
+            }
+        }
+        subtable += length as usize;
+    }
+    pairs
+}
+
+/// The contours of a simple glyph whose data starts at the beginning of `data`.
+fn simple_outline(data: &[u8], contour_count: usize) -> Option<Vec<Vec<OutlinePoint>>> {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+    let ends: Vec<usize> = (0..contour_count)
+        .map(|i| u16_at(data, 10 + 2 * i).map(|end| end as usize))
+        .collect::<Option<_>>()?;
+    let point_count = ends.last().map_or(0, |&end| end + 1);
+    let instructions = 10 + 2 * contour_count;
+    let mut offset = instructions + 2 + u16_at(data, instructions)? as usize;
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = *data.get(offset)?;
+        offset += 1;
+        let repeats = if flag & REPEAT != 0 {
+            offset += 1;
+            *data.get(offset - 1)? as usize
+        } else {
+            0
+        };
+        flags.extend(std::iter::repeat_n(flag, repeats + 1));
+    }
+    flags.truncate(point_count);
+    let mut read_coordinates = |short: u8, same_or_positive: u8| -> Option<Vec<f64>> {
+        let mut value = 0i32;
+        let mut coordinates = Vec::with_capacity(point_count);
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = *data.get(offset)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 {
+                    delta
+                } else {
+                    -delta
+                };
+            } else if flag & same_or_positive == 0 {
+                value += i16_at(data, offset)? as i32;
+                offset += 2;
+            }
+            coordinates.push(value as f64);
+        }
+        Some(coordinates)
+    };
+    let xs = read_coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = read_coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+    let mut contours = Vec::with_capacity(contour_count);
+    let mut start = 0;
+    for &end in &ends {
+        if end < start || end >= point_count {
+            return None;
+        }
+        contours.push(
+            (start..=end)
+                .map(|i| (xs[i], ys[i], flags[i] & ON_CURVE != 0))
+                .collect(),
+        );
+        start = end + 1;
+    }
+    Some(contours)
+}
+
+/// Adds the closed `contour` to `path`, mapping font units to pixels with `transform`.
+/// Consecutive control points have an implied on-curve point halfway between them.
+fn contour_path<T: Fn(f64, f64) -> Point>(
+    path: Path,
+    contour: &[OutlinePoint],
+    transform: T,
+) -> Path {
+    let mut points: Vec<(Point, bool)> = Vec::with_capacity(contour.len() * 2);
+    for (index, &(x, y, on_curve)) in contour.iter().enumerate() {
+        let (next_x, next_y, next_on_curve) = contour[(index + 1) % contour.len()];
+        points.push((transform(x, y), on_curve));
+        if !on_curve && !next_on_curve {
+            points.push((transform((x + next_x) / 2.0, (y + next_y) / 2.0), true));
+        }
+    }
+    let Some(first) = points.iter().position(|&(_, on_curve)| on_curve) else {
+        return path;
+    };
+    points.rotate_left(first);
+    let ((start_x, start_y), _) = points[0];
+    let mut path = path.move_to(start_x, start_y);
+    let mut control = None;
+    for index in 1..=points.len() {
+        let ((x, y), on_curve) = points[index % points.len()];
+        if !on_curve {
+            control = Some((x, y));
+        } else if let Some((cx, cy)) = control.take() {
+            path = path.quad_to(cx, cy, x, y);
+        } else {
+            path = path.line_to(x, y);
+        }
+    }
+    path.close()
+}
+
+/// Calls `visit` with every pixel inside `clip` (left, top, right and bottom, exclusive)
+/// that the closed subpaths of `path` cover and how much of it they cover, taking whole
+/// numbers as pixel corners. Only the clipped area is allocated, however large the path.
+fn rasterize_path<F: FnMut(isize, isize, f64)>(path: &Path, clip: [isize; 4], visit: &mut F) {
+    let points = || {
+        path.subpaths
+            .iter()
+            .flat_map(|subpath| subpath.points.iter())
+    };
+    let (left, top) = points().fold((f64::INFINITY, f64::INFINITY), |(left, top), &(x, y)| {
+        (left.min(x), top.min(y))
+    });
+    let (right, bottom) = points()
+        .fold((f64::NEG_INFINITY, f64::NEG_INFINITY), |(r, b), &(x, y)| {
+            (r.max(x), b.max(y))
+        });
+    if !(left.is_finite() && top.is_finite() && right.is_finite() && bottom.is_finite()) {
+        return;
+    }
+    let [clip_left, clip_top, clip_right, clip_bottom] = clip.map(|bound| bound as f64);
+    let (left, top) = (left.floor().max(clip_left), top.floor().max(clip_top));
+    let (right, bottom) = (right.ceil().min(clip_right), bottom.ceil().min(clip_bottom));
+    if right <= left || bottom <= top {
+        return;
+    }
+    let visible = (right - left) as usize;
+    // One column past the visible ones collects everything right of them, plus slack for
+    // edges ending exactly on that column.
+    let width = visible + 1;
+    let height = (bottom - top) as usize;
+    let mut accumulation = vec![0.0; width * height + 2];
+    for subpath in &path.subpaths {
+        let points = &subpath.points;
+        for (index, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(index + 1) % points.len()];
+            let from = (x0 - left, y0 - top);
+            let to = (x1 - left, y1 - top);
+            clip_edge(from, to, visible as f64, |from, to| {
+                accumulate_edge(&mut accumulation, width, height, from, to);
+            });
+        }
+    }
+    let mut area = 0.0;
+    for (index, &delta) in accumulation[..width * height].iter().enumerate() {
+        if index % width == visible {
+            area = 0.0;
+            continue;
+        }
+        area += delta;
+        let coverage = f64::min(area.abs(), 1.0);
+        if coverage > 1.0 / 512.0 {
+            let (x, y) = (index % width, index / width);
+            visit(
+                left as isize + x as isize,
+                top as isize + y as isize,
+                coverage,
+            );
+        }
+    }
+}
+
+/// Splits the edge from `from` to `to` where it crosses x = 0 and x = `right`, and passes
+/// each piece to `emit` with its x clamped to that range. Pieces past either side become
+/// vertical edges along it, which cover the same pixels inside the range as the originals.
+fn clip_edge<F: FnMut(Point, Point)>(from: Point, to: Point, right: f64, mut emit: F) {
+    let mut splits = [0.0, 1.0, 1.0, 1.0];
+    for (slot, bound) in [0.0, right].into_iter().enumerate() {
+        let t = (bound - from.0) / (to.0 - from.0);
+        if t > 0.0 && t < 1.0 {
+            splits[slot + 1] = t;
+        }
+    }
+    splits.sort_by(f64::total_cmp);
+    let at = |t: f64| {
+        let x = from.0 + (to.0 - from.0) * t;
+        let y = from.1 + (to.1 - from.1) * t;
+        (x.clamp(0.0, right), y)
+    };
+    for pair in splits.windows(2).filter(|pair| pair[1] > pair[0]) {
+        emit(at(pair[0]), at(pair[1]));
+    }
+}
+
+/// Adds the signed area the edge from `from` to `to` covers to the right of itself in each
+/// pixel, as differences along the row so that a running sum gives every pixel's coverage.
+fn accumulate_edge(accumulation: &mut [f64], width: usize, height: usize, from: Point, to: Point) {
+    if from.1 == to.1 {
+        return;
+    }
+    let (direction, (x0, y0), (x1, y1)) = if from.1 < to.1 {
+        (1.0, from, to)
+    } else {
+        (-1.0, to, from)
+    };
+    let dxdy = (x1 - x0) / (y1 - y0);
+    let mut x = x0;
+    let first_row = y0.max(0.0) as usize;
+    if y0 < 0.0 {
+        x -= y0 * dxdy;
+    }
+    for row in first_row..height.min(y1.ceil() as usize) {
+        let line_start = row * width;
+        let dy = ((row + 1) as f64).min(y1) - (row as f64).max(y0);
+        let next_x = x + dxdy * dy;
+        let d = dy * direction;
+        let (left, right) = if x < next_x { (x, next_x) } else { (next_x, x) };
+        let left_floor = left.floor();
+        let left_index = left_floor as usize;
+        let right_ceil = right.ceil();
+        let right_index = right_ceil as usize;
+        if right_index <= left_index + 1 {
+            // The edge stays within one pixel of this row.
+            let middle = 0.5 * (x + next_x) - left_floor;
+            accumulation[line_start + left_index] += d - d * middle;
+            accumulation[line_start + left_index + 1] += d * middle;
+        } else {
+            let slope = (right - left).recip();
+            let left_fraction = left - left_floor;
+            let first_area = 0.5 * slope * (1.0 - left_fraction) * (1.0 - left_fraction);
+            let right_fraction = right - right_ceil + 1.0;
+            let last_area = 0.5 * slope * right_fraction * right_fraction;
+            accumulation[line_start + left_index] += d * first_area;
+            if right_index == left_index + 2 {
+                accumulation[line_start + left_index + 1] += d * (1.0 - first_area - last_area);
+            } else {
+                let second_area = slope * (1.5 - left_fraction);
+                accumulation[line_start + left_index + 1] += d * (second_area - first_area);
+                for column in left_index + 2..right_index - 1 {
+                    accumulation[line_start + column] += d * slope;
+                }
+                let before_last = second_area + (right_index - left_index - 3) as f64 * slope;
+                accumulation[line_start + right_index - 1] += d * (1.0 - before_last - last_area);
+            }
+            accumulation[line_start + right_index] += d * last_area;
+        }
+        x = next_x;
+    }
+}
@@ -0,0 +1,95 @@
+//! Rectangular windows onto part of an image, addressed in their own coordinates with
+//! (0, 0) at the window's top-left corner. Views borrow the image, so nothing is copied.
+
+use crate::{BmpFile, Color, Image, ReadImage};
+
+/// A read-only window onto a rectangle of an image, see [`ReadImage::view`].
+pub struct View<'a, I: ?Sized> {
+    image: &'a I,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a, I: ReadImage + ?Sized> View<'a, I> {
+    pub(crate) fn new(image: &'a I, x: usize, y: usize, width: usize, height: usize) -> Self {
+        View {
+            image,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+    /// Copies the viewed pixels out into a bitmap of their own.
+    pub fn to_bmp(&self) -> BmpFile {
+        let pixels = (0..self.height)
+            .flat_map(|y| (0..self.width).filter_map(move |x| self.get(x, y)))
+            .collect();
+        BmpFile::from_pixels(self.width, self.height, pixels)
+    }
+}
+
+impl<I: ReadImage + ?Sized> ReadImage for View<'_, I> {
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    /// The color at (x, y) within the view, or `None` outside it.
+    fn get(&self, x: usize, y: usize) -> Option<Color> {
+        if x < self.width && y < self.height {
+            self.image.get(self.x + x, self.y + y)
+        } else {
+            None
+        }
+    }
+}
+
+/// A mutable window onto a rectangle of an image, see [`Image::view_mut`]. Drawing and
+/// filters run on it only change pixels inside the rectangle, and sample nothing outside it.
+pub struct ViewMut<'a, I: ?Sized> {
+    image: &'a mut I,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a, I: Image + ?Sized> ViewMut<'a, I> {
+    pub(crate) fn new(image: &'a mut I, x: usize, y: usize, width: usize, height: usize) -> Self {
+        ViewMut {
+            image,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+impl<I: Image + ?Sized> ReadImage for ViewMut<'_, I> {
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn get(&self, x: usize, y: usize) -> Option<Color> {
+        if x < self.width && y < self.height {
+            self.image.get(self.x + x, self.y + y)
+        } else {
+            None
+        }
+    }
+}
+
+impl<I: Image + ?Sized> Image for ViewMut<'_, I> {
+    fn set(&mut self, x: usize, y: usize, color: Color) {
+        if x < self.width && y < self.height {
+            self.image.set(self.x + x, self.y + y, color);
+        }
+    }
+}
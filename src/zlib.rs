@@ -0,0 +1,361 @@
+//! Just enough zlib for PNG: a complete inflater and a deflater that emits a single
+//! fixed-Huffman block.
+//!
+//! Deflate streams are read least significant bit first. Huffman codes are canonical, so a
+//! table of code lengths is all either side needs to agree on a code.
+
+/// Base match length of length symbols 257..=285, and how many extra bits follow each.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distance of distance symbols 0..=29, and how many extra bits follow each.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order dynamic blocks list the code lengths of the code length alphabet in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const WINDOW: usize = 32 * 1024;
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, &'static str> {
+        while self.count < n {
+            let &byte = self
+                .data
+                .get(self.position)
+                .ok_or("deflate stream ends early")?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer = self.buffer.checked_shr(n).unwrap_or(0);
+        self.count -= n;
+        Ok(value)
+    }
+    /// Drops the rest of the current byte, as stored blocks start on a byte boundary.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols
+/// sorted by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|&symbol| lengths[symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+        Huffman { counts, symbols }
+    }
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return self
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied()
+                    .ok_or("invalid Huffman code");
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code")
+    }
+}
+
+/// The literal/length and distance codes of a fixed-Huffman block.
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut literals = [8; 288];
+    literals[144..256].fill(9);
+    literals[256..280].fill(7);
+    (literals, [5; 30])
+}
+
+/// Reads the code length tables at the start of a dynamic block.
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), &'static str> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let length_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let &previous = lengths.last().ok_or("repeat with no previous length")?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("code lengths overrun their tables");
+    }
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+/// Decompresses a zlib stream, refusing to produce more than `limit` bytes.
+pub(crate) fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, &'static str> {
+    let [method, flags, ..] = *data else {
+        return Err("zlib header is missing");
+    };
+    if method & 0x0F != 8
+        || !(method as u16 * 256 + flags as u16).is_multiple_of(31)
+        || flags & 0x20 != 0
+    {
+        return Err("not a plain deflate zlib stream");
+    }
+    let mut reader = BitReader {
+        data,
+        position: 2,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data
+                    .get(reader.position..reader.position + 4)
+                    .ok_or("stored block ends early")?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if len != !u16::from_le_bytes([header[2], header[3]]) as usize {
+                    return Err("stored block length check failed");
+                }
+                let start = reader.position + 4;
+                let block = data
+                    .get(start..start + len)
+                    .ok_or("stored block ends early")?;
+                if out.len() + len > limit {
+                    return Err("inflated data is larger than expected");
+                }
+                out.extend_from_slice(block);
+                reader.position = start + len;
+            }
+            kind @ (1 | 2) => {
+                let (literals, distances) = if kind == 1 {
+                    let (literals, distances) = fixed_lengths();
+                    (Huffman::new(&literals), Huffman::new(&distances))
+                } else {
+                    dynamic_codes(&mut reader)?
+                };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    if symbol < 256 {
+                        out.push(symbol as u8);
+                    } else if symbol == 256 {
+                        break;
+                    } else {
+                        let index = symbol - 257;
+                        let &base = LENGTH_BASE.get(index).ok_or("invalid length symbol")?;
+                        let len = base as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                        let index = distances.decode(&mut reader)? as usize;
+                        let &base = DISTANCE_BASE.get(index).ok_or("invalid distance symbol")?;
+                        let distance =
+                            base as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                        if distance > out.len() {
+                            return Err("distance reaches before the start of the data");
+                        }
+                        // Matches may overlap the bytes they produce, so copy one at a time.
+                        for _ in 0..len {
+                            out.push(out[out.len() - distance]);
+                        }
+                    }
+                    if out.len() > limit {
+                        return Err("inflated data is larger than expected");
+                    }
+                }
+            }
+            _ => return Err("invalid deflate block type"),
+        }
+        if last {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+    /// Writes a Huffman code, which unlike other fields starts with its most significant bit.
+    fn code(&mut self, code: u32, n: u32) {
+        self.bits(code.reverse_bits() >> (32 - n), n);
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// Writes `symbol` with the fixed literal/length code.
+fn fixed_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xC0 + symbol - 280, 8),
+    }
+}
+
+/// Writes a match, splitting its length and distance into symbol and extra bits.
+fn fixed_match(writer: &mut BitWriter, len: usize, distance: usize) {
+    let index = LENGTH_BASE.partition_point(|&base| base as usize <= len) - 1;
+    fixed_literal(writer, 257 + index as u32);
+    writer.bits(
+        (len - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index] as u32,
+    );
+    let index = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    writer.code(index as u32, 5);
+    writer.bits(
+        (distance - DISTANCE_BASE[index] as usize) as u32,
+        DISTANCE_EXTRA[index] as u32,
+    );
+}
+
+/// Compresses `data` into a zlib stream. Matches are found through hash chains over
+/// three-byte prefixes, followed a bounded number of steps back.
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    const CHAIN: usize = 32;
+    let hash = |at: usize| {
+        ((data[at] as usize) << 10 ^ (data[at + 1] as usize) << 5 ^ data[at + 2] as usize)
+            & (WINDOW - 1)
+    };
+    let mut head = vec![usize::MAX; WINDOW];
+    let mut previous = vec![usize::MAX; WINDOW];
+    let mut writer = BitWriter {
+        out: vec![0x78, 0x9C],
+        buffer: 0,
+        count: 0,
+    };
+    // A single final block using the fixed codes.
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+    let mut position = 0;
+    while position < data.len() {
+        let (mut best_len, mut best_distance) = (0, 0);
+        if position + 3 <= data.len() {
+            let mut candidate = head[hash(position)];
+            for _ in 0..CHAIN {
+                if candidate == usize::MAX || position - candidate > WINDOW - 1 {
+                    break;
+                }
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[position..])
+                    .take(258)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_distance) = (len, position - candidate);
+                }
+                let next = previous[candidate % WINDOW];
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+        if best_len >= 3 {
+            fixed_match(&mut writer, best_len, best_distance);
+        } else {
+            best_len = 1;
+            fixed_literal(&mut writer, data[position] as u32);
+        }
+        for at in (position..position + best_len).filter(|&at| at + 3 <= data.len()) {
+            let key = hash(at);
+            previous[at % WINDOW] = head[key];
+            head[key] = at;
+        }
+        position += best_len;
+    }
+    fixed_literal(&mut writer, 256);
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// The CRC-32 used by PNG chunks and gzip.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
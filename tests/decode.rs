@@ -0,0 +1,36 @@
+use image_manip::{BmpFile, Color, ReadImage};
+
+/// A 2x2 8-bit OS/2 bitmap with a 12-byte core header and its 256-entry, 3-byte palette.
+fn core_header_8_bit() -> Vec<u8> {
+    let offset = 14 + 12 + 256 * 3;
+    let mut bytes = b"BM".to_vec();
+    bytes.extend_from_slice(&(offset as u32 + 8).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+    bytes.extend_from_slice(&12u32.to_le_bytes());
+    for field in [2u16, 2, 1, 8] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    for index in 0..=255u8 {
+        // Blue, green, red.
+        bytes.extend_from_slice(&[index, 0, 255 - index]);
+    }
+    // Bottom-up rows padded to four bytes.
+    bytes.extend_from_slice(&[2, 3, 0, 0, 0, 1, 0, 0]);
+    bytes
+}
+
+#[test]
+fn core_header_8_bit_decodes() {
+    let bytes = core_header_8_bit();
+    for image in [
+        BmpFile::from_bytes(&bytes).unwrap(),
+        BmpFile::from_bytes_strict(&bytes).unwrap(),
+    ] {
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(image.get(0, 0), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(image.get(1, 0), Some(Color::rgb(254, 0, 1)));
+        assert_eq!(image.get(0, 1), Some(Color::rgb(253, 0, 2)));
+        assert_eq!(image.get(1, 1), Some(Color::rgb(252, 0, 3)));
+    }
+}
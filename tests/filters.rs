@@ -0,0 +1,81 @@
+use image_manip::{BmpFile, BorderMode, Color, Image, ImageError, ReadImage};
+
+#[test]
+fn convolve_rejects_malformed_kernels() {
+    let mut image = BmpFile::new(3, 3, Color::rgb(10, 20, 30));
+    for (kernel, size) in [(&[][..], 0), (&[1.0; 9][..], 2), (&[1.0; 8][..], 3)] {
+        assert!(matches!(
+            image.convolve(kernel, size, BorderMode::Replicate),
+            Err(ImageError::KernelSize { .. })
+        ));
+    }
+    let huge = image.convolve(&[1.0], usize::MAX, BorderMode::Replicate);
+    assert!(huge.unwrap_err().to_string().contains("kernel"));
+    assert_eq!(image.get(1, 1), Some(Color::rgb(10, 20, 30)));
+    image.convolve(&[0.5], 1, BorderMode::Replicate).unwrap();
+    assert_eq!(image.get(1, 1), Some(Color::rgb(5, 10, 15)));
+}
+
+#[test]
+fn median_filter_removes_salt_and_pepper_noise() {
+    let background = Color::rgb(90, 140, 200);
+    let mut image = BmpFile::new(12, 9, background);
+    // Isolated noise pixels, never more than one in any 3x3 window.
+    for (x, y) in [(1, 1), (5, 2), (9, 1), (3, 5), (7, 6), (10, 7), (0, 8)] {
+        let noise = if (x + y) % 2 == 0 {
+            Color::WHITE
+        } else {
+            Color::BLACK
+        };
+        image.set(x, y, noise);
+    }
+    image.median_filter(1);
+    assert!(image.pixels().all(|&pixel| pixel == background));
+}
+
+#[test]
+fn box_blur_clamps_huge_radii() {
+    let mut gradient = BmpFile::new(5, 3, Color::BLACK);
+    gradient.update(|x, y, _| Color::rgb(x as u8 * 60, y as u8 * 100, 7));
+    let mut clamped = gradient.clone();
+    clamped.box_blur(5);
+    gradient.box_blur(usize::MAX);
+    assert!(gradient.pixels().eq(clamped.pixels()));
+    assert!(gradient.pixels().all(|color| color.b() == 7));
+}
+
+#[test]
+fn gaussian_blur_clamps_huge_sigmas() {
+    let mut gradient = BmpFile::new(5, 3, Color::BLACK);
+    gradient.update(|x, y, _| Color::rgba(x as u8 * 60, y as u8 * 100, 7, 200));
+    let mut huge = gradient.clone();
+    huge.gaussian_blur(1e300);
+    gradient.gaussian_blur(f64::INFINITY);
+    assert!(gradient.pixels().eq(huge.pixels()));
+    assert!(gradient
+        .pixels()
+        .all(|color| color.b() == 7 && color.a() == 200));
+}
+
+#[test]
+fn unsharp_mask_steepens_edges_above_the_threshold() {
+    // A dark half and a light half, with faint noise on the dark side.
+    let mut image = BmpFile::new(16, 4, Color::BLACK);
+    image.update(|x, y, _| {
+        if x < 8 {
+            Color::gray(60 + ((x + y) % 2) as u8)
+        } else {
+            Color::gray(180)
+        }
+    });
+    let mut sharpened = image.clone();
+    sharpened.unsharp_mask(1.0, 1.0, 4);
+    for y in 0..4 {
+        let (dark, light) = (sharpened.get(7, y).unwrap(), sharpened.get(8, y).unwrap());
+        assert!(dark.r() < 60 && light.r() > 180, "row {y}");
+        // Away from the edge the noise stays under the threshold and is untouched.
+        for x in (0..4).chain(12..16) {
+            assert_eq!(sharpened.get(x, y), image.get(x, y), "({x}, {y})");
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use image_manip::{BmpFile, Color, Image, ImageError};
+use std::collections::HashSet;
+
+/// The first `count` values of the linear congruential generator, seeded with 1, that the
+/// GIF fixture's indices were drawn from.
+fn noise(count: usize) -> Vec<u32> {
+    let mut state = 1u32;
+    (0..count)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            state >> 16
+        })
+        .collect()
+}
+
+fn image(width: usize, height: usize, pixel: impl Fn(usize, usize) -> Color + Sync) -> BmpFile {
+    let mut image = BmpFile::new(width, height, Color::BLACK);
+    image.update(|x, y, _| pixel(x, y));
+    image
+}
+
+fn round_trip(image: &BmpFile) -> BmpFile {
+    BmpFile::from_gif_bytes(&image.to_gif_bytes().unwrap()).unwrap()
+}
+
+#[test]
+fn round_trips_up_to_256_colors_exactly() {
+    let image = image(20, 10, |x, y| {
+        let index = (y * 20 + x) as u8;
+        Color::rgb(index, 255 - index, index.wrapping_mul(7))
+    });
+    assert!(round_trip(&image).pixels().eq(image.pixels()));
+}
+
+#[test]
+fn round_trips_transparency() {
+    let mut image = BmpFile::new(6, 4, Color::rgb(0, 90, 180));
+    image.set_pixel(2, 1, Color::rgba(255, 0, 0, 10)).unwrap();
+    image.set_pixel(5, 3, Color::TRANSPARENT).unwrap();
+    image.set_pixel(0, 0, Color::rgba(10, 20, 30, 200)).unwrap();
+    let decoded = round_trip(&image);
+    let expected = image.pixels().map(|&pixel| match pixel.a() {
+        0..=127 => Color::TRANSPARENT,
+        _ => pixel.with_alpha(255),
+    });
+    assert!(decoded.pixels().copied().eq(expected));
+}
+
+#[test]
+fn round_trips_streams_that_fill_the_code_table() {
+    let values = noise(300 * 300);
+    let image = image(300, 300, |x, y| Color::gray(values[y * 300 + x] as u8));
+    assert!(round_trip(&image).pixels().eq(image.pixels()));
+}
+
+#[test]
+fn reduces_more_than_256_colors_to_a_close_palette() {
+    let image = image(64, 64, |x, y| {
+        let (x, y) = (x as u8, y as u8);
+        Color::rgb(x * 4, y * 4, 255 - x * 2 - y * 2)
+    });
+    let decoded = round_trip(&image);
+    let colors: HashSet<Color> = decoded.pixels().copied().collect();
+    assert!(colors.len() <= 256);
+    for (original, decoded) in image.pixels().zip(decoded.pixels()) {
+        let error = [
+            original.r().abs_diff(decoded.r()),
+            original.g().abs_diff(decoded.g()),
+            original.b().abs_diff(decoded.b()),
+        ];
+        assert!(
+            error.iter().all(|&error| error <= 16),
+            "{original:?} became {decoded:?}"
+        );
+    }
+}
+
+/// An 8-color 128x128 GIF from another encoder, whose LZW codes widen from 4 to 12 bits
+/// and whose table fills up and is cleared partway through.
+#[test]
+fn decodes_an_external_gif_with_code_growth_and_a_clear_code() {
+    let image = BmpFile::from_gif_bytes(include_bytes!("fixtures/noise.gif")).unwrap();
+    assert_eq!((image.width(), image.height()), (128, 128));
+    let palette: Vec<Color> = (0..8u8)
+        .map(|index| Color::rgb(index * 32, 255 - index * 32, index.wrapping_mul(97)))
+        .collect();
+    let expected = noise(128 * 128)
+        .into_iter()
+        .map(|value| palette[value as usize % 8]);
+    assert!(image.pixels().copied().eq(expected));
+}
+
+#[test]
+fn oversized_images_are_rejected() {
+    for (width, height) in [(65536, 1), (1, 65536)] {
+        let image = BmpFile::new(width, height, Color::BLACK);
+        assert!(matches!(
+            image.to_gif_bytes(),
+            Err(ImageError::DimensionsExceedFormat { max: 65535, .. })
+        ));
+    }
+    let widest = BmpFile::new(65535, 1, Color::BLACK);
+    let decoded = BmpFile::from_gif_bytes(&widest.to_gif_bytes().unwrap()).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (65535, 1));
+}
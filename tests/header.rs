@@ -0,0 +1,52 @@
+use image_manip::{BmpFile, Color, ImageError};
+
+#[test]
+fn sizes_account_for_row_padding() {
+    for width in [1, 2, 3, 4, 5] {
+        let height = 3;
+        let image = BmpFile::new(width, height, Color::WHITE);
+        let header = image.header();
+        let padded_row_len = (width * 3).div_ceil(4) * 4;
+        assert_eq!(header.pixel_image_size() as usize, padded_row_len * height);
+        assert_eq!(
+            header.file_size() as usize,
+            header.offset() as usize + padded_row_len * height
+        );
+        assert_eq!(image.to_bytes().len(), header.file_size() as usize);
+    }
+}
+
+#[test]
+fn resolution_is_stored_in_pixels_per_meter() {
+    let mut image = BmpFile::new(1, 1, Color::WHITE);
+    image.set_resolution(300);
+    assert_eq!(image.header().hres(), 11811);
+    assert_eq!(image.header().vres(), 11811);
+    let again = BmpFile::from_bytes(&image.to_bytes()).unwrap();
+    assert_eq!(
+        (again.header().hres(), again.header().vres()),
+        (11811, 11811)
+    );
+}
+
+#[test]
+fn dimensions_ignore_huge_pixel_offsets() {
+    let path = std::env::temp_dir().join(format!("huge-offset-{}.bmp", std::process::id()));
+    let mut bytes = BmpFile::new(7, 5, Color::WHITE).to_bytes()[..54].to_vec();
+    bytes[10..14].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    std::fs::write(&path, &bytes[..18]).unwrap();
+    let truncated = BmpFile::dimensions(&path);
+    std::fs::write(&path, &bytes).unwrap();
+    let complete = BmpFile::dimensions(&path);
+    bytes[14..18].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    std::fs::write(&path, &bytes).unwrap();
+    let huge_header = BmpFile::dimensions(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(truncated, Err(ImageError::TruncatedHeader)));
+    assert_eq!(complete.unwrap(), (7, 5));
+    assert!(matches!(
+        huge_header,
+        Err(ImageError::UnsupportedHeader(0xFFFF_FFF0))
+    ));
+}
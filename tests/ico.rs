@@ -0,0 +1,89 @@
+use image_manip::{BmpFile, Color, Image, ImageError, ReadImage};
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+/// A 5x5 image with a transparent top-left corner and a translucent bottom row.
+fn icon() -> BmpFile {
+    let mut image = BmpFile::new(5, 5, Color::BLACK);
+    image.update(|x, y, _| match (x, y) {
+        (0, 0) | (1, 0) | (0, 1) => Color::TRANSPARENT,
+        (_, 4) => Color::rgba(10, 20, 30, 100),
+        _ => Color::rgb((x * 50) as u8, (y * 60) as u8, 77),
+    });
+    image
+}
+
+/// The color rows of the DIB for one icon, decoded by wrapping them in a BMP file header
+/// and halving the height back to the color rows alone.
+fn decode_dib(dib: &[u8], size: usize) -> BmpFile {
+    let color_bytes = 40 + size * size * 4;
+    let mut bmp = b"BM".to_vec();
+    bmp.extend_from_slice(&(14 + color_bytes as u32).to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&54u32.to_le_bytes());
+    bmp.extend_from_slice(&dib[..color_bytes]);
+    bmp[22..26].copy_from_slice(&(size as i32).to_le_bytes());
+    BmpFile::from_bytes(&bmp).unwrap()
+}
+
+#[test]
+fn writes_a_directory_entry_per_size() {
+    let sizes = [5, 256, 1];
+    let encoded = icon().to_ico_bytes(&sizes).unwrap();
+    assert_eq!(&encoded[..6], &[0, 0, 1, 0, 3, 0]);
+    let mut offset = 6 + 16 * sizes.len();
+    for (i, size) in sizes.into_iter().enumerate() {
+        let entry = &encoded[6 + 16 * i..6 + 16 * (i + 1)];
+        let side = if size == 256 { 0 } else { size as u8 };
+        assert_eq!(&entry[..4], &[side, side, 0, 0]);
+        assert_eq!((u16_at(entry, 4), u16_at(entry, 6)), (1, 32));
+        let mask_stride = size.div_ceil(32) * 4;
+        let length = 40 + size * size * 4 + size * mask_stride;
+        assert_eq!(u32_at(entry, 8) as usize, length);
+        assert_eq!(u32_at(entry, 12) as usize, offset);
+
+        let dib = &encoded[offset..offset + length];
+        assert_eq!(u32_at(dib, 0), 40);
+        assert_eq!(u32_at(dib, 4) as usize, size);
+        assert_eq!(u32_at(dib, 8) as usize, 2 * size);
+        assert_eq!(u16_at(dib, 14), 32);
+        offset += length;
+    }
+    assert_eq!(encoded.len(), offset);
+}
+
+#[test]
+fn embeds_the_colors_and_a_transparency_mask() {
+    let image = icon();
+    let encoded = image.to_ico_bytes(&[5]).unwrap();
+    let dib = &encoded[22..];
+    let decoded = decode_dib(dib, 5);
+    for y in 0..5 {
+        for x in 0..5 {
+            assert_eq!(decoded.get(x, y), image.get(x, y), "({x}, {y})");
+        }
+    }
+    // Rows run bottom up, each padded to four bytes, with set bits over transparent pixels.
+    let mask = &dib[40 + 5 * 5 * 4..];
+    assert_eq!(mask.len(), 5 * 4);
+    let rows: Vec<&[u8]> = mask.chunks(4).collect();
+    assert_eq!(rows[4], &[0b1100_0000, 0, 0, 0]);
+    assert_eq!(rows[3], &[0b1000_0000, 0, 0, 0]);
+    assert!(rows[..3].iter().all(|row| row == &[0; 4]));
+}
+
+#[test]
+fn rejects_sizes_outside_one_to_256() {
+    for sizes in [&[][..], &[0], &[16, 257]] {
+        assert!(matches!(
+            icon().to_ico_bytes(sizes),
+            Err(ImageError::Malformed { format: "ICO", .. })
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "jpeg")]
+
+use image_manip::{BmpFile, Color, ImageError};
+
+/// Decodes `bytes` and checks every channel of every pixel is within `tolerance` of
+/// `expected(x, y)`.
+fn assert_close(
+    bytes: &[u8],
+    size: (usize, usize),
+    tolerance: u8,
+    expected: impl Fn(usize, usize) -> Color,
+) {
+    let image = BmpFile::from_jpeg_bytes(bytes).unwrap();
+    assert_eq!((image.width(), image.height()), size);
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let (found, expected) = (image.get_pixel(x, y).unwrap(), expected(x, y));
+            let errors = [
+                found.r().abs_diff(expected.r()),
+                found.g().abs_diff(expected.g()),
+                found.b().abs_diff(expected.b()),
+            ];
+            assert!(
+                errors.iter().all(|&error| error <= tolerance),
+                "({x}, {y}) is {found:?}, expected {expected:?}"
+            );
+        }
+    }
+}
+
+/// The 32x32 color fixtures hold four flat 16x16 quadrants.
+fn quadrant(x: usize, y: usize) -> Color {
+    [
+        Color::rgb(200, 30, 30),
+        Color::rgb(30, 180, 60),
+        Color::rgb(40, 60, 200),
+        Color::rgb(128, 128, 128),
+    ][y / 16 * 2 + x / 16]
+}
+
+#[test]
+fn decodes_grayscale() {
+    let bytes = include_bytes!("fixtures/gray.jpg");
+    assert_close(bytes, (16, 16), 3, |x, y| {
+        Color::gray((x * 8 + y * 4) as u8)
+    });
+}
+
+#[test]
+fn decodes_subsampled_color() {
+    assert_close(
+        include_bytes!("fixtures/ycbcr420.jpg"),
+        (32, 32),
+        4,
+        quadrant,
+    );
+}
+
+#[test]
+fn decodes_restart_intervals() {
+    let bytes = include_bytes!("fixtures/restart.jpg");
+    assert_close(bytes, (32, 32), 4, quadrant);
+    let plain = BmpFile::from_jpeg_bytes(include_bytes!("fixtures/ycbcr420.jpg")).unwrap();
+    let restarted = BmpFile::from_jpeg_bytes(bytes).unwrap();
+    assert!(restarted.pixels().eq(plain.pixels()));
+}
+
+fn assert_malformed(bytes: &[u8]) {
+    assert!(matches!(
+        BmpFile::from_jpeg_bytes(bytes),
+        Err(ImageError::Malformed { format: "JPEG", .. })
+    ));
+}
+
+#[test]
+fn rejects_progressive_and_truncated_files() {
+    assert_malformed(include_bytes!("fixtures/progressive.jpg"));
+    let bytes = include_bytes!("fixtures/ycbcr420.jpg");
+    for len in [0, 1, 2, 20, 200, bytes.len() / 2, bytes.len() - 2] {
+        assert_malformed(&bytes[..len]);
+    }
+}
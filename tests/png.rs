@@ -0,0 +1,330 @@
+#![cfg(feature = "png")]
+
+use image_manip::{BmpFile, Color, Image, ImageError};
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// A zlib stream holding `data` in stored (uncompressed) deflate blocks.
+fn stored_zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        out.push((index + 1 == blocks.len()) as u8);
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A PNG of the given header fields whose IDAT holds `zlib` and whose other chunks come
+/// from `extra`, placed before it.
+fn png(
+    size: (u32, u32),
+    depth: u8,
+    color_type: u8,
+    interlaced: bool,
+    extra: &[(&[u8; 4], Vec<u8>)],
+    zlib: &[u8],
+) -> Vec<u8> {
+    let mut out = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    let mut ihdr = size.0.to_be_bytes().to_vec();
+    ihdr.extend_from_slice(&size.1.to_be_bytes());
+    ihdr.extend_from_slice(&[depth, color_type, 0, 0, interlaced as u8]);
+    chunk(&mut out, b"IHDR", &ihdr);
+    for (kind, data) in extra {
+        chunk(&mut out, kind, data);
+    }
+    chunk(&mut out, b"IDAT", zlib);
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Packs rows of samples at `depth` bits each, most significant first, behind a `filter`
+/// byte per row.
+fn scanlines(rows: &[Vec<u16>], depth: u8, filter: u8) -> Vec<u8> {
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(filter);
+        if depth == 16 {
+            row.iter()
+                .for_each(|sample| out.extend_from_slice(&sample.to_be_bytes()));
+            continue;
+        }
+        let mut bits = 0u32;
+        let mut count = 0;
+        for &sample in row {
+            bits = bits << depth | sample as u32;
+            count += depth;
+            if count == 8 {
+                out.push(bits as u8);
+                (bits, count) = (0, 0);
+            }
+        }
+        if count > 0 {
+            out.push((bits << (8 - count)) as u8);
+        }
+    }
+    out
+}
+
+fn channels(color_type: u8) -> usize {
+    match color_type {
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => 1,
+    }
+}
+
+/// Sample `channel` of pixel `index`, spread over every value `depth` bits can hold.
+fn sample(index: usize, channel: usize, depth: u8) -> u16 {
+    let value = index * 4099 + channel * 257 + 31;
+    (value % (1 << depth)) as u16
+}
+
+fn to_u8(sample: u16, depth: u8) -> u8 {
+    match depth {
+        16 => (sample >> 8) as u8,
+        depth => (sample as u32 * 255 / ((1 << depth) - 1)) as u8,
+    }
+}
+
+fn palette_entry(index: usize) -> Color {
+    Color::rgb(index as u8, 255 - index as u8, (index * 3) as u8)
+}
+
+/// The color the samples of pixel `index` stand for.
+fn expected(index: usize, depth: u8, color_type: u8) -> Color {
+    let channel = |channel| to_u8(sample(index, channel, depth), depth);
+    match color_type {
+        0 => Color::gray(channel(0)),
+        2 => Color::rgb(channel(0), channel(1), channel(2)),
+        3 => {
+            let entry = sample(index, 0, depth) as usize;
+            palette_entry(entry).with_alpha(if entry < 4 { entry as u8 * 60 } else { 255 })
+        }
+        4 => Color::gray(channel(0)).with_alpha(channel(1)),
+        _ => Color::rgba(channel(0), channel(1), channel(2), channel(3)),
+    }
+}
+
+fn decode_generated(width: usize, height: usize, depth: u8, color_type: u8) -> BmpFile {
+    let rows: Vec<Vec<u16>> = (0..height)
+        .map(|y| {
+            (0..width * channels(color_type))
+                .map(|i| {
+                    sample(
+                        y * width + i / channels(color_type),
+                        i % channels(color_type),
+                        depth,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let mut extra = Vec::new();
+    if color_type == 3 {
+        let entries = 1 << depth;
+        let plte = (0..entries)
+            .flat_map(|index| {
+                let color = palette_entry(index);
+                [color.r(), color.g(), color.b()]
+            })
+            .collect();
+        extra.push((b"PLTE", plte));
+        extra.push((b"tRNS", vec![0, 60, 120, 180]));
+    }
+    let data = stored_zlib(&scanlines(&rows, depth, 0));
+    let bytes = png(
+        (width as u32, height as u32),
+        depth,
+        color_type,
+        false,
+        &extra,
+        &data,
+    );
+    BmpFile::from_png_bytes(&bytes).unwrap()
+}
+
+#[test]
+fn decodes_every_color_type_and_bit_depth() {
+    let cases: [(u8, &[u8]); 5] = [
+        (0, &[1, 2, 4, 8, 16]),
+        (2, &[8, 16]),
+        (3, &[1, 2, 4, 8]),
+        (4, &[8, 16]),
+        (6, &[8, 16]),
+    ];
+    for (color_type, depths) in cases {
+        for &depth in depths {
+            let (width, height) = (7, 3);
+            let image = decode_generated(width, height, depth, color_type);
+            assert_eq!((image.width(), image.height()), (width, height));
+            let expected = (0..width * height).map(|index| expected(index, depth, color_type));
+            assert!(
+                image.pixels().copied().eq(expected),
+                "color type {color_type} at {depth} bits"
+            );
+        }
+    }
+}
+
+#[test]
+fn transparent_keys_hide_matching_samples() {
+    let rows = vec![vec![10, 20, 30, 10, 20, 31]];
+    let data = stored_zlib(&scanlines(&rows, 8, 0));
+    let key = vec![0, 10, 0, 20, 0, 30];
+    let bytes = png((2, 1), 8, 2, false, &[(b"tRNS", key)], &data);
+    let image = BmpFile::from_png_bytes(&bytes).unwrap();
+    let pixels: Vec<Color> = image.pixels().copied().collect();
+    assert_eq!(pixels, [Color::rgba(10, 20, 30, 0), Color::rgb(10, 20, 31)]);
+}
+
+#[test]
+fn decodes_adam7_interlacing() {
+    const ADAM7: [(usize, usize, usize, usize); 7] = [
+        (0, 0, 8, 8),
+        (4, 0, 8, 8),
+        (0, 4, 4, 8),
+        (2, 0, 4, 4),
+        (0, 2, 2, 4),
+        (1, 0, 2, 2),
+        (0, 1, 1, 2),
+    ];
+    let (width, height) = (11, 9);
+    let gray = |x: usize, y: usize| (x * 20 + y * 3) as u16;
+    let mut raw = Vec::new();
+    for (x0, y0, dx, dy) in ADAM7 {
+        let rows: Vec<Vec<u16>> = (y0..height)
+            .step_by(dy)
+            .map(|y| (x0..width).step_by(dx).map(|x| gray(x, y)).collect())
+            .filter(|row: &Vec<u16>| !row.is_empty())
+            .collect();
+        raw.extend(scanlines(&rows, 8, 0));
+    }
+    let bytes = png((11, 9), 8, 0, true, &[], &stored_zlib(&raw));
+    let image = BmpFile::from_png_bytes(&bytes).unwrap();
+    for y in 0..height {
+        for x in 0..width {
+            assert_eq!(image.get_pixel(x, y), Some(Color::gray(gray(x, y) as u8)));
+        }
+    }
+}
+
+#[test]
+fn round_trips_rgb_and_rgba() {
+    let mut opaque = BmpFile::new(40, 30, Color::BLACK);
+    opaque.update(|x, y, _| Color::rgb((x * 6) as u8, (y * 8) as u8, ((x + y) * 3) as u8));
+    opaque.set_resolution(300);
+    let again = BmpFile::from_png_bytes(&opaque.to_png_bytes()).unwrap();
+    assert!(again.pixels().eq(opaque.pixels()));
+    assert_eq!(again.header().hres(), 11811);
+
+    let mut translucent = opaque.clone();
+    translucent.update(|x, y, color| color.with_alpha((x * y) as u8));
+    let again = BmpFile::from_png_bytes(&translucent.to_png_bytes()).unwrap();
+    assert!(again.pixels().eq(translucent.pixels()));
+
+    let flat = BmpFile::new(300, 200, Color::rgb(1, 2, 3));
+    let encoded = flat.to_png_bytes();
+    assert!(encoded.len() < 4000);
+    assert!(BmpFile::from_png_bytes(&encoded)
+        .unwrap()
+        .pixels()
+        .eq(flat.pixels()));
+}
+
+/// A 32x32 RGB PNG whose IDAT zlib wrote elsewhere: the top 16 rows in a dynamic Huffman
+/// block ended by a sync flush's empty stored block, the bottom 16 rows, noise, in stored
+/// blocks.
+#[test]
+fn decodes_dynamic_and_stored_blocks_from_zlib() {
+    let image = BmpFile::from_png_bytes(include_bytes!("fixtures/zlib.png")).unwrap();
+    let mut state = 1u32;
+    let mut noise = || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) as u8
+    };
+    for y in 0..32 {
+        for x in 0..32 {
+            let expected = if y < 16 {
+                Color::rgb((x * 8) as u8, (y * 16) as u8, ((x + y) * 4) as u8)
+            } else {
+                Color::rgb(noise(), noise(), noise())
+            };
+            assert_eq!(image.get_pixel(x, y), Some(expected), "({x}, {y})");
+        }
+    }
+}
+
+fn assert_malformed(bytes: &[u8], reason: &str) {
+    match BmpFile::from_png_bytes(bytes) {
+        Err(ImageError::Malformed {
+            format: "PNG",
+            reason: found,
+        }) => assert_eq!(found, reason),
+        other => panic!("expected {reason:?}, got {other:?}"),
+    }
+}
+
+#[test]
+fn rejects_corrupt_files() {
+    let image = BmpFile::new(4, 4, Color::rgb(9, 8, 7));
+    let mut bad_crc = image.to_png_bytes();
+    let ihdr_crc = 8 + 8 + 13;
+    bad_crc[ihdr_crc] ^= 1;
+    assert_malformed(&bad_crc, "chunk checksum mismatch");
+
+    let encoded = image.to_png_bytes();
+    let idat = encoded.windows(4).position(|kind| kind == b"IDAT").unwrap();
+    assert_malformed(&encoded[..idat + 10], "chunk runs past the end of the file");
+
+    let rows = vec![vec![1, 2, 3, 4, 5, 6]; 2];
+    let raw = scanlines(&rows, 8, 0);
+    let truncated = &stored_zlib(&raw)[..20];
+    assert_malformed(
+        &png((2, 2), 8, 2, false, &[], truncated),
+        "stored block ends early",
+    );
+
+    let short = stored_zlib(&raw[..raw.len() - 3]);
+    assert_malformed(
+        &png((2, 2), 8, 2, false, &[], &short),
+        "pixel data ends early",
+    );
+
+    let bad_filter = stored_zlib(&scanlines(&rows, 8, 5));
+    assert_malformed(
+        &png((2, 2), 8, 2, false, &[], &bad_filter),
+        "unknown row filter",
+    );
+}
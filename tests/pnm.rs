@@ -0,0 +1,77 @@
+use image_manip::{BmpFile, Color, Image, ImageError, PnmFormat};
+
+fn round_trip(image: &BmpFile, format: PnmFormat) -> BmpFile {
+    BmpFile::from_ppm_bytes(&image.to_ppm_bytes(format)).unwrap()
+}
+
+#[test]
+fn round_trips_pixmaps() {
+    let mut image = BmpFile::new(37, 5, Color::BLACK);
+    image.update(|x, y, _| Color::rgb((x * 7) as u8, (y * 50) as u8, (x * y) as u8));
+    for format in [PnmFormat::P3, PnmFormat::P6] {
+        let again = round_trip(&image, format);
+        assert_eq!((again.width(), again.height()), (37, 5));
+        assert!(again.pixels().eq(image.pixels()), "{format:?}");
+    }
+}
+
+#[test]
+fn round_trips_graymaps() {
+    let mut image = BmpFile::new(37, 5, Color::BLACK);
+    image.update(|x, y, _| Color::gray((x * 7 + y) as u8));
+    for format in [PnmFormat::P2, PnmFormat::P5] {
+        let again = round_trip(&image, format);
+        assert!(again.pixels().eq(image.pixels()), "{format:?}");
+    }
+    let color = BmpFile::new(1, 1, Color::rgb(255, 0, 0).with_alpha(10));
+    let gray = round_trip(&color, PnmFormat::P5);
+    assert_eq!(gray.get_pixel(0, 0), Some(Color::gray(76)));
+}
+
+#[test]
+fn round_trips_bitmaps_with_padded_rows() {
+    // Eleven columns leave five bits of padding at the end of each P4 row.
+    let mut image = BmpFile::new(11, 4, Color::WHITE);
+    image.update(|x, y, _| {
+        if (x + y) % 3 == 0 {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        }
+    });
+    for format in [PnmFormat::P1, PnmFormat::P4] {
+        let again = round_trip(&image, format);
+        assert_eq!((again.width(), again.height()), (11, 4));
+        assert!(again.pixels().eq(image.pixels()), "{format:?}");
+    }
+    assert_eq!(
+        image.to_ppm_bytes(PnmFormat::P4).len(),
+        "P4\n11 4\n".len() + 2 * 4
+    );
+}
+
+#[test]
+fn decodes_comments_and_wide_samples() {
+    let plain = b"P2\n# a comment\n2 1 # trailing\n1023\n0 1023\n";
+    let image = BmpFile::from_ppm_bytes(plain).unwrap();
+    assert!(image.pixels().eq([Color::BLACK, Color::WHITE].iter()));
+
+    let mut raw = b"P5 2 1 65535\n".to_vec();
+    raw.extend_from_slice(&[0x80, 0x00, 0xFF, 0xFF]);
+    let image = BmpFile::from_ppm_bytes(&raw).unwrap();
+    assert!(image.pixels().eq([Color::gray(128), Color::WHITE].iter()));
+}
+
+#[test]
+fn rejects_truncated_files() {
+    for bytes in [
+        &b"P6 2 2 255\n\x01\x02\x03"[..],
+        b"P3 1 1 255 1 2",
+        b"P7 1 1",
+    ] {
+        assert!(matches!(
+            BmpFile::from_ppm_bytes(bytes),
+            Err(ImageError::Malformed { .. })
+        ));
+    }
+}
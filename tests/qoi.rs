@@ -0,0 +1,69 @@
+use image_manip::{BmpFile, Color, Image, ImageError};
+
+/// A 6x13 image whose pixels, in order, need every QOI op: a run of the initial black, a
+/// small diff, a luma diff, a full RGB and RGBA pixel, an index back to the diff pixel and
+/// a run too long for one op.
+fn every_op() -> BmpFile {
+    let mut pixels = vec![Color::BLACK; 3];
+    pixels.extend([
+        Color::rgb(1, 255, 0),
+        Color::rgb(11, 9, 10),
+        Color::rgb(200, 50, 100),
+        Color::rgba(200, 50, 100, 128),
+    ]);
+    pixels.extend([Color::rgb(1, 255, 0); 71]);
+    let mut image = BmpFile::new(6, 13, Color::BLACK);
+    image.update(|x, y, _| pixels[y * 6 + x]);
+    image
+}
+
+#[test]
+fn encodes_every_op() {
+    let encoded = every_op().to_qoi_bytes();
+    assert_eq!(&encoded[..4], b"qoif");
+    assert_eq!(&encoded[4..14], &[0, 0, 0, 6, 0, 0, 0, 13, 4, 0]);
+    let expected: &[u8] = &[
+        0xC2, // a run of three
+        0x76, // diff: +1, -1, 0
+        0xAA, 0x88, // luma: every channel +10
+        0xFE, 200, 50, 100, // RGB
+        0xFF, 200, 50, 100, 128,  // RGBA
+        0x33, // index of the diff pixel
+        0xFD, 0xC7, // runs of 62 and 8
+        0, 0, 0, 0, 0, 0, 0, 1,
+    ];
+    assert_eq!(&encoded[14..], expected);
+}
+
+#[test]
+fn round_trips_every_op() {
+    let image = every_op();
+    let again = BmpFile::from_qoi_bytes(&image.to_qoi_bytes()).unwrap();
+    assert_eq!((again.width(), again.height()), (6, 13));
+    assert!(again.pixels().eq(image.pixels()));
+
+    let mut noisy = BmpFile::new(40, 30, Color::BLACK);
+    noisy.update(|x, y, _| {
+        let value = (x * 37 + y * 91) % 256;
+        Color::rgb(value as u8, (value / 3 + y) as u8, (x % 4) as u8)
+    });
+    let encoded = noisy.to_qoi_bytes();
+    assert_eq!(encoded[12], 3);
+    let again = BmpFile::from_qoi_bytes(&encoded).unwrap();
+    assert!(again.pixels().eq(noisy.pixels()));
+}
+
+#[test]
+fn rejects_truncated_files() {
+    let encoded = every_op().to_qoi_bytes();
+    for bytes in [
+        &encoded[..10],
+        &encoded[..20],
+        b"qoix\0\0\0\x01\0\0\0\x01\x03\0",
+    ] {
+        assert!(matches!(
+            BmpFile::from_qoi_bytes(bytes),
+            Err(ImageError::Malformed { .. })
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+//! Decoding and re-encoding every supported layout. Uncompressed files written the way the
+//! encoder writes them must come back byte for byte; run-length encoded ones may be encoded
+//! differently but must decode to the same pixels.
+
+use image_manip::{BmpFile, Color, Image};
+
+/// The fields of a bitmap file, laid out by [`Fixture::bytes`] in the order the encoder
+/// writes them.
+struct Fixture {
+    header_size: u32,
+    width: i32,
+    height: i32,
+    bits_per_pixel: u16,
+    compression: u32,
+    masks: [u32; 4],
+    palette: Vec<[u8; 4]>,
+    /// The pixel array exactly as stored, including row padding.
+    pixels: Vec<u8>,
+    /// An embedded ICC profile, written after the pixel array. V5 headers only.
+    profile: Vec<u8>,
+}
+
+impl Fixture {
+    fn new(width: i32, height: i32, bits_per_pixel: u16, pixels: Vec<u8>) -> Fixture {
+        Fixture {
+            header_size: 40,
+            width,
+            height,
+            bits_per_pixel,
+            compression: 0,
+            masks: [0; 4],
+            palette: Vec::new(),
+            pixels,
+            profile: Vec::new(),
+        }
+    }
+    fn bytes(&self) -> Vec<u8> {
+        if self.header_size == 12 {
+            return self.core_bytes();
+        }
+        let mut info = Vec::new();
+        let mut push = |word: u32| info.extend_from_slice(&word.to_le_bytes());
+        push(self.header_size);
+        push(self.width as u32);
+        push(self.height as u32);
+        push(1 | (self.bits_per_pixel as u32) << 16);
+        push(self.compression);
+        push(self.pixels.len() as u32);
+        push(2835);
+        push(2835);
+        push(self.palette.len() as u32);
+        push(0);
+        let trailing_masks = match (self.header_size, self.compression) {
+            (40, 3) => 3,
+            _ => 0,
+        };
+        let header_end = 14 + self.header_size as usize;
+        let offset = header_end + trailing_masks * 4 + self.palette.len() * 4;
+        if self.header_size >= 108 {
+            self.masks.iter().for_each(|&mask| push(mask));
+            // sRGB, then endpoints and gamma, unused for sRGB.
+            push(u32::from_be_bytes(*b"sRGB"));
+            (0..12).for_each(|_| push(0));
+        }
+        if self.header_size >= 124 {
+            let profile_offset = offset + self.pixels.len() - 14;
+            // Perceptual intent, the profile's position and size, and the reserved field.
+            push(4);
+            push(profile_offset as u32);
+            push(self.profile.len() as u32);
+            push(0);
+        }
+        self.masks[..trailing_masks]
+            .iter()
+            .for_each(|&mask| push(mask));
+        assert_eq!(info.len(), header_end - 14 + trailing_masks * 4);
+        let file_size = offset + self.pixels.len() + self.profile.len();
+        let mut bytes = b"BM".to_vec();
+        bytes.extend_from_slice(&(file_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&info);
+        self.palette
+            .iter()
+            .for_each(|entry| bytes.extend_from_slice(entry));
+        bytes.extend_from_slice(&self.pixels);
+        bytes.extend_from_slice(&self.profile);
+        bytes
+    }
+    /// The file with an OS/2 BITMAPCOREHEADER, whose palette entries leave out the reserved
+    /// byte.
+    fn core_bytes(&self) -> Vec<u8> {
+        let offset = 14 + 12 + self.palette.len() * 3;
+        let mut bytes = b"BM".to_vec();
+        bytes.extend_from_slice(&((offset + self.pixels.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&12u32.to_le_bytes());
+        for field in [
+            self.width as u16,
+            self.height as u16,
+            1,
+            self.bits_per_pixel,
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for entry in &self.palette {
+            bytes.extend_from_slice(&entry[..3]);
+        }
+        bytes.extend_from_slice(&self.pixels);
+        bytes
+    }
+}
+
+/// A palette of `count` distinct colors, stored blue, green, red, reserved.
+fn palette(count: usize) -> Vec<[u8; 4]> {
+    (0..count)
+        .map(|index| [index as u8 * 16, 255 - index as u8 * 16, index as u8 * 7, 0])
+        .collect()
+}
+
+fn pixels(image: &BmpFile) -> Vec<Color> {
+    image.pixels().copied().collect()
+}
+
+/// Decodes `bytes`, encodes the result and checks that it is byte-identical.
+fn assert_identical(bytes: &[u8]) -> BmpFile {
+    let image = BmpFile::from_bytes_strict(bytes).unwrap();
+    assert_eq!(image.to_bytes(), bytes);
+    image
+}
+
+/// Decodes `bytes`, encodes the result and checks that it decodes to the same pixels.
+fn assert_same_pixels(bytes: &[u8]) -> BmpFile {
+    let image = BmpFile::from_bytes_strict(bytes).unwrap();
+    let again = BmpFile::from_bytes_strict(&image.to_bytes()).unwrap();
+    assert_eq!(pixels(&again), pixels(&image));
+    assert_eq!(again.header().compression(), image.header().compression());
+    image
+}
+
+#[test]
+fn true_color_24_bit() {
+    // 3x2, so every row carries three bytes of padding.
+    let rows = [
+        [0, 0, 255, 0, 255, 0, 255, 0, 0],
+        [1, 2, 3, 4, 5, 6, 7, 8, 9],
+    ];
+    let fixture = Fixture::new(
+        3,
+        2,
+        24,
+        rows.map(|row| [&row[..], &[0; 3]].concat()).concat(),
+    );
+    let image = assert_identical(&fixture.bytes());
+    assert_eq!(image.pixels().nth(3), Some(&Color::rgb(255, 0, 0)));
+    assert_eq!(image.pixels().next(), Some(&Color::rgb(3, 2, 1)));
+}
+
+#[test]
+fn paletted_8_bit() {
+    let mut fixture = Fixture::new(3, 2, 8, vec![0, 1, 2, 0, 3, 4, 5, 0]);
+    fixture.palette = palette(6);
+    let image = assert_identical(&fixture.bytes());
+    assert_eq!(image.pixels().nth(4), Some(&Color::rgb(7, 239, 16)));
+}
+
+#[test]
+fn core_header_24_bit() {
+    let mut fixture = Fixture::new(
+        2,
+        2,
+        24,
+        vec![1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0],
+    );
+    fixture.header_size = 12;
+    let image = assert_identical(&fixture.bytes());
+    assert_eq!(image.pixels().next(), Some(&Color::rgb(9, 8, 7)));
+}
+
+#[test]
+fn core_header_8_bit() {
+    let mut fixture = Fixture::new(3, 2, 8, vec![0, 1, 2, 0, 3, 4, 5, 0]);
+    fixture.header_size = 12;
+    // Core color tables always have 2^bits_per_pixel entries.
+    fixture.palette = palette(6);
+    fixture.palette.resize(256, [0; 4]);
+    let image = assert_identical(&fixture.bytes());
+    assert_eq!(image.pixels().nth(4), Some(&Color::rgb(7, 239, 16)));
+
+    // Settings a core header cannot hold widen it to a BITMAPINFOHEADER.
+    let mut widened = image.clone();
+    widened.set_resolution(96);
+    let bytes = widened.to_bytes();
+    assert_eq!(bytes[14..18], 40u32.to_le_bytes());
+    assert_eq!(bytes.len(), 14 + 40 + 256 * 4 + 8);
+    let again = BmpFile::from_bytes_strict(&bytes).unwrap();
+    assert_eq!(pixels(&again), pixels(&image));
+}
+
+#[test]
+fn run_length_encoded_8_bit() {
+    let mut fixture = Fixture::new(
+        4,
+        2,
+        8,
+        vec![
+            // Two pixels of index 1 and two of index 2, then end of line.
+            2, 1, 2, 2, 0, 0, //
+            // An absolute run of four, then end of bitmap.
+            0, 4, 3, 4, 5, 3, 0, 1,
+        ],
+    );
+    fixture.compression = 1;
+    fixture.palette = palette(6);
+    let image = assert_same_pixels(&fixture.bytes());
+    assert_eq!(image.pixels().next(), Some(&Color::rgb(21, 207, 48)));
+    assert_eq!(image.pixels().nth(6), Some(&Color::rgb(14, 223, 32)));
+}
+
+#[test]
+fn run_length_encoded_4_bit() {
+    let mut fixture = Fixture::new(
+        5,
+        2,
+        4,
+        vec![
+            // Five pixels alternating between indices 1 and 2, then end of line.
+            5, 0x12, 0, 0, //
+            // An absolute run of five, padded to a word, then end of bitmap.
+            0, 5, 0x34, 0x53, 0x10, 0, 0, 1,
+        ],
+    );
+    fixture.compression = 2;
+    fixture.palette = palette(16);
+    let image = assert_same_pixels(&fixture.bytes());
+    assert_eq!(image.pixels().next(), Some(&Color::rgb(21, 207, 48)));
+    assert_eq!(image.pixels().nth(7), Some(&Color::rgb(7, 239, 16)));
+}
+
+#[test]
+fn bitfields_565() {
+    let mut fixture = Fixture::new(
+        2,
+        1,
+        16,
+        [0xf800u16, 0x07e0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect(),
+    );
+    fixture.compression = 3;
+    fixture.masks = [0xf800, 0x07e0, 0x001f, 0];
+    let image = assert_identical(&fixture.bytes());
+    assert_eq!(
+        pixels(&image),
+        [Color::rgb(255, 0, 0), Color::rgb(0, 255, 0)]
+    );
+}
+
+#[test]
+fn v4_header() {
+    let mut fixture = Fixture::new(1, 2, 32, vec![10, 20, 30, 128, 40, 50, 60, 255]);
+    fixture.header_size = 108;
+    fixture.compression = 3;
+    fixture.masks = [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0xff00_0000];
+    let image = assert_identical(&fixture.bytes());
+    assert_eq!(image.pixels().next(), Some(&Color::rgba(60, 50, 40, 255)));
+    assert_eq!(image.pixels().nth(1), Some(&Color::rgba(30, 20, 10, 128)));
+}
+
+#[test]
+fn v5_header_with_profile() {
+    let mut fixture = Fixture::new(2, 1, 24, vec![1, 2, 3, 4, 5, 6, 0, 0]);
+    fixture.header_size = 124;
+    fixture.profile = b"an ICC profile would go here".to_vec();
+    let image = assert_identical(&fixture.bytes());
+    let color_space = image.header().color_space().unwrap();
+    assert_eq!(color_space.profile, fixture.profile);
+}
+
+#[test]
+fn bundled_bitmaps() {
+    for path in ["src/bear.bmp", "src/pad1.bmp"] {
+        let bytes = std::fs::read(path).unwrap();
+        assert_identical(&bytes);
+    }
+}
+
+#[test]
+fn translucent_pixels_are_written_as_32_bit() {
+    let mut canvas = BmpFile::new(2, 2, Color::WHITE);
+    let mut paletted = Fixture::new(2, 2, 8, vec![0, 1, 0, 0, 1, 0, 0, 0]);
+    paletted.palette = palette(2);
+    let mut paletted = BmpFile::from_bytes(&paletted.bytes()).unwrap();
+    for image in [&mut canvas, &mut paletted] {
+        image.set(1, 0, Color::rgba(10, 20, 30, 40));
+        let again = BmpFile::from_bytes_strict(&image.to_bytes()).unwrap();
+        assert_eq!(again.header().bits_per_pixel(), 32);
+        assert_eq!(pixels(&again), pixels(image));
+    }
+}
+
+#[test]
+fn bit_depth_can_be_chosen_for_saving() {
+    let mut image = BmpFile::new(3, 2, Color::WHITE);
+    image.set(0, 0, Color::rgb(200, 10, 10));
+    image.set(2, 1, Color::rgb(10, 10, 200));
+    let original = pixels(&image);
+    for bits in [1, 4, 8, 16, 24, 32] {
+        image.set_bit_depth(bits).unwrap();
+        let again = BmpFile::from_bytes_strict(&image.to_bytes()).unwrap();
+        assert_eq!(again.header().bits_per_pixel(), bits);
+        if bits == 1 {
+            assert_eq!(again.header().palette().len(), 2);
+        } else if bits != 16 {
+            assert_eq!(pixels(&again), original);
+        }
+    }
+    assert!(image.set_bit_depth(12).is_err());
+}
+
+#[test]
+fn photos_are_quantized_to_a_median_cut_palette() {
+    let mut bear = BmpFile::open("src/bear.bmp").unwrap();
+    bear.set_bit_depth(8).unwrap();
+    let again = BmpFile::from_bytes_strict(&bear.to_bytes()).unwrap();
+    assert_eq!(again.header().bits_per_pixel(), 8);
+    let palette = again.header().palette();
+    assert!(palette.len() <= 256);
+    assert!(again.pixels().all(|pixel| palette.contains(pixel)));
+    let error: f64 = bear
+        .pixels()
+        .zip(again.pixels())
+        .map(|(a, b)| (a.r() as f64 - b.r() as f64).abs())
+        .sum::<f64>()
+        / bear.pixels().count() as f64;
+    assert!(error < 8.0, "mean red error {}", error);
+}
+
+#[test]
+fn run_length_encoding_can_be_chosen_for_saving() {
+    let mut image = BmpFile::new(40, 3, Color::WHITE);
+    image.set(5, 1, Color::BLACK);
+    for (bits, compression) in [(8, 1), (4, 2)] {
+        image.set_bit_depth(bits).unwrap();
+        image.set_run_length_encoding(true).unwrap();
+        let bytes = image.to_bytes();
+        let again = BmpFile::from_bytes_strict(&bytes).unwrap();
+        assert_eq!(again.header().compression(), compression);
+        assert_eq!(pixels(&again), pixels(&image));
+        image.set_run_length_encoding(false).unwrap();
+        assert!(image.to_bytes().len() > bytes.len());
+    }
+    image.set_bit_depth(24).unwrap();
+    assert!(image.set_run_length_encoding(true).is_err());
+}
@@ -0,0 +1,14 @@
+use image_manip::{BmpFile, Color, Image, LineCap, ReadImage, StrokeStyle};
+
+#[test]
+fn tiny_dash_lengths_are_clamped() {
+    let mut image = BmpFile::new(40, 40, Color::BLACK);
+    let style = StrokeStyle {
+        width: 2.0,
+        cap: LineCap::Round,
+        dash: vec![1e-20, 1e-20],
+        ..StrokeStyle::default()
+    };
+    image.stroke_polyline(&[(0.0, 20.0), (39.0, 20.0)], &style, Color::WHITE);
+    assert_eq!(image.get(20, 20), Some(Color::WHITE));
+}
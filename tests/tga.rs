@@ -0,0 +1,28 @@
+use image_manip::{BmpFile, Color, ImageError};
+
+#[test]
+fn round_trips_with_and_without_run_length_encoding() {
+    let mut image = BmpFile::new(5, 3, Color::rgb(10, 20, 30));
+    image
+        .set_pixel(1, 0, Color::rgba(200, 100, 50, 128))
+        .unwrap();
+    image.set_pixel(4, 2, Color::WHITE).unwrap();
+    for rle in [false, true] {
+        let decoded = BmpFile::from_tga_bytes(&image.to_tga_bytes(rle).unwrap()).unwrap();
+        assert!(decoded.pixels().eq(image.pixels()));
+    }
+}
+
+#[test]
+fn oversized_images_are_rejected() {
+    for (width, height) in [(65536, 1), (1, 65536)] {
+        let image = BmpFile::new(width, height, Color::BLACK);
+        assert!(matches!(
+            image.to_tga_bytes(false),
+            Err(ImageError::DimensionsExceedFormat { max: 65535, .. })
+        ));
+    }
+    let widest = BmpFile::new(65535, 1, Color::BLACK);
+    let decoded = BmpFile::from_tga_bytes(&widest.to_tga_bytes(true).unwrap()).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (65535, 1));
+}
@@ -0,0 +1,73 @@
+use image_manip::{BmpFile, BorderMode, Color, Image, ImageError, ResizeMode};
+
+#[test]
+fn empty_images_transform_without_panicking() {
+    let borders = [
+        BorderMode::Constant(Color::WHITE),
+        BorderMode::Replicate,
+        BorderMode::Wrap,
+    ];
+    for (width, height) in [(0, 0), (0, 3), (3, 0)] {
+        let image = BmpFile::new(width, height, Color::BLACK);
+        for mode in [ResizeMode::Bilinear, ResizeMode::Area] {
+            let resized = image.resize_with(4, 2, mode);
+            assert_eq!((resized.width(), resized.height()), (4, 2));
+        }
+        for border in borders {
+            let rotated = image.rotate(30.0, border, false);
+            assert_eq!((rotated.width(), rotated.height()), (width, height));
+            let warped = image.warp_affine([1.0, 0.0, 0.5, 0.0, 1.0, 0.5], 3, 3, border);
+            assert_eq!(warped.pixels().count(), 9);
+        }
+    }
+}
+
+#[test]
+fn area_resize_averages_a_checkerboard_to_gray() {
+    let mut checkerboard = BmpFile::new(16, 12, Color::BLACK);
+    checkerboard.update(|x, y, _| {
+        if (x + y) % 2 == 0 {
+            Color::WHITE
+        } else {
+            Color::BLACK
+        }
+    });
+    for (width, height) in [(8, 6), (4, 3), (2, 2), (1, 1)] {
+        let resized = checkerboard.resize_with(width, height, ResizeMode::Area);
+        assert_eq!(resized.pixels().count(), width * height);
+        for pixel in resized.pixels() {
+            assert!(
+                [127, 128].contains(&pixel.r()) && pixel.r() == pixel.g() && pixel.g() == pixel.b(),
+                "{:?} at {}x{}",
+                pixel,
+                width,
+                height
+            );
+        }
+    }
+}
+
+#[test]
+fn crops_reaching_past_usize_max_are_out_of_bounds() {
+    let mut image = BmpFile::new(8, 6, Color::BLACK);
+    image.update(|x, y, _| Color::rgb(x as u8, y as u8, 0));
+    for (x, y, width, height) in [
+        (5, 0, usize::MAX, 1),
+        (0, 5, 1, usize::MAX),
+        (usize::MAX, 0, 2, 1),
+        (4, 2, 5, 1),
+    ] {
+        assert!(matches!(
+            image.crop(x, y, width, height),
+            Err(ImageError::OutOfBounds { .. })
+        ));
+    }
+    let cropped = image.crop(5, 2, 3, 4).unwrap();
+    assert_eq!((cropped.width(), cropped.height()), (3, 4));
+    assert_eq!(cropped.get_pixel(2, 3), Some(Color::rgb(7, 5, 0)));
+    let empty = image.crop(usize::MAX, 3, 0, 2).unwrap();
+    assert_eq!(
+        (empty.width(), empty.height(), empty.pixels().count()),
+        (0, 2, 0)
+    );
+}
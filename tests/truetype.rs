@@ -0,0 +1,198 @@
+#![cfg(feature = "truetype")]
+
+use image_manip::{BmpFile, Color, Font, Image, ReadImage};
+use std::time::{Duration, Instant};
+
+/// A simple glyph: one square contour from (0, 0) to (side, side) in font units.
+fn square(side: i16) -> Vec<u8> {
+    let mut glyph = Vec::new();
+    for word in [1, 0, 0, side, side, 3, 0] {
+        glyph.extend_from_slice(&i16::to_be_bytes(word));
+    }
+    glyph.extend_from_slice(&[0x01; 4]);
+    for delta in [0, 0, side, 0, 0, side, 0, -side] {
+        glyph.extend_from_slice(&i16::to_be_bytes(delta));
+    }
+    glyph
+}
+
+/// A composite glyph placing each `(glyph, dx, dy)` component in turn.
+fn composite(components: &[(u16, i16, i16)]) -> Vec<u8> {
+    const ARGS_ARE_WORDS_AND_XY_VALUES: u16 = 0x0003;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    let mut data = Vec::new();
+    for word in [-1i16, 0, 0, 0, 0] {
+        data.extend_from_slice(&word.to_be_bytes());
+    }
+    for (index, &(glyph, dx, dy)) in components.iter().enumerate() {
+        let more = if index + 1 < components.len() {
+            MORE_COMPONENTS
+        } else {
+            0
+        };
+        data.extend_from_slice(&(ARGS_ARE_WORDS_AND_XY_VALUES | more).to_be_bytes());
+        data.extend_from_slice(&glyph.to_be_bytes());
+        data.extend_from_slice(&dx.to_be_bytes());
+        data.extend_from_slice(&dy.to_be_bytes());
+    }
+    data
+}
+
+/// A cmap subtable in `format` 4 or 12 mapping 'A', 'B' and 'C' to glyphs 1 to 3, and in
+/// format 12 also U+1F600 to glyph 1.
+fn cmap(format: u16) -> Vec<u8> {
+    let mut subtable = Vec::new();
+    let mut push = |words: &[u16]| {
+        for word in words {
+            subtable.extend_from_slice(&word.to_be_bytes());
+        }
+    };
+    if format == 4 {
+        let delta = 1u16.wrapping_sub('A' as u16);
+        push(&[4, 32, 0, 4, 4, 1, 0]);
+        push(&[0x43, 0xFFFF, 0, 0x41, 0xFFFF, delta, 1, 0, 0]);
+    } else {
+        push(&[12, 0, 0, 40, 0, 0, 0, 2]);
+        push(&[0, 0x41, 0, 0x43, 0, 1]);
+        push(&[1, 0xF600, 1, 0xF600, 0, 1]);
+    }
+    let encoding: u16 = if format == 4 { 1 } else { 10 };
+    let mut table = Vec::new();
+    for word in [0, 1, 3, encoding, 0, 12] {
+        table.extend_from_slice(&u16::to_be_bytes(word));
+    }
+    table.extend_from_slice(&subtable);
+    table
+}
+
+/// A font with an ascent of 800 units, a descent of 200 and no line gap, whose glyphs all
+/// advance 600 units, with a kerning pair of -100 between two glyph 1s.
+fn font(units_per_em: u16, cmap_format: u16, glyphs: &[Vec<u8>]) -> Vec<u8> {
+    let count = glyphs.len() as u16;
+    let mut head = vec![0; 54];
+    head[18..20].copy_from_slice(&units_per_em.to_be_bytes());
+    head[50..52].copy_from_slice(&1u16.to_be_bytes());
+    let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+    maxp.extend_from_slice(&count.to_be_bytes());
+    let mut hhea = vec![0; 36];
+    hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+    hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+    hhea[34..36].copy_from_slice(&count.to_be_bytes());
+    let hmtx: Vec<u8> = (0..count).flat_map(|_| [0x02, 0x58, 0, 0]).collect();
+    let mut loca = Vec::new();
+    let mut glyf = Vec::new();
+    for glyph in glyphs {
+        loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+        glyf.extend_from_slice(glyph);
+    }
+    loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+    let mut kern = Vec::new();
+    for word in [0i16, 1, 0, 20, 1, 1, 6, 0, 0, 1, 1, -100] {
+        kern.extend_from_slice(&word.to_be_bytes());
+    }
+
+    let tables: [(&[u8; 4], Vec<u8>); 8] = [
+        (b"cmap", cmap(cmap_format)),
+        (b"glyf", glyf),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", hmtx),
+        (b"kern", kern),
+        (b"loca", loca),
+        (b"maxp", maxp),
+    ];
+    let mut data = 0x0001_0000u32.to_be_bytes().to_vec();
+    data.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+    data.extend_from_slice(&[0; 6]);
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, table) in &tables {
+        data.extend_from_slice(&tag[..]);
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(offset as u32).to_be_bytes());
+        data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += table.len();
+    }
+    for (_, table) in tables {
+        data.extend_from_slice(&table);
+    }
+    data
+}
+
+/// Glyph 1 is a 500-unit square, glyph 2 the same square one pixel (at 10 pixels per em)
+/// to the right as a composite, and glyph 3 a composite of fourteen copies of itself.
+fn test_font(cmap_format: u16) -> Font {
+    let glyphs = [
+        Vec::new(),
+        square(500),
+        composite(&[(1, 100, 0)]),
+        composite(&[(3, 0, 0); 14]),
+    ];
+    Font::from_bytes(font(1000, cmap_format, &glyphs)).unwrap()
+}
+
+/// The pixels `text` turns white when drawn at 10 pixels per em onto a black 16x24 image.
+fn render(font: &Font, text: &str) -> Vec<(usize, usize)> {
+    let mut image = BmpFile::new(16, 24, Color::BLACK);
+    image.draw_text_ttf(0, 0, text, font, 10.0, Color::WHITE);
+    (0..24)
+        .flat_map(|y| (0..16).map(move |x| (x, y)))
+        .filter(|&(x, y)| image.get(x, y) != Some(Color::BLACK))
+        .collect()
+}
+
+fn block(columns: std::ops::Range<usize>, rows: std::ops::Range<usize>) -> Vec<(usize, usize)> {
+    rows.flat_map(|y| columns.clone().map(move |x| (x, y)))
+        .collect()
+}
+
+#[test]
+fn measures_advances_kerning_and_lines() {
+    for format in [4, 12] {
+        let font = test_font(format);
+        assert_eq!(font.text_size("A", 10.0), (6, 10));
+        assert_eq!(font.text_size("AA", 10.0), (11, 10));
+        assert_eq!(font.text_size("AB", 10.0), (12, 10));
+        assert_eq!(font.text_size("A\nAAB", 10.0), (17, 20));
+    }
+}
+
+#[test]
+fn renders_simple_and_composite_glyphs() {
+    for format in [4, 12] {
+        let font = test_font(format);
+        assert_eq!(render(&font, "A"), block(0..5, 3..8));
+        assert_eq!(render(&font, "B"), block(1..6, 3..8));
+        let mut kerned = block(0..5, 3..8);
+        kerned.extend(block(5..10, 3..8));
+        kerned.sort_by_key(|&(x, y)| (y, x));
+        assert_eq!(render(&font, "AA"), kerned);
+        assert_eq!(render(&font, "\nA"), block(0..5, 13..18));
+    }
+    let astral = test_font(12);
+    assert_eq!(render(&astral, "\u{1F600}"), block(0..5, 3..8));
+    // Format 4 cannot reach past U+FFFF, so the character falls back to the empty glyph 0.
+    assert_eq!(render(&test_font(4), "\u{1F600}"), []);
+}
+
+#[test]
+fn self_referencing_composites_render_quickly() {
+    let font = test_font(4);
+    let start = Instant::now();
+    assert_eq!(render(&font, "CCCC"), []);
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn huge_glyphs_are_clipped_to_the_image() {
+    let font = Font::from_bytes(font(1, 4, &[Vec::new(), square(30000)])).unwrap();
+    for size in [10.0, 1e12] {
+        let mut image = BmpFile::new(10, 10, Color::BLACK);
+        image.draw_text_ttf(-3, 2, "A", &font, size, Color::WHITE);
+        assert!(image.pixels().all(|&color| color == Color::WHITE));
+    }
+
+    let mut partly = BmpFile::new(10, 10, Color::BLACK);
+    partly.draw_text_ttf(-3, -7999, "A", &font, 10.0, Color::WHITE);
+    assert_eq!(partly.row(0), [Color::WHITE; 10]);
+    assert_eq!(partly.row(1), [Color::BLACK; 10]);
+}
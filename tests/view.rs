@@ -0,0 +1,45 @@
+use image_manip::{BmpFile, Color, Image, ImageError, ReadImage};
+
+#[test]
+fn oversized_views_are_out_of_bounds() {
+    let mut image = BmpFile::new(4, 3, Color::BLACK);
+    for (x, y, width, height) in [(1, 0, usize::MAX, 1), (0, usize::MAX, 1, 2), (4, 0, 1, 1)] {
+        assert!(matches!(
+            image.view(x, y, width, height),
+            Err(ImageError::OutOfBounds {
+                width: 4,
+                height: 3,
+                ..
+            })
+        ));
+        assert!(matches!(
+            image.view_mut(x, y, width, height),
+            Err(ImageError::OutOfBounds {
+                width: 4,
+                height: 3,
+                ..
+            })
+        ));
+    }
+    assert!(image.view(usize::MAX, 0, 0, 5).is_ok());
+}
+
+#[test]
+fn views_support_read_only_operations() {
+    let mut image = BmpFile::new(6, 4, Color::WHITE);
+    image.fill_rect(0, 0, 3, 4, Color::rgb(200, 0, 0));
+    image.fill_rect(3, 0, 3, 4, Color::rgb(0, 0, 200));
+    image.fill_rect(4, 1, 2, 2, Color::BLACK);
+    let view = image.view(3, 0, 3, 4).unwrap();
+    assert_eq!(
+        view.row(1),
+        [Color::rgb(0, 0, 200), Color::BLACK, Color::BLACK]
+    );
+    let mut palette = view.extract_palette(4);
+    palette.sort_by_key(|color| color.b());
+    assert_eq!(palette, [Color::BLACK, Color::rgb(0, 0, 200)]);
+    assert_eq!(view.otsu_level(), view.to_bmp().otsu_level());
+    let region = view.flood_select(1, 1, 0);
+    assert_eq!(region.iter().filter(|&&selected| selected).count(), 4);
+    assert_eq!(view.view(1, 1, 2, 2).unwrap().to_bmp().pixels().count(), 4);
+}